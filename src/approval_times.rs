@@ -0,0 +1,218 @@
+//! How long entitlements take, from the dates of PPTS records:
+//! days from opening to the first hearing, from the hearing to closing, and from opening to closing.
+
+use crate::sfplanningacela::PPTSRecord;
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// The intervals of one record, in days. None if a date is missing or out of order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeline {
+    pub open_to_hearing: Option<i64>,
+    pub hearing_to_close: Option<i64>,
+    pub open_to_close: Option<i64>,
+}
+
+/// Days from `start` to `end`, or None if either is missing or `end` is before `start`
+pub fn days_between(start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>) -> Option<i64> {
+    match (start, end) {
+        (Some(start), Some(end)) if end >= start => Some((end - start).num_days()),
+        _ => None,
+    }
+}
+
+impl Timeline {
+    pub fn new(
+        date_opened: DateTime<Utc>,
+        hearing: Option<DateTime<Utc>>,
+        date_closed: Option<DateTime<Utc>>,
+    ) -> Self {
+        Timeline {
+            open_to_hearing: days_between(Some(date_opened), hearing),
+            hearing_to_close: days_between(hearing, date_closed),
+            open_to_close: days_between(Some(date_opened), date_closed),
+        }
+    }
+
+    /// The first hearing is the Planning Commission hearing, or else the BOS committee hearing
+    pub fn of(record: &PPTSRecord) -> Self {
+        Timeline::new(
+            record.date_opened,
+            record.com_hearing.or(record.com_hearing_date_bos),
+            record.date_closed,
+        )
+    }
+
+    /// The interval named by `Interval`
+    pub fn days(&self, interval: Interval) -> Option<i64> {
+        match interval {
+            Interval::OpenToHearing => self.open_to_hearing,
+            Interval::HearingToClose => self.hearing_to_close,
+            Interval::OpenToClose => self.open_to_close,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interval {
+    OpenToHearing,
+    HearingToClose,
+    OpenToClose,
+}
+
+impl Interval {
+    pub const ALL: [Interval; 3] = [Interval::OpenToHearing, Interval::HearingToClose, Interval::OpenToClose];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Interval::OpenToHearing => "open-to-hearing",
+            Interval::HearingToClose => "hearing-to-close",
+            Interval::OpenToClose => "open-to-close",
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Bucket of proposed units, e.g. `10-49`
+pub fn unit_bucket(units: f64) -> &'static str {
+    if units < 1.0 {
+        "0"
+    } else if units < 2.0 {
+        "1"
+    } else if units < 5.0 {
+        "2-4"
+    } else if units < 10.0 {
+        "5-9"
+    } else if units < 50.0 {
+        "10-49"
+    } else if units < 200.0 {
+        "50-199"
+    } else {
+        "200+"
+    }
+}
+
+/// Distribution of a number of days
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Percentiles {
+    pub count: usize,
+    pub p25: i64,
+    pub median: i64,
+    pub p75: i64,
+    pub p90: i64,
+    pub max: i64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles of `days`, or None if it is empty
+    pub fn new(days: &[i64]) -> Option<Self> {
+        if days.is_empty() {
+            return None;
+        }
+        let mut days = days.to_vec();
+        days.sort();
+        let rank = |p: f64| {
+            let rank = (p * days.len() as f64).ceil() as usize;
+            days[rank.max(1) - 1]
+        };
+        Some(Percentiles {
+            count: days.len(),
+            p25: rank(0.25),
+            median: rank(0.5),
+            p75: rank(0.75),
+            p90: rank(0.9),
+            max: days[days.len() - 1],
+        })
+    }
+}
+
+impl fmt::Display for Percentiles {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "n={} p25={} median={} p75={} p90={} max={}",
+            self.count, self.p25, self.median, self.p75, self.p90, self.max
+        )
+    }
+}
+
+/// Text histogram of `days` in bins of `bin_days`, with bars of at most `width` characters
+pub fn histogram(days: &[i64], bin_days: i64, width: usize) -> String {
+    let bin_days = bin_days.max(1);
+    let num_bins = match days.iter().max() {
+        Some(max) => (max / bin_days + 1) as usize,
+        None => return String::new(),
+    };
+    let mut counts = vec![0usize; num_bins];
+    for &day in days {
+        counts[(day.max(0) / bin_days) as usize] += 1;
+    }
+    let max_count = counts.iter().cloned().max().unwrap_or(0).max(1);
+    let label_width = format!("{}-{}", (num_bins as i64 - 1) * bin_days, num_bins as i64 * bin_days - 1).len();
+    counts
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            let label = format!("{}-{}", i as i64 * bin_days, (i as i64 + 1) * bin_days - 1);
+            let bar_len = (count * width).div_ceil(max_count);
+            format!("{:>label_width$} | {} {}\n", label, "#".repeat(bar_len), count, label_width = label_width)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{histogram, unit_bucket, Interval, Percentiles, Timeline};
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn test_timeline() {
+        let date = |s: &str| s.parse::<DateTime<Utc>>().expect("valid date");
+        let opened = date("2018-01-01T00:00:00Z");
+        let hearing = date("2018-03-02T00:00:00Z");
+        let closed = date("2018-04-01T00:00:00Z");
+        let timeline = Timeline::new(opened, Some(hearing), Some(closed));
+        assert_eq!(Some(60), timeline.days(Interval::OpenToHearing));
+        assert_eq!(Some(30), timeline.days(Interval::HearingToClose));
+        assert_eq!(Some(90), timeline.days(Interval::OpenToClose));
+        let timeline = Timeline::new(closed, Some(hearing), None);
+        assert_eq!(Timeline::default(), timeline);
+    }
+
+    #[test]
+    fn test_percentiles() {
+        assert_eq!(None, Percentiles::new(&[]));
+        let days: Vec<i64> = (1..=10).rev().collect();
+        let percentiles = Percentiles::new(&days).expect("percentiles");
+        assert_eq!(10, percentiles.count);
+        assert_eq!(3, percentiles.p25);
+        assert_eq!(5, percentiles.median);
+        assert_eq!(8, percentiles.p75);
+        assert_eq!(9, percentiles.p90);
+        assert_eq!(10, percentiles.max);
+    }
+
+    #[test]
+    fn test_unit_bucket() {
+        assert_eq!("0", unit_bucket(0.0));
+        assert_eq!("1", unit_bucket(1.0));
+        assert_eq!("2-4", unit_bucket(2.0));
+        assert_eq!("2-4", unit_bucket(4.99));
+        assert_eq!("10-49", unit_bucket(10.0));
+        assert_eq!("200+", unit_bucket(200.0));
+    }
+
+    #[test]
+    fn test_histogram() {
+        assert_eq!("", histogram(&[], 30, 10));
+        assert_eq!(
+            " 0-29 | ########## 2\n30-59 |  0\n60-89 | ##### 1\n",
+            histogram(&[1, 10, 65], 30, 10)
+        );
+    }
+}
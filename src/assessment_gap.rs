@@ -0,0 +1,174 @@
+//! How far assessed values lag market values under Prop 13.
+//!
+//! A property is reassessed at market value when it is sold, and after that its assessed value
+//! grows by at most 2% a year. If market values grew by `market_growth` a year since the last sale,
+//! the market value is the assessed value times ((1 + market_growth) / 1.02) ^ years.
+//! Reassessments of new construction are not accounted for.
+
+use crate::approval_times::Percentiles;
+use crate::sfassessormap::{ParcelAssessment, TaxProperty};
+use chrono::Datelike;
+
+/// Largest yearly increase of an assessed value under Prop 13
+pub const ASSESSMENT_GROWTH_CAP: f64 = 0.02;
+
+/// Estimated market value of one property
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GapEstimate {
+    pub assessed_value: f64,
+    pub market_value: f64,
+    /// roll years since the last sale
+    pub years: i64,
+}
+
+impl GapEstimate {
+    pub fn new(assessed_value: f64, years: i64, market_growth: f64) -> Self {
+        let factor = ((1.0 + market_growth) / (1.0 + ASSESSMENT_GROWTH_CAP)).powi(years.max(0) as i32);
+        GapEstimate {
+            assessed_value,
+            market_value: assessed_value * factor,
+            years: years.max(0),
+        }
+    }
+
+    /// None if the property has no roll year or last sale date
+    pub fn of(property: &TaxProperty, market_growth: f64) -> Option<Self> {
+        let roll_year = property.rollyear? as i64;
+        let sale_year = property.last_sale_date()?.year() as i64;
+        Some(GapEstimate::new(property.assessed_value(), roll_year - sale_year, market_growth))
+    }
+}
+
+/// The estimates of the properties on a parcel.
+/// Values only include the properties that have an estimate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParcelGap {
+    pub properties: usize,
+    /// properties without a last sale date
+    pub unknown: usize,
+    pub assessed_value: f64,
+    pub market_value: f64,
+    /// years since the last sale of each property
+    pub years: Vec<i64>,
+}
+
+impl ParcelGap {
+    pub fn new(assessment: &ParcelAssessment, market_growth: f64) -> Self {
+        ParcelGap::from_estimates(
+            assessment
+                .properties
+                .iter()
+                .map(|property| GapEstimate::of(property, market_growth)),
+        )
+    }
+
+    pub fn from_estimates<I: IntoIterator<Item = Option<GapEstimate>>>(estimates: I) -> Self {
+        let mut parcel_gap = ParcelGap::default();
+        for estimate in estimates {
+            parcel_gap.properties += 1;
+            match estimate {
+                Some(estimate) => {
+                    parcel_gap.assessed_value += estimate.assessed_value;
+                    parcel_gap.market_value += estimate.market_value;
+                    parcel_gap.years.push(estimate.years);
+                }
+                None => parcel_gap.unknown += 1,
+            }
+        }
+        parcel_gap
+    }
+
+    pub fn gap(&self) -> f64 {
+        self.market_value - self.assessed_value
+    }
+
+    /// Assessed value as a fraction of market value
+    pub fn assessment_ratio(&self) -> Option<f64> {
+        assessment_ratio(self.assessed_value, self.market_value)
+    }
+
+    /// Years since the least recent sale
+    pub fn max_years(&self) -> Option<i64> {
+        self.years.iter().cloned().max()
+    }
+}
+
+fn assessment_ratio(assessed_value: f64, market_value: f64) -> Option<f64> {
+    if market_value > 0.0 {
+        Some(assessed_value / market_value)
+    } else {
+        None
+    }
+}
+
+/// Totals of the parcels in a group, e.g. a neighborhood
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GapSummary {
+    pub parcels: usize,
+    pub properties: usize,
+    pub unknown: usize,
+    pub assessed_value: f64,
+    pub market_value: f64,
+    pub years: Vec<i64>,
+}
+
+impl GapSummary {
+    pub fn add(&mut self, parcel_gap: &ParcelGap) {
+        self.parcels += 1;
+        self.properties += parcel_gap.properties;
+        self.unknown += parcel_gap.unknown;
+        self.assessed_value += parcel_gap.assessed_value;
+        self.market_value += parcel_gap.market_value;
+        self.years.extend(&parcel_gap.years);
+    }
+
+    pub fn gap(&self) -> f64 {
+        self.market_value - self.assessed_value
+    }
+
+    pub fn assessment_ratio(&self) -> Option<f64> {
+        assessment_ratio(self.assessed_value, self.market_value)
+    }
+
+    /// Median years since the last sale of the properties
+    pub fn median_years(&self) -> Option<i64> {
+        Percentiles::new(&self.years).map(|percentiles| percentiles.median)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GapEstimate, GapSummary, ParcelGap};
+
+    #[test]
+    fn test_gap_estimate() {
+        // market growth at the cap: no gap
+        let estimate = GapEstimate::new(100.0, 10, 0.02);
+        assert!((estimate.market_value - 100.0).abs() < 1e-9);
+        let estimate = GapEstimate::new(100.0, 2, 0.0710);
+        assert!((estimate.market_value - 110.25).abs() < 1e-9);
+        assert_eq!(0, GapEstimate::new(100.0, -1, 0.05).years);
+    }
+
+    #[test]
+    fn test_summary() {
+        let parcel_gap = ParcelGap::from_estimates(vec![
+            Some(GapEstimate::new(100.0, 2, 0.0710)),
+            Some(GapEstimate::new(300.0, 0, 0.0710)),
+            None,
+        ]);
+        assert_eq!(3, parcel_gap.properties);
+        assert_eq!(1, parcel_gap.unknown);
+        assert_eq!(400.0, parcel_gap.assessed_value);
+        assert!((parcel_gap.gap() - 10.25).abs() < 1e-9);
+        assert_eq!(Some(2), parcel_gap.max_years());
+
+        let mut summary = GapSummary::default();
+        summary.add(&parcel_gap);
+        summary.add(&ParcelGap::from_estimates(vec![Some(GapEstimate::new(100.0, 7, 0.0710))]));
+        assert_eq!(2, summary.parcels);
+        assert_eq!(4, summary.properties);
+        assert_eq!(Some(2), summary.median_years());
+        assert_eq!(None, GapSummary::default().assessment_ratio());
+    }
+}
@@ -0,0 +1,13 @@
+//! Same as `parcelscan approvaltimes`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::approvaltimes;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = approvaltimes::app().args(&global_args()).get_matches();
+    approvaltimes::run(&matches)
+}
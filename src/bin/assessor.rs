@@ -0,0 +1,13 @@
+//! Same as `parcelscan assessor`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::assessor;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = assessor::app().args(&global_args()).get_matches();
+    assessor::run(&matches)
+}
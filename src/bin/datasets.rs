@@ -0,0 +1,13 @@
+//! Same as `parcelscan datasets`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::datasets;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = datasets::app().args(&global_args()).get_matches();
+    datasets::run(&matches)
+}
@@ -0,0 +1,13 @@
+//! Same as `parcelscan nonconforming`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::nonconforming;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = nonconforming::app().args(&global_args()).get_matches();
+    nonconforming::run(&matches)
+}
@@ -0,0 +1,13 @@
+//! Same as `parcelscan projects`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::projects;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = projects::app().args(&global_args()).get_matches();
+    projects::run(&matches)
+}
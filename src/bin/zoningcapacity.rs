@@ -0,0 +1,13 @@
+//! Same as `parcelscan zoningcapacity`
+extern crate env_logger;
+extern crate parcelscan;
+
+use parcelscan::cli::global_args;
+use parcelscan::commands::zoningcapacity;
+use std::error::Error;
+
+fn main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+    let matches = zoningcapacity::app().args(&global_args()).get_matches();
+    zoningcapacity::run(&matches)
+}
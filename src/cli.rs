@@ -0,0 +1,129 @@
+//! Command line options shared by the `parcelscan` subcommands and the per-analysis binaries.
+//!
+//! Dataset paths, row error handling and caching are global options, so they can be given
+//! before or after the subcommand (e.g. `parcelscan --land-use LandUse2016.csv nonconforming report`).
+//! Datasets that aren't given on the command line come from the dataset manifest.
+
+use crate::csv_loader::row_error_args;
+use crate::dataset_cache::cache_args;
+use crate::manifest::{Dataset, DatasetManifest, ManifestError, DEFAULT_MANIFEST};
+use clap::{Arg, ArgMatches};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const MANIFEST_ARG: &str = "manifest";
+
+#[derive(Error, Debug)]
+pub enum DatasetPathError {
+    #[error("missing --{} file (or {} in the manifest)", .0.arg_name(), .0.as_str())]
+    Missing(Dataset),
+    #[error(transparent)]
+    Manifest(#[from] ManifestError),
+}
+
+/// `--manifest` and an argument for each dataset, which overrides the manifest
+pub fn dataset_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(MANIFEST_ARG)
+            .long(MANIFEST_ARG)
+            .help("Dataset manifest that gives the path of each dataset (default: parcelscan.toml if it exists)")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::LandUse.arg_name())
+            .long(Dataset::LandUse.arg_name())
+            // the original parcelscan binary called it --input
+            .alias("input")
+            .help("parcels csv file LandUse2016.csv https://data.sfgov.org/Housing-and-Buildings/Land-Use/us3s-fp9q")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::ZoningDistricts.arg_name())
+            .long(Dataset::ZoningDistricts.arg_name())
+            .help("zoning map file Zoning_Map_-_Zoning_Districts_data.csv https://data.sfgov.org/Geographic-Locations-and-Boundaries/Zoning-Map-Zoning-Districts/xvjh-uu28")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::HeightBulk.arg_name())
+            .long(Dataset::HeightBulk.arg_name())
+            .help("height and bulk districts map file (Zoning Map - Height and Bulk Districts on data.sfgov.org)")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::Footprints.arg_name())
+            .long(Dataset::Footprints.arg_name())
+            .help("Building_Footprints.csv file https://data.sfgov.org/Geographic-Locations-and-Boundaries/Building-Footprints/ynuv-fyni")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::Ppts.arg_name())
+            .long(Dataset::Ppts.arg_name())
+            .help("Planning CSV file named PPTS_Records_data.csv from https://data.sfgov.org/Housing-and-Buildings/PPTS-Records/7yuw-98m5")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::BuildingPermits.arg_name())
+            .long(Dataset::BuildingPermits.arg_name())
+            .help("DBI building permits csv file Building_Permits.csv https://data.sfgov.org/Housing-and-Buildings/Building-Permits/i86a-77a5")
+            .takes_value(true)
+            .global(true),
+        Arg::with_name(Dataset::AssessorRoll.arg_name())
+            .long(Dataset::AssessorRoll.arg_name())
+            .help("Assessor secured roll file e.g. 2019.1.15__SF_ASR_Secured_Roll_Data_2017-2018.xlsx https://sfassessor.org/news-information/property-data-0")
+            .takes_value(true)
+            .global(true),
+    ]
+}
+
+/// Dataset, row error and cache arguments, for the top level of every binary
+pub fn global_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    let mut args = dataset_args();
+    args.extend(row_error_args().into_iter().map(|arg| arg.global(true)));
+    args.extend(cache_args().into_iter().map(|arg| arg.global(true)));
+    args
+}
+
+/// The `--manifest`, or `parcelscan.toml` if it exists
+pub fn dataset_manifest(matches: &ArgMatches) -> Result<Option<DatasetManifest>, ManifestError> {
+    match matches.value_of_os(MANIFEST_ARG) {
+        Some(path) => DatasetManifest::load(Path::new(path)).map(Some),
+        None if Path::new(DEFAULT_MANIFEST).exists() => DatasetManifest::load(Path::new(DEFAULT_MANIFEST)).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The path of `dataset`, from its command line argument or else from the manifest
+pub fn dataset_path(matches: &ArgMatches, dataset: Dataset) -> Result<PathBuf, DatasetPathError> {
+    if let Some(path) = matches.value_of_os(dataset.arg_name()) {
+        return Ok(PathBuf::from(path));
+    }
+    let manifest = dataset_manifest(matches)?;
+    let entry = manifest
+        .as_ref()
+        .and_then(|manifest| manifest.get(dataset))
+        .ok_or(DatasetPathError::Missing(dataset))?;
+    if entry.schema_version != dataset.schema_version() {
+        warn!(
+            "{} has schema version {} in the manifest, but version {} is supported",
+            dataset,
+            entry.schema_version,
+            dataset.schema_version()
+        );
+    }
+    Ok(entry.path.clone())
+}
+
+/// Create the output file given for the argument `name`, if any
+pub fn output_file(matches: &ArgMatches, name: &str) -> io::Result<Option<File>> {
+    match matches.value_of_os(name) {
+        Some(path) => {
+            info!("Opening output file {}", path.to_string_lossy());
+            Ok(Some(File::create(path)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// `--out`-style output argument
+pub fn output_arg<'a, 'b>(name: &'a str, help: &'a str) -> Arg<'a, 'b> {
+    Arg::with_name(name)
+        .long(name)
+        .help(help)
+        .takes_value(true)
+}
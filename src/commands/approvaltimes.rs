@@ -0,0 +1,279 @@
+//! How long entitlements take, from the dates of the PPTS records,
+//! grouped by record type, zoning district, number of units or year
+
+use chrono::Datelike;
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use crate::approval_times::{histogram, unit_bucket, Interval, Percentiles, Timeline};
+use crate::cli::{dataset_path, output_arg, output_file};
+use crate::commands::projects::load_project_graph;
+use crate::csv_loader::{row_error_policy, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+use crate::manifest::Dataset;
+use crate::polygon_wrapper::parse_wkt_to_multipolygon;
+use crate::sfplanningacela::{PPTSRecord, Project, RecordTypeCategory};
+use crate::sfzoningdistricts::{get_zoning, ZoningDistrict};
+use crate::spatial_layer::SpatialLayer;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+pub const NAME: &str = "approvaltimes";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupBy {
+    RecordType,
+    Zoning,
+    Units,
+    Year,
+}
+
+impl GroupBy {
+    const VALUES: [&'static str; 4] = ["record-type", "zoning", "units", "year"];
+
+    fn from_str(s: &str) -> GroupBy {
+        match s {
+            "record-type" => GroupBy::RecordType,
+            "zoning" => GroupBy::Zoning,
+            "units" => GroupBy::Units,
+            "year" => GroupBy::Year,
+            _ => panic!("clap should only allow possible values"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct RecordRow {
+    record_id: String,
+    record_type_category: String,
+    group: String,
+    project: String,
+    units: f64,
+    date_opened: String,
+    hearing: Option<String>,
+    date_closed: Option<String>,
+    open_to_hearing: Option<i64>,
+    hearing_to_close: Option<i64>,
+    open_to_close: Option<i64>,
+    address: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SummaryRow {
+    group: String,
+    interval: &'static str,
+    count: usize,
+    p25: i64,
+    median: i64,
+    p75: i64,
+    p90: i64,
+    max: i64,
+}
+
+struct Options<'a> {
+    group_by: GroupBy,
+    record_type: Option<RecordTypeCategory>,
+    min_units: f64,
+    slowest: usize,
+    bin_days: i64,
+    zoning_districts: Option<&'a Path>,
+}
+
+fn format_date(date: &chrono::DateTime<chrono::Utc>) -> String {
+    date.format("%Y-%m-%d").to_string()
+}
+
+fn approval_times(
+    planning: &Path,
+    options: &Options,
+    mut out_records: Option<csv::Writer<File>>,
+    mut out_summary: Option<csv::Writer<File>>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error>> {
+    let graph = load_project_graph(planning, row_error_policy)?;
+    let zoning_districts = match options.zoning_districts {
+        Some(zoning_districts_path) => {
+            info!("Loading zoning districts");
+            Some(SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?)
+        }
+        None => None,
+    };
+    let projects: HashMap<String, Project> = graph
+        .projects()
+        .into_iter()
+        .map(|project| (project.record_id.clone(), project))
+        .collect();
+    let zoning = |record: &PPTSRecord| -> String {
+        let zoning_districts = zoning_districts.as_ref().expect("zoning districts are loaded to group by zoning");
+        parse_wkt_to_multipolygon(&record.the_geom)
+            .ok()
+            .and_then(|shape| get_zoning(zoning_districts, &shape).map(|district| district.zoning_sim.to_string()))
+            .unwrap_or_else(|| "unknown".to_string())
+    };
+
+    let mut groups: BTreeMap<String, Vec<Timeline>> = BTreeMap::new();
+    let mut all_open_to_close = vec![];
+    for record in graph.records() {
+        if let Some(record_type) = &options.record_type {
+            if record.record_type_category != *record_type {
+                continue;
+            }
+        }
+        let project = &projects[graph.root(&record.record_id)];
+        if project.units_prop < options.min_units {
+            continue;
+        }
+        let timeline = Timeline::of(record);
+        let group = match options.group_by {
+            GroupBy::RecordType => record.record_type_category.to_string(),
+            GroupBy::Zoning => zoning(record),
+            GroupBy::Units => unit_bucket(project.units_prop).to_string(),
+            GroupBy::Year => record.date_opened.year().to_string(),
+        };
+        if let Some(days) = timeline.open_to_close {
+            all_open_to_close.push(days);
+        }
+        if let Some(out_records) = out_records.as_mut() {
+            out_records.serialize(RecordRow {
+                record_id: record.record_id.clone(),
+                record_type_category: record.record_type_category.to_string(),
+                group: group.clone(),
+                project: project.record_id.clone(),
+                units: project.units_prop,
+                date_opened: format_date(&record.date_opened),
+                hearing: record.com_hearing.or(record.com_hearing_date_bos).as_ref().map(format_date),
+                date_closed: record.date_closed.as_ref().map(format_date),
+                open_to_hearing: timeline.open_to_hearing,
+                hearing_to_close: timeline.hearing_to_close,
+                open_to_close: timeline.open_to_close,
+                address: record.address.clone(),
+            })?;
+        }
+        groups.entry(group).or_default().push(timeline);
+    }
+
+    for (group, timelines) in &groups {
+        println!("{} ({} records)", group, timelines.len());
+        for &interval in Interval::ALL.iter() {
+            let days: Vec<i64> = timelines.iter().filter_map(|timeline| timeline.days(interval)).collect();
+            if let Some(percentiles) = Percentiles::new(&days) {
+                println!("    {:<16} {}", interval.as_str(), percentiles);
+                if let Some(out_summary) = out_summary.as_mut() {
+                    out_summary.serialize(SummaryRow {
+                        group: group.clone(),
+                        interval: interval.as_str(),
+                        count: percentiles.count,
+                        p25: percentiles.p25,
+                        median: percentiles.median,
+                        p75: percentiles.p75,
+                        p90: percentiles.p90,
+                        max: percentiles.max,
+                    })?;
+                }
+            }
+        }
+    }
+
+    println!();
+    println!("Days from open to close ({} records):", all_open_to_close.len());
+    print!("{}", histogram(&all_open_to_close, options.bin_days, 60));
+
+    let mut slowest: Vec<(i64, &Project)> = projects
+        .values()
+        .filter(|project| project.units_prop >= options.min_units)
+        .filter_map(|project| {
+            project
+                .date_approved
+                .map(|date_approved| ((date_approved - project.date_opened).num_days(), project))
+        })
+        .collect();
+    slowest.sort_by(|(a_days, a), (b_days, b)| b_days.cmp(a_days).then_with(|| a.record_id.cmp(&b.record_id)));
+    println!();
+    println!("Slowest approved projects:");
+    for (days, project) in slowest.into_iter().take(options.slowest) {
+        println!(
+            "{days} days: {record_id} opened: {date_opened}, approved: {date_approved}, units: {units}, address: {address}",
+            days = days,
+            record_id = project.record_id,
+            date_opened = format_date(&project.date_opened),
+            date_approved = project.date_approved.as_ref().map(format_date).unwrap_or_default(),
+            units = project.units_prop,
+            address = project.address,
+        );
+    }
+    Ok(())
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("How long entitlements take, from the dates of the PPTS records")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name("times")
+            .about("Percentiles of the days from open to hearing to close, a histogram, and the slowest projects")
+            .arg(Arg::with_name("group-by")
+                .long("group-by")
+                .help("Report percentiles for each record type, zoning district (needs --zoning-districts), unit count bucket or year opened")
+                .takes_value(true)
+                .possible_values(&GroupBy::VALUES)
+                .default_value("record-type")
+            )
+            .arg(Arg::with_name("record-type")
+                .long("record-type")
+                .help("Only records of this type, e.g. PRJ or CUA")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("min-units")
+                .long("min-units")
+                .help("Only records of projects that propose at least this many units")
+                .takes_value(true)
+                .default_value("0")
+            )
+            .arg(Arg::with_name("slowest")
+                .long("slowest")
+                .help("Number of slowest approved projects to list")
+                .takes_value(true)
+                .default_value("20")
+            )
+            .arg(Arg::with_name("bin-days")
+                .long("bin-days")
+                .help("Width of the histogram bins in days")
+                .takes_value(true)
+                .default_value("90")
+            )
+            .arg(output_arg("out-records", "csv file output of the intervals of each record"))
+            .arg(output_arg("out-summary", "csv file output of the percentiles of each group"))
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("times") {
+        let group_by = GroupBy::from_str(matches.value_of("group-by").expect("default value should exist"));
+        let zoning_districts = if group_by == GroupBy::Zoning {
+            Some(dataset_path(matches, Dataset::ZoningDistricts)?)
+        } else {
+            None
+        };
+        let options = Options {
+            group_by,
+            record_type: matches.value_of("record-type").map(RecordTypeCategory::from),
+            min_units: value_t!(matches.value_of("min-units"), f64).expect("Expected value for min-units"),
+            slowest: value_t!(matches.value_of("slowest"), usize).expect("Expected value for slowest"),
+            bin_days: value_t!(matches.value_of("bin-days"), i64).expect("Expected value for bin-days"),
+            zoning_districts: zoning_districts.as_deref(),
+        };
+        approval_times(
+            &dataset_path(matches, Dataset::Ppts)?,
+            &options,
+            output_file(matches, "out-records")?.map(csv::Writer::from_writer),
+            output_file(matches, "out-summary")?.map(csv::Writer::from_writer),
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Apartments that were approved since 2017-01-01, from the PPTS records
+
+use chrono::Datelike;
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use crate::cli::{dataset_path, output_arg, output_file};
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowErrorHandler, RowErrorPolicy};
+use crate::sfplanningacela::{Lifecycle, PPTSRecord, RecordTypeCategory};
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct OutputRow {
+    description: String,
+    address: String,
+    units: i32,
+    net_units: i32,
+    date_opened: String,
+    date_closed: Option<String>,
+    record_status: String,
+    related_building_permit: String,
+}
+
+fn expansions(
+    mut planning_rdr: csv::Reader<File>,
+    mut output_write: Option<csv::Writer<File>>,
+    row_error_policy: &RowErrorPolicy,
+) -> Result<(), Box<dyn Error>> {
+    info!("Scanning PPTS records of applications");
+    let planning_errors = RowErrorHandler::new("planning", row_error_policy);
+    for result in planning_errors.records::<_, PPTSRecord>(&mut planning_rdr) {
+        // The iterator yields Result<Row, RowError>, so we check the
+        // error here.
+        let record = result?.record;
+        if record.record_status.lifecycle() == Lifecycle::Withdrawn {
+            continue;
+        }
+        if ! (record.record_type_group == "Planning" &&
+            record.record_type_type == "Project" &&
+            record.record_type_subtype == "Project" &&
+            record.record_type_category == RecordTypeCategory::Prj
+            ) {
+            continue;
+        }
+        if record.related_building_permit == "" {
+            continue;
+        }
+        if ! (record.date_opened.year() >= 2017) {
+            continue;
+        }
+        let units = (record.prj_feature_market_rate_prop.unwrap_or(0f64) +
+            record.prj_feature_affordable_prop.unwrap_or(0f64)) as i32;
+        if ! (units >= 10) {
+            continue;
+        }
+        let net_units = (record.prj_feature_affordable_net.unwrap_or(0f64) +
+            record.prj_feature_market_rate_net.unwrap_or(0f64)) as i32;
+        if net_units < 5 {
+            continue;
+        }
+        let o = OutputRow {
+            description: record.description,
+            address: record.address,
+            units,
+            net_units,
+            date_opened: record.date_opened.date().format("%Y-%m-%d").to_string(),
+            date_closed: record.date_closed.map(|d| d.date().format("%Y-%m-%d").to_string()),
+            record_status: record.record_status.to_string(),
+            related_building_permit: record.related_building_permit,
+        };
+        print_row(&o);
+        if let Some(output_write) = output_write.as_mut() {
+            output_write.serialize(o)?;
+        }
+    }
+    planning_errors.log_summary();
+    Ok(())
+}
+fn print_row(o: &OutputRow) {
+    println!(
+        "opened: {date_opened}, closed: {date_closed}, address: {address}, units: {units}, net units: {net_units}, status: {record_status}, building: {related_building_permit}, description: {description}",
+        date_opened = o.date_opened,
+        date_closed = o.date_closed.as_ref().map(|x| x.as_str()).unwrap_or("none"),
+        address = o.address,
+        units = o.units,
+        net_units = o.net_units,
+        record_status = o.record_status,
+        related_building_permit = o.related_building_permit,
+        description = o.description,
+    );
+}
+
+pub const NAME: &str = "approvedapartments";
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Shows apartments that were approved")
+        .author("Yonathan.")
+        .after_help("Print stats on apartments that were approved since 2017-01-01. Note: everything is output using the logger, so you should set RUST_LOG=parcelscan=info to see the output.")
+        .subcommand(SubCommand::with_name("apartments")
+            .arg(output_arg("out-projects", "csv file output"))
+            .about("Show information about expansions")
+        )
+        .subcommand(SubCommand::with_name("reprint")
+            .arg(Arg::with_name("projects")
+                .long("projects")
+                .help("csv file input, which was output by apartments")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("apartments") {
+        let planning = dataset_path(matches, Dataset::Ppts)?;
+        info!(
+            "Opening acela: {acela}",
+            acela = planning.display()
+        );
+        let planning_file = File::open(planning)?;
+        let planning_rdr = csv::Reader::from_reader(planning_file);
+        let out_projects_writer_opt = output_file(matches, "out-projects")?.map(csv::Writer::from_writer);
+        expansions(planning_rdr, out_projects_writer_opt, &row_error_policy(matches))?;
+    } else if let Some(matches) = matches.subcommand_matches("reprint") {
+        let projects_path = matches.value_of_os("projects").expect("required arg should exist");
+        let projects_file: Box<dyn Read> = if projects_path == "-" {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(projects_path)?)
+        };
+        let mut projects_rdr = csv::Reader::from_reader(projects_file);
+        for result in projects_rdr.deserialize::<OutputRow>() {
+            let o = result?;
+            print_row(&o);
+        }
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
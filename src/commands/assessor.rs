@@ -0,0 +1,553 @@
+//! The Assessor's secured roll joined to the LandUse parcels:
+//! assessed value of each parcel next to its units and lot area,
+//! the Prop 13 gap between assessed and market values,
+//! and the changes to properties between roll years
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use crate::assessment_gap::{GapSummary, ParcelGap};
+use crate::cli::{dataset_path, output_arg, output_file, DatasetPathError};
+use crate::csv_loader::{row_error_policy, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+use crate::manifest::Dataset;
+use crate::parcel_join::ParcelKeyIndex;
+use crate::roll_diff::{block_changes, diff_roll_years, ApnDiff, Change};
+use crate::geo_util::multipolygon_to_geojson;
+use crate::sfassessormap::{join_land_use, latest_roll_year, load_assessor_roll, ParcelAssessment, TaxProperty};
+use crate::sflanduse::LandUseRecord;
+use crate::sfzoningdistricts::{get_zoning, ZoningDistrict};
+use crate::spatial_layer::SpatialLayer;
+use geojson::{Feature, FeatureCollection};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+pub const NAME: &str = "assessor";
+
+#[derive(Clone, Debug, Serialize)]
+struct ParcelRow {
+    blklot: String,
+    address: String,
+    landuse: String,
+    resunits: usize,
+    lot_area: f64,
+    roll_year: u32,
+    properties: usize,
+    /// distinct class codes of the properties, e.g. `Z` for a building of condominiums
+    class_codes: String,
+    assessor_units: f64,
+    land_value: f64,
+    improvement_value: f64,
+    assessed_value: f64,
+    assessed_value_per_unit: Option<f64>,
+    assessed_value_per_sqft_lot: Option<f64>,
+}
+
+impl ParcelRow {
+    fn new(assessment: &ParcelAssessment, roll_year: u32) -> Self {
+        let parcel = &assessment.parcel.value;
+        let mut class_codes: Vec<&str> = assessment
+            .properties
+            .iter()
+            .map(|property| property.rp1clacde.as_str())
+            .collect();
+        class_codes.sort();
+        class_codes.dedup();
+        let assessed_value = assessment.assessed_value();
+        ParcelRow {
+            blklot: parcel.blklot.clone(),
+            address: parcel.address(),
+            landuse: parcel.landuse.to_string(),
+            resunits: parcel.resunits,
+            lot_area: parcel.shape_area,
+            roll_year,
+            properties: assessment.properties.len(),
+            class_codes: class_codes.join(","),
+            assessor_units: assessment.units(),
+            land_value: assessment.land_value(),
+            improvement_value: assessment.improvement_value(),
+            assessed_value,
+            assessed_value_per_unit: if parcel.resunits > 0 {
+                Some(assessed_value / parcel.resunits as f64)
+            } else {
+                None
+            },
+            assessed_value_per_sqft_lot: if parcel.shape_area > 0.0 {
+                Some(assessed_value / parcel.shape_area)
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// The whole roll, and `roll_year` or else the latest roll year in it
+fn load_roll(assessor_roll_path: &Path, roll_year: Option<u32>) -> Result<(Vec<TaxProperty>, u32), Box<dyn Error>> {
+    info!("Opening assessor roll: {}", assessor_roll_path.display());
+    let roll = load_assessor_roll(assessor_roll_path)?;
+    let mut num_by_roll_year: BTreeMap<Option<u32>, usize> = BTreeMap::new();
+    for property in &roll {
+        *num_by_roll_year.entry(property.rollyear).or_insert(0) += 1;
+    }
+    for (year, num) in &num_by_roll_year {
+        info!(
+            "Roll year {}: {} properties",
+            year.map(|year| year.to_string()).unwrap_or_else(|| "none".to_string()),
+            num
+        );
+    }
+    let roll_year = match roll_year.or_else(|| latest_roll_year(&roll)) {
+        Some(roll_year) => roll_year,
+        None => return Err("the assessor roll has no ROLLYEAR".into()),
+    };
+    Ok((roll, roll_year))
+}
+
+fn assessed_parcels(
+    land_use_path: &Path,
+    assessor_roll_path: &Path,
+    roll_year: Option<u32>,
+    mut output_write: Option<csv::Writer<File>>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error>> {
+    let (roll, roll_year) = load_roll(assessor_roll_path, roll_year)?;
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+    let keys = ParcelKeyIndex::new(&parcels);
+    let (assessments, unmatched) = join_land_use(&roll, roll_year, &keys);
+    for assessment in &assessments {
+        let o = ParcelRow::new(assessment, roll_year);
+        println!(
+            "{blklot}: assessed: {assessed_value}, land: {land_value}, improvements: {improvement_value}, units: {resunits}, lot area: {lot_area:.0}, properties: {properties}, address: {address}",
+            blklot = o.blklot,
+            assessed_value = o.assessed_value,
+            land_value = o.land_value,
+            improvement_value = o.improvement_value,
+            resunits = o.resunits,
+            lot_area = o.lot_area,
+            properties = o.properties,
+            address = o.address,
+        );
+        if let Some(output_write) = output_write.as_mut() {
+            output_write.serialize(o)?;
+        }
+    }
+    let num_properties: usize = assessments.iter().map(|assessment| assessment.properties.len()).sum();
+    info!(
+        "Roll year {}: {} properties on {} parcels; {} properties are not on any parcel",
+        roll_year,
+        num_properties,
+        assessments.len(),
+        unmatched.len()
+    );
+    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct GapRow {
+    blklot: String,
+    address: String,
+    landuse: String,
+    zoning: String,
+    neighborhood: String,
+    roll_year: u32,
+    properties: usize,
+    /// properties without a last sale date, which are left out of the values
+    unknown_sale_date: usize,
+    assessed_value: f64,
+    market_value: f64,
+    gap: f64,
+    assessment_ratio: Option<f64>,
+    /// years since the least recent sale
+    max_years: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct GapSummaryRow {
+    group_by: &'static str,
+    group: String,
+    parcels: usize,
+    properties: usize,
+    unknown_sale_date: usize,
+    assessed_value: f64,
+    market_value: f64,
+    gap: f64,
+    assessment_ratio: Option<f64>,
+    median_years: Option<i64>,
+}
+
+const GAP_GROUPS: [&str; 3] = ["neighborhood", "zoning", "landuse"];
+
+struct Options<'a> {
+    zoning_districts: Option<&'a Path>,
+    roll_year: Option<u32>,
+    market_growth: f64,
+    geojson_path: Option<&'a Path>,
+}
+
+/// Estimate the market value of each parcel from the years since its properties were sold,
+/// and total the gaps by neighborhood, zoning district and land use
+fn assessment_gap(
+    land_use_path: &Path,
+    assessor_roll_path: &Path,
+    options: &Options,
+    mut out_parcels: Option<csv::Writer<File>>,
+    mut out_summary: Option<csv::Writer<File>>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error>> {
+    let (roll, roll_year) = load_roll(assessor_roll_path, options.roll_year)?;
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+    let zoning_districts = match options.zoning_districts {
+        Some(zoning_districts_path) => {
+            info!("Loading zoning districts");
+            Some(SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?)
+        }
+        None => None,
+    };
+    let keys = ParcelKeyIndex::new(&parcels);
+    let (assessments, unmatched) = join_land_use(&roll, roll_year, &keys);
+    info!("{} properties are not on any parcel", unmatched.len());
+
+    let mut summaries: BTreeMap<(&'static str, String), GapSummary> = BTreeMap::new();
+    let mut features = vec![];
+    for assessment in &assessments {
+        let parcel = &assessment.parcel.value;
+        let parcel_gap = ParcelGap::new(assessment, options.market_growth);
+        let zoning = zoning_districts
+            .as_ref()
+            .and_then(|zoning_districts| get_zoning(zoning_districts, &assessment.parcel.multi_polygon))
+            .map(|district| district.zoning_sim.to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let neighborhood = assessment
+            .properties
+            .iter()
+            .map(|property| property.rp1nbrcde.trim())
+            .find(|code| !code.is_empty())
+            .unwrap_or("unknown")
+            .to_string();
+        let o = GapRow {
+            blklot: parcel.blklot.clone(),
+            address: parcel.address(),
+            landuse: parcel.landuse.to_string(),
+            zoning,
+            neighborhood,
+            roll_year,
+            properties: parcel_gap.properties,
+            unknown_sale_date: parcel_gap.unknown,
+            assessed_value: parcel_gap.assessed_value,
+            market_value: parcel_gap.market_value,
+            gap: parcel_gap.gap(),
+            assessment_ratio: parcel_gap.assessment_ratio(),
+            max_years: parcel_gap.max_years(),
+        };
+        for (&group_by, group) in GAP_GROUPS.iter().zip(&[&o.neighborhood, &o.zoning, &o.landuse]) {
+            if group_by == "zoning" && zoning_districts.is_none() {
+                continue;
+            }
+            summaries
+                .entry((group_by, group.to_string()))
+                .or_default()
+                .add(&parcel_gap);
+        }
+        if options.geojson_path.is_some() {
+            let properties = match serde_json::to_value(&o)? {
+                serde_json::Value::Object(map) => map,
+                _ => panic!("object should have turned into json object"),
+            };
+            features.push(Feature {
+                bbox: None,
+                geometry: Some(multipolygon_to_geojson(&assessment.parcel.multi_polygon)),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            });
+        }
+        if let Some(out_parcels) = out_parcels.as_mut() {
+            out_parcels.serialize(o)?;
+        }
+    }
+
+    for &group_by in GAP_GROUPS.iter() {
+        if group_by == "zoning" && zoning_districts.is_none() {
+            continue;
+        }
+        println!("By {}:", group_by);
+        for ((_, group), summary) in summaries.range((group_by, String::new())..).take_while(|((g, _), _)| *g == group_by) {
+            let o = GapSummaryRow {
+                group_by,
+                group: group.clone(),
+                parcels: summary.parcels,
+                properties: summary.properties,
+                unknown_sale_date: summary.unknown,
+                assessed_value: summary.assessed_value,
+                market_value: summary.market_value,
+                gap: summary.gap(),
+                assessment_ratio: summary.assessment_ratio(),
+                median_years: summary.median_years(),
+            };
+            println!(
+                "    {group}: parcels: {parcels}, assessed: {assessed_value:.0}, market: {market_value:.0}, gap: {gap:.0}, ratio: {assessment_ratio}, median years since sale: {median_years}",
+                group = o.group,
+                parcels = o.parcels,
+                assessed_value = o.assessed_value,
+                market_value = o.market_value,
+                gap = o.gap,
+                assessment_ratio = o.assessment_ratio.map(|ratio| format!("{:.2}", ratio)).unwrap_or_else(|| "none".to_string()),
+                median_years = o.median_years.map(|years| years.to_string()).unwrap_or_else(|| "none".to_string()),
+            );
+            if let Some(out_summary) = out_summary.as_mut() {
+                out_summary.serialize(o)?;
+            }
+        }
+    }
+
+    if let Some(geojson_path) = options.geojson_path {
+        info!("Writing {}", geojson_path.display());
+        let geojson = FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        serde_json::to_writer(File::create(geojson_path)?, &geojson)?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ApnDiffRow {
+    from_roll_year: u32,
+    to_roll_year: u32,
+    apn: String,
+    block: String,
+    /// e.g. `units,class-code`
+    changes: String,
+    units_before: Option<f64>,
+    units_after: Option<f64>,
+    units_change: f64,
+    sqft_before: Option<f64>,
+    sqft_after: Option<f64>,
+    yrblt_before: Option<u32>,
+    yrblt_after: Option<u32>,
+    class_code_before: Option<String>,
+    class_code_after: Option<String>,
+    new_construction: bool,
+    address: String,
+}
+
+impl ApnDiffRow {
+    fn new(diff: &ApnDiff, from_roll_year: u32, to_roll_year: u32) -> Self {
+        let (class_code_before, class_code_after) = diff.class_codes();
+        ApnDiffRow {
+            from_roll_year,
+            to_roll_year,
+            apn: diff.apn.clone(),
+            block: diff.block().to_string(),
+            changes: diff.changes.iter().map(Change::as_str).collect::<Vec<_>>().join(","),
+            units_before: diff.before.and_then(|p| p.units),
+            units_after: diff.after.and_then(|p| p.units),
+            units_change: diff.units_change(),
+            sqft_before: diff.before.and_then(|p| p.sqft),
+            sqft_after: diff.after.and_then(|p| p.sqft),
+            yrblt_before: diff.before.and_then(|p| p.yrblt),
+            yrblt_after: diff.after.and_then(|p| p.yrblt),
+            class_code_before: class_code_before.map(|code| code.to_string()),
+            class_code_after: class_code_after.map(|code| code.to_string()),
+            new_construction: diff.is_new_construction(),
+            address: diff.after.or(diff.before).map(|p| p.proploc.trim().to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct BlockChangeRow {
+    from_roll_year: u32,
+    to_roll_year: u32,
+    block: String,
+    kind: &'static str,
+    added: String,
+    removed: String,
+    units_change: f64,
+}
+
+/// Compare each roll year to the next one
+fn roll_diff(
+    roll_paths: &[PathBuf],
+    mut out_apns: Option<csv::Writer<File>>,
+    mut out_blocks: Option<csv::Writer<File>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut roll = vec![];
+    for roll_path in roll_paths {
+        info!("Opening assessor roll: {}", roll_path.display());
+        roll.extend(load_assessor_roll(roll_path)?);
+    }
+    let mut roll_years: Vec<u32> = roll.iter().filter_map(|property| property.rollyear).collect();
+    roll_years.sort();
+    roll_years.dedup();
+    if roll_years.len() < 2 {
+        return Err(format!("need at least two roll years to compare; got {:?}", roll_years).into());
+    }
+    for years in roll_years.windows(2) {
+        let (from, to) = (years[0], years[1]);
+        let diffs = diff_roll_years(&roll, from, to);
+        let mut num_by_change: BTreeMap<Change, usize> = BTreeMap::new();
+        let mut num_new_construction = 0;
+        let (mut num_lost, mut units_lost, mut num_gained, mut units_gained) = (0, 0.0, 0, 0.0);
+        for diff in &diffs {
+            for &change in &diff.changes {
+                *num_by_change.entry(change).or_insert(0) += 1;
+            }
+            if diff.is_new_construction() {
+                num_new_construction += 1;
+            }
+            // units of APNs in both years; added and removed APNs are counted by block below
+            if diff.before.is_some() && diff.after.is_some() {
+                let units_change = diff.units_change();
+                if units_change < 0.0 {
+                    num_lost += 1;
+                    units_lost -= units_change;
+                } else if units_change > 0.0 {
+                    num_gained += 1;
+                    units_gained += units_change;
+                }
+            }
+            if let Some(out_apns) = out_apns.as_mut() {
+                out_apns.serialize(ApnDiffRow::new(diff, from, to))?;
+            }
+        }
+        let blocks = block_changes(&diffs);
+        println!("Roll year {} to {}: {} APNs changed", from, to, diffs.len());
+        for change in Change::ALL.iter() {
+            println!("    {:<12} {}", change.as_str(), num_by_change.get(change).cloned().unwrap_or(0));
+        }
+        println!("    {} APNs lost {} units (mergers and demolitions)", num_lost, units_lost);
+        println!("    {} APNs gained {} units", num_gained, units_gained);
+        println!("    {} APNs have new construction", num_new_construction);
+        for block_change in &blocks {
+            let o = BlockChangeRow {
+                from_roll_year: from,
+                to_roll_year: to,
+                block: block_change.block.clone(),
+                kind: block_change.kind().as_str(),
+                added: block_change.added.join(","),
+                removed: block_change.removed.join(","),
+                units_change: block_change.units_change,
+            };
+            println!(
+                "    block {block}: {kind}, added: [{added}], removed: [{removed}], units: {units_change:+}",
+                block = o.block,
+                kind = o.kind,
+                added = o.added,
+                removed = o.removed,
+                units_change = o.units_change,
+            );
+            if let Some(out_blocks) = out_blocks.as_mut() {
+                out_blocks.serialize(o)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("The Assessor's secured roll joined to the LandUse parcels")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name("parcels")
+            .about("Assessed land and improvement values of each parcel, next to its units and lot area")
+            .arg(Arg::with_name("roll-year")
+                .long("roll-year")
+                .help("Roll year to join, e.g. 2018 (default: the latest in the roll)")
+                .takes_value(true)
+            )
+            .arg(output_arg("out-parcels", "csv file output"))
+        )
+        .subcommand(SubCommand::with_name("gap")
+            .about("Prop 13 gap between assessed and estimated market values, by neighborhood, zoning district (with --zoning-districts) and land use")
+            .arg(Arg::with_name("roll-year")
+                .long("roll-year")
+                .help("Roll year to join, e.g. 2018 (default: the latest in the roll)")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("market-growth")
+                .long("market-growth")
+                .help("Assumed yearly growth of market values since the last sale, e.g. 0.05 for 5%; assessed values grow by at most 2%")
+                .takes_value(true)
+                .default_value("0.05")
+            )
+            .arg(output_arg("out-parcels", "csv file output, one row per parcel"))
+            .arg(output_arg("out-summary", "csv file output, one row per group"))
+            .arg(output_arg("geojson", "geojson file output, one feature per parcel"))
+        )
+        .subcommand(SubCommand::with_name("diff")
+            .about("Changes in units, area, year built and class code of each APN between roll years, and lot splits and merges")
+            .arg(Arg::with_name("rolls")
+                .help("More roll workbooks, e.g. one per year, besides --assessor-roll")
+                .multiple(true)
+                .takes_value(true)
+            )
+            .arg(output_arg("out-apns", "csv file output, one row per changed APN"))
+            .arg(output_arg("out-blocks", "csv file output, one row per block where APNs were added or removed"))
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("parcels") {
+        let roll_year = match matches.value_of("roll-year") {
+            Some(_) => Some(value_t!(matches.value_of("roll-year"), u32)?),
+            None => None,
+        };
+        assessed_parcels(
+            &dataset_path(matches, Dataset::LandUse)?,
+            &dataset_path(matches, Dataset::AssessorRoll)?,
+            roll_year,
+            output_file(matches, "out-parcels")?.map(csv::Writer::from_writer),
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("gap") {
+        let roll_year = match matches.value_of("roll-year") {
+            Some(_) => Some(value_t!(matches.value_of("roll-year"), u32)?),
+            None => None,
+        };
+        let zoning_districts = match dataset_path(matches, Dataset::ZoningDistricts) {
+            Ok(path) => Some(path),
+            Err(DatasetPathError::Missing(_)) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let options = Options {
+            zoning_districts: zoning_districts.as_deref(),
+            roll_year,
+            market_growth: value_t!(matches.value_of("market-growth"), f64).expect("Expected value for market-growth"),
+            geojson_path: matches.value_of_os("geojson").map(Path::new),
+        };
+        assessment_gap(
+            &dataset_path(matches, Dataset::LandUse)?,
+            &dataset_path(matches, Dataset::AssessorRoll)?,
+            &options,
+            output_file(matches, "out-parcels")?.map(csv::Writer::from_writer),
+            output_file(matches, "out-summary")?.map(csv::Writer::from_writer),
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        let mut roll_paths = match dataset_path(matches, Dataset::AssessorRoll) {
+            Ok(path) => vec![path],
+            Err(DatasetPathError::Missing(_)) => vec![],
+            Err(err) => return Err(err.into()),
+        };
+        roll_paths.extend(matches.values_of_os("rolls").into_iter().flatten().map(PathBuf::from));
+        roll_diff(
+            &roll_paths,
+            output_file(matches, "out-apns")?.map(csv::Writer::from_writer),
+            output_file(matches, "out-blocks")?.map(csv::Writer::from_writer),
+        )?;
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
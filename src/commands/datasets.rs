@@ -0,0 +1,118 @@
+//! Checks the local copies of the datasets listed in the dataset manifest
+
+use crate::cli::dataset_manifest;
+use crate::commands::parcelscanchicago::{BuildingRecord, ParcelRecord};
+use crate::manifest::{sha256_file, Dataset, DatasetEntry};
+use crate::schema::{read_csv_header, read_xlsx_header, HeaderDiff};
+use crate::sfassessormap::TaxProperty;
+use crate::sfbuildingpermits::BuildingPermitRecord;
+use crate::sfbuidingfootprints::BuildingFootprintsRecord;
+use crate::sfheightbulk::HeightBulkDistrict;
+use crate::sflanduse::LandUseRecord;
+use crate::sfplanningacela::PPTSRecord;
+use crate::sfzoningdistricts::ZoningDistrict;
+use clap::{App, AppSettings, ArgMatches, SubCommand};
+use std::error::Error;
+
+pub const NAME: &str = "datasets";
+
+/// How `header` differs from the columns that the record struct of `dataset` reads
+fn header_diff(dataset: Dataset, header: &[String]) -> HeaderDiff {
+    match dataset {
+        Dataset::LandUse => HeaderDiff::for_record::<LandUseRecord>(header),
+        Dataset::ZoningDistricts => HeaderDiff::for_record::<ZoningDistrict>(header),
+        Dataset::HeightBulk => HeaderDiff::for_record::<HeightBulkDistrict>(header),
+        Dataset::Footprints => HeaderDiff::for_record::<BuildingFootprintsRecord>(header),
+        Dataset::Ppts => HeaderDiff::for_record::<PPTSRecord>(header),
+        Dataset::BuildingPermits => HeaderDiff::for_record::<BuildingPermitRecord>(header),
+        Dataset::AssessorRoll => HeaderDiff::for_record::<TaxProperty>(header),
+        Dataset::ChicagoBuildings => HeaderDiff::for_record::<BuildingRecord>(header),
+        Dataset::CookParcels => HeaderDiff::for_record::<ParcelRecord>(header),
+    }
+}
+
+/// Everything that is wrong with the file of one dataset, and notes that are not problems
+fn check_dataset(dataset: Dataset, entry: &DatasetEntry) -> (Vec<String>, Vec<String>) {
+    let mut problems = vec![];
+    let mut notes = vec![];
+    if !entry.path.is_file() {
+        problems.push(format!("{} does not exist", entry.path.display()));
+        return (problems, notes);
+    }
+    if entry.schema_version != dataset.schema_version() {
+        problems.push(format!(
+            "schema version {} is not supported (expected {})",
+            entry.schema_version,
+            dataset.schema_version()
+        ));
+    }
+    if let Some(expected_sha256) = &entry.sha256 {
+        match sha256_file(&entry.path) {
+            Ok(sha256) if sha256.eq_ignore_ascii_case(expected_sha256) => {}
+            Ok(sha256) => problems.push(format!("sha256 is {}, expected {}", sha256, expected_sha256)),
+            Err(err) => problems.push(format!("could not read file: {}", err)),
+        }
+    }
+    let header = match dataset {
+        Dataset::AssessorRoll => read_xlsx_header(&entry.path),
+        _ => read_csv_header(&entry.path),
+    };
+    match header {
+        Ok(header) => {
+            let diff = header_diff(dataset, &header);
+            if diff.is_compatible() {
+                if !diff.renamed.is_empty() || !diff.extra.is_empty() || !diff.missing_optional.is_empty() {
+                    notes.push(diff.to_string());
+                }
+            } else {
+                problems.push(diff.to_string());
+            }
+        }
+        Err(err) => problems.push(format!("could not read header: {}", err)),
+    }
+    (problems, notes)
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Manage the dataset manifest (parcelscan.toml)")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name("check")
+            .about("Check that every dataset in the manifest exists, matches its checksum and has the expected columns")
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let manifest = dataset_manifest(matches)?.ok_or("no --manifest given and no parcelscan.toml")?;
+        println!("{}:", manifest.path.display());
+        let mut num_failed = 0;
+        for &dataset in Dataset::ALL.iter() {
+            let entry = match manifest.get(dataset) {
+                Some(entry) => entry,
+                None => {
+                    println!("{}: not in manifest", dataset);
+                    continue;
+                }
+            };
+            let (problems, notes) = check_dataset(dataset, entry);
+            if problems.is_empty() {
+                println!("{}: ok ({})", dataset, entry.path.display());
+            } else {
+                num_failed += 1;
+                println!("{}: FAILED ({})", dataset, entry.path.display());
+            }
+            for problem_or_note in problems.iter().chain(notes.iter()) {
+                println!("    {}", problem_or_note);
+            }
+        }
+        if num_failed > 0 {
+            return Err(format!("{} datasets failed the check", num_failed).into());
+        }
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
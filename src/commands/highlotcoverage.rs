@@ -0,0 +1,489 @@
+//! Buildings that exceed the maximum lot coverage of their zoning district
+
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Stdin, StdinLock, Write};
+use std::sync::{Mutex, RwLock};
+
+use chrono::Datelike;
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use conv::ConvUtil;
+use geo::{Coordinate, MultiPolygon, Point};
+use geo::algorithm::area::Area;
+use geo::algorithm::centroid::Centroid;
+use geo::algorithm::orient::{Direction, Orient};
+use geojson::{Feature, FeatureCollection, Geometry};
+use geojson::feature::Id;
+use rayon::prelude::*;
+use serde_json::Map;
+use wkt::types::Coord;
+
+use crate::cli::{dataset_path, output_arg, output_file, DatasetPathError};
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowError, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+use crate::geo_util::{default_projection, multipolygon_to_geojson, point_from_ft, to_ft};
+use crate::filter_expr::FilterExpr;
+use crate::lot_sides::{get_rear_side, min_setback, side_setbacks, SetbackAndAnnotation, SideType};
+use crate::parcel_join::{join_parcels_by_key, JoinMethod, JoinStats, ParcelJoinMode, ParcelKeyIndex};
+use crate::polygon_wrapper::{parse_wkt_to_multipolygon, GeometryParseError};
+use crate::sfbuidingfootprints::BuildingFootprintsRecord;
+use crate::sfheightbulk::{get_height_limit, HeightBulkDistrict, HeightStatus};
+use crate::sflanduse::LandUseRecord;
+use crate::sfplanningacela::PPTSRecord;
+use crate::sfzoningdistricts::{get_zoning, ZoningDistrict};
+use crate::spatial_layer::SpatialLayer;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct OutputRow {
+    mblr: String,
+    building_area: f64,
+    side_setbacks: Option<Vec<SetbackAndAnnotation>>,
+    /// smallest setback of each side type, in ft.
+    #[serde(default)]
+    front_setback: Option<f64>,
+    #[serde(default)]
+    side_setback: Option<f64>,
+    #[serde(default)]
+    rear_setback: Option<f64>,
+    yrbuilt: Option<usize>,
+    addr: Option<String>,
+    lot_blklot: Option<String>,
+    lot_area: Option<f64>,
+    zoning_district_name: Option<String>,
+    /// simplified zoning district, e.g. RH-2
+    #[serde(default)]
+    zoning: Option<String>,
+    geojson: FeatureCollection,
+    height: f64,
+    building_wkt: String,
+    resunits: usize,
+    lot_wkt: Option<String>,
+    /// fraction of the building's footprint that is on this lot
+    #[serde(default)]
+    overlap_fraction: Option<f64>,
+    /// fraction of the building's footprint that is on the lot(s) it was assigned to
+    #[serde(default)]
+    overlap_confidence: Option<f64>,
+    /// whether the lot was found by the footprint's mblr key or by geometry
+    #[serde(default)]
+    parcel_join_method: Option<JoinMethod>,
+    /// height and bulk district label, e.g. 40-X
+    #[serde(default)]
+    height_district: Option<String>,
+    #[serde(default)]
+    height_limit: Option<f64>,
+    #[serde(default)]
+    height_status: Option<HeightStatus>,
+}
+
+
+
+#[derive(thiserror::Error, Debug)]
+pub enum LotCoverageError {
+    #[error("io error writing file")]
+    Io {#[from] source: std::io::Error },
+    #[error("{0}")]
+    Row(#[from] RowError),
+    #[error("json error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+struct Options<'a> {
+    height_bulk: Option<&'a Path>,
+    min_coverage: f64,
+    filter: FilterExpr,
+    parcel_join_mode: ParcelJoinMode,
+    min_overlap_confidence: f64,
+    height_tolerance: f64,
+    under_height_fraction: f64,
+}
+
+fn lot_coverage(
+    land_use_path: &Path,
+    zoning_districts_path: &Path,
+    footprints_path: &Path,
+    options: &Options,
+    output_write: Option<File>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), LotCoverageError> {
+    let proj = default_projection();
+
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+
+    info!("Scanning Zoning_Districts to make lookup table of zoning");
+    let zoning_districts = SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?;
+
+    let height_bulk_districts = match options.height_bulk {
+        Some(height_bulk_path) => {
+            info!("Scanning Height and Bulk Districts");
+            Some(SpatialLayer::<HeightBulkDistrict>::load_cached("height_bulk_districts", height_bulk_path, row_error_policy, cache)?)
+        }
+        None => None,
+    };
+
+    info!("Scanning footprints");
+    let footprints = SpatialLayer::<BuildingFootprintsRecord>::load_cached("footprints", footprints_path, row_error_policy, cache)?;
+    let output_write: Option<Mutex<File>> = output_write.map(Mutex::new);
+    let parcel_keys = ParcelKeyIndex::new(&parcels);
+    let join_stats = JoinStats::default();
+    footprints.iter().par_bridge().map(|footprint| -> Result<(), LotCoverageError> {
+        let shape: &MultiPolygon<f64> = &footprint.multi_polygon;
+        let record = &footprint.value;
+        let zoning_district = get_zoning(&zoning_districts, shape);
+        let zoning_district_name = zoning_district.map(|zoning_district| zoning_district.zoning.to_owned());
+        let height = record.hgt_maxcm/2.54/12.0;
+        let height_bulk = height_bulk_districts.as_ref()
+            .and_then(|height_bulk_districts| get_height_limit(height_bulk_districts, shape))
+            .map(|height_bulk_district| &height_bulk_district.height);
+        let height_status = height_bulk
+            .and_then(|height_bulk| height_bulk.height_status(height, options.height_tolerance, options.under_height_fraction));
+        let blklot = record.blklot();
+        let join = join_parcels_by_key(&parcels, &parcel_keys, blklot.as_deref(), shape, options.parcel_join_mode, &join_stats);
+        if !join_stats.is_confident(&join, options.min_overlap_confidence) {
+            // the footprint is mostly off its lot(s), so the datasets disagree
+            return Ok(()); // continue
+        }
+        // Centroid in latitude and longitude seems to be about a hundred feet incorrect
+        // so we find the centroid in the ft projection
+        let centroid: Point<f64> = point_from_ft(
+            to_ft(shape).centroid()
+            .expect("multipolygon should have at least one point")
+        );
+        for parcel_match in join.matches.iter() {
+            let land_use_polygon = parcel_match.parcel;
+            let land_use_record = &land_use_polygon.value;
+            // only the part of the building that stands on this lot counts towards its setbacks and coverage
+            let building_part_ft: MultiPolygon<f64> = to_ft(&parcel_match.building_part);
+            let sides_and_annotations = get_rear_side(&parcels, land_use_polygon);
+            let side_setbacks: Vec<SetbackAndAnnotation> = side_setbacks(&sides_and_annotations, &building_part_ft);
+            let lot_area = land_use_record.shape_area;
+            let addr = format!("{}-{} {} {}", land_use_record.from_st.map(|x| format!("{}", x)).unwrap_or("?".to_owned()), land_use_record.to_st.map(|x| format!("{}", x)).unwrap_or("?".to_owned()), land_use_record.street, land_use_record.st_type);
+            let building_area: f64 = building_part_ft.0.iter().map(|polygon_ft| polygon_ft.area().abs()).sum(); // note: area() is signed area
+            let resunits = land_use_record.resunits;
+
+            let footprint_feature: Feature = Feature {
+                bbox: None,
+                geometry: Some(multipolygon_to_geojson(shape)),
+                id: None,
+                properties: Some({
+                    let mut map = Map::new();
+                    map.insert("name".to_string(), serde_json::Value::String("footprint".to_string()));
+                    map
+                }),
+                foreign_members: None
+            };
+            let footprint_centroid_feature: Feature = Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                    centroid.x(), centroid.y()
+                ]))),
+                id: None,
+                properties: Some({
+                    let mut map = Map::new();
+                    map.insert("name".to_string(), serde_json::Value::String("footprint centroid".to_string()));
+                    map
+                }),
+                foreign_members: None
+            };
+            let lot_polygon = &land_use_polygon.multi_polygon;
+            let lot_feature: Feature = Feature {
+                bbox: None,
+                geometry: Some(multipolygon_to_geojson(lot_polygon)),
+                id: None,
+                properties: Some({
+                    let mut map = Map::new();
+                    map.insert("name".to_string(), serde_json::Value::String("lot".to_string()));
+                    map
+                }),
+                foreign_members: None
+            };
+            let lot_centroid: Feature = {
+                let centroid = lot_polygon.centroid().expect("lot has no centroid");
+                Feature {
+                    bbox: None,
+                    geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                        centroid.x(), centroid.y()
+                    ]))),
+                    id: None,
+                    properties: Some({
+                        let mut map = Map::new();
+                        map.insert("name".to_string(), serde_json::Value::String("lot centroid".to_string()));
+                        map
+                    }),
+                    foreign_members: None
+                }
+            };
+            let features: Vec<Feature> = vec![
+                footprint_feature,
+                footprint_centroid_feature,
+                lot_feature,
+                lot_centroid,
+            ];
+            let geojson = FeatureCollection {
+                bbox: None,
+                features,
+                foreign_members: None,
+            };
+            if building_area / lot_area < options.min_coverage {
+                continue;
+            }
+            let o = OutputRow {
+                mblr: record.sf_mblr.clone(),
+                building_area,
+                front_setback: min_setback(&side_setbacks, SideType::Front),
+                side_setback: min_setback(&side_setbacks, SideType::Side),
+                rear_setback: min_setback(&side_setbacks, SideType::Rear),
+                side_setbacks: Some(side_setbacks),
+                yrbuilt: Some(land_use_record.yrbuilt),
+                addr: Some(addr),
+                lot_blklot: Some(land_use_record.blklot.to_owned()),
+                lot_area: Some(lot_area),
+                zoning_district_name: zoning_district_name.clone(),
+                zoning: zoning_district.map(|zoning_district| zoning_district.zoning_sim.to_string()),
+                geojson,
+                height,
+                building_wkt: record.shape.clone(),
+                resunits,
+                lot_wkt: Some(land_use_record.the_geom.clone()),
+                overlap_fraction: Some(parcel_match.overlap_fraction),
+                overlap_confidence: Some(join.confidence),
+                parcel_join_method: Some(join.method),
+                height_district: height_bulk.map(|height_bulk| height_bulk.label.clone()),
+                height_limit: height_bulk.and_then(|height_bulk| height_bulk.height_limit),
+                height_status,
+            /*
+                record_type_category: record.record_type_category,
+                description: record.description,
+                address: record.address,
+                office_proposed,
+                office_net,
+                date_opened: record.date_opened.date().format("%Y-%m-%d").to_string(),
+                date_closed: record.date_closed.map(|d| d.date().format("%Y-%m-%d").to_string()),
+                record_status: record.record_status,
+                related_building_permit: record.related_building_permit,
+             */
+            };
+            if !options.filter.matches(&o)? {
+                continue;
+            }
+            print_row(&o);
+            if let Some(output_write) = output_write.as_ref() {
+                let mut output_write = output_write.lock()
+                    .expect("Failed to acquire lock on output file");
+                write!(output_write, "{}\n", serde_json::to_string(&o).unwrap())
+                    .map_err(LotCoverageError::from)?;
+            }
+        }
+        Ok(())
+    }).collect::<Result<(), LotCoverageError>>()?;
+    join_stats.log_summary();
+    Ok(())
+}
+fn print_row(o: &OutputRow) {
+
+    println!(
+    "{}",
+        serde_json::to_string(&o).unwrap()
+/*
+        "mblr: {mblr}, building_name: {building_name:?} zoning_district: {zoning_district_name:?}, building_area: {building_area}, lot_blklot: {lot_blklot:?}, lot_area: {lot_area:?}",
+        mblr = o.mblr,
+        building_name = o.building_name,
+        building_area = o.building_area,
+        lot_blklot = o.lot_blklot,
+        lot_area = o.lot_area,
+        zoning_district_name = o.zoning_district_name,
+*/
+        // "* {record_type_category} opened: {date_opened}, closed: {date_closed}, address: {address}, office: {office_proposed}, office_net: {office_net}, status: {record_status}, building: {related_building_permit}, description: {description}",
+        // record_type_category = o.record_type_category,
+        // date_opened = o.date_opened,
+        // date_closed = o.date_closed.as_ref().map(|x| x.as_str()).unwrap_or("none"),
+        // address = o.address,
+        // office_proposed = o.office_proposed,
+        // office_net = o.office_net,
+        // record_status = o.record_status,
+        // related_building_permit = o.related_building_permit,
+        // description = o.description,
+    );
+}
+
+pub const NAME: &str = "highlotcoverage";
+const MAIN_COMMAND: &str = "coverage";
+/// The buildings like 4326-4336 Irving Street: residential buildings on full-size RH-2 lots.
+/// `zoning` is the simplified district (`zoning_sim`), which the hard-coded check before filters compared;
+/// the full district name (`zoning` column) is `zoning_district_name`.
+const DEFAULT_FILTER: &str = "zoning == \"RH-2\" && lot_area >= 2490 && resunits > 0";
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("List of buildings that exceed max lot coverage")
+        .author("Yonathan.")
+        .after_help("Print buildings that exceed 75% of lot coverage")
+        .subcommand(SubCommand::with_name(MAIN_COMMAND)
+            .arg(Arg::with_name("min-coverage")
+                .long("min-coverage")
+                .help("Minimum lot coverage (0-1)")
+                .takes_value(true)
+                .required(false)
+                .default_value("0.85")
+            )
+            .arg(Arg::with_name("filter")
+                .long("filter")
+                .help("Only output buildings for which this expression is true. It can refer to any output field, e.g. 'zoning == \"RH-1\" && resunits >= 4 && yrbuilt < 1930' or 'rear_setback <= 15'. zoning is the simplified district (zoning_sim) and zoning_district_name is the full district name")
+                .takes_value(true)
+                .default_value(DEFAULT_FILTER)
+            )
+            .arg(Arg::with_name("filter-file")
+                .long("filter-file")
+                .help("Read the --filter expression from this file instead")
+                .takes_value(true)
+                .conflicts_with("filter")
+            )
+            .arg(Arg::with_name("parcel-join")
+                .long("parcel-join")
+                .help("How to assign footprints without a usable mblr key to LandUse parcels: the parcel containing the footprint centroid, the parcel with the largest overlap, or split the footprint across all parcels it overlaps")
+                .takes_value(true)
+                .possible_values(&["centroid", "largest-overlap", "split"])
+                .default_value("largest-overlap")
+            )
+            .arg(Arg::with_name("min-overlap-confidence")
+                .long("min-overlap-confidence")
+                .help("Skip footprints that have less than this fraction (0-1) of their area on their assigned parcel(s). With largest-overlap, a building spread over several lots has a low confidence, so the default keeps every footprint")
+                .takes_value(true)
+                .default_value("0")
+            )
+            .arg(Arg::with_name("height-tolerance")
+                .long("height-tolerance")
+                .help("Feet that a building may exceed its height limit before it is over-height, to allow for parapets and penthouses that are exempt from the limit")
+                .takes_value(true)
+                .default_value("16")
+            )
+            .arg(Arg::with_name("under-height-fraction")
+                .long("under-height-fraction")
+                .help("Buildings shorter than this fraction (0-1) of their height limit are far-under-height")
+                .takes_value(true)
+                .default_value("0.5")
+            )
+            .arg(output_arg("out", "jsonl file output"))
+            .about("Show information about expansions")
+        )
+        .subcommand(SubCommand::with_name("geojson")
+            .arg(Arg::with_name("file")
+                .long("file")
+                .help("jsonl file input, which was output by coverage")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches(MAIN_COMMAND) {
+        let zoning_districts = dataset_path(matches, Dataset::ZoningDistricts)?;
+        let land_use_path = dataset_path(matches, Dataset::LandUse)?;
+        let height_bulk = match dataset_path(matches, Dataset::HeightBulk) {
+            Ok(path) => Some(path),
+            Err(DatasetPathError::Missing(_)) => None,
+            Err(err) => return Err(err.into()),
+        };
+        let footprints = dataset_path(matches, Dataset::Footprints)?;
+        let filter: FilterExpr = match matches.value_of_os("filter-file") {
+            Some(filter_path) => std::fs::read_to_string(filter_path)?.parse()?,
+            None => matches.value_of("filter").unwrap_or(DEFAULT_FILTER).parse()?,
+        };
+        let options = Options {
+            height_bulk: height_bulk.as_deref(),
+            min_coverage: value_t!(matches.value_of("min-coverage"), f64)
+                .expect("Expected value for min-coverage"),
+            filter,
+            parcel_join_mode: value_t!(matches.value_of("parcel-join"), ParcelJoinMode)
+                .expect("Expected value for parcel-join"),
+            min_overlap_confidence: value_t!(matches.value_of("min-overlap-confidence"), f64)
+                .expect("Expected value for min-overlap-confidence"),
+            height_tolerance: value_t!(matches.value_of("height-tolerance"), f64)
+                .expect("Expected value for height-tolerance"),
+            under_height_fraction: value_t!(matches.value_of("under-height-fraction"), f64)
+                .expect("Expected value for under-height-fraction"),
+        };
+
+        let out_projects_writer_opt = output_file(matches, "out")?;
+        lot_coverage(
+            &land_use_path,
+            &zoning_districts,
+            &footprints,
+            &options,
+            out_projects_writer_opt,
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("geojson") {
+        let projects_path = matches.value_of_os("file").expect("required arg should exist");
+        let mut projects_file = BufReader::new(File::open(projects_path)?);
+        let mut lines: Vec<OutputRow> = vec![];
+
+        for line in projects_file.lines().into_iter() {
+            lines.push(serde_json::from_str(&line?)?);
+        }
+
+        let features = lines.into_iter().map(|o| {
+            let building_shape: MultiPolygon<f64> = parse_wkt_to_multipolygon(&*o.building_wkt)?;
+            let lot_polygon: Option<MultiPolygon<f64>> = o.lot_wkt.as_ref()
+                .map(|wkt| parse_wkt_to_multipolygon(&**wkt))
+                .map_or(Ok(None), |x: Result<MultiPolygon<f64>, GeometryParseError>| x.map(Some))?;
+            let properties = {
+                let val = serde_json::to_value(o).unwrap();
+                let mut map = match val {
+                    serde_json::Value::Object(map) => map,
+                    _ => panic!("object should have turned into json object"),
+                };
+                map.remove("building_wkt");
+                map.remove("lot_wkt");
+                map.remove("geojson");
+                map
+            };
+            let lot_feature: Option<Feature> = lot_polygon.as_ref().map(|lot_polygon| {
+                Feature {
+                    bbox: None,
+                    geometry: Some(multipolygon_to_geojson(lot_polygon)),
+                    id: None,
+                    properties: Some({
+                        let mut map = Map::new();
+                        map.insert("name".to_string(), serde_json::Value::String("lot".to_string()));
+                        map
+                    }),
+                    foreign_members: None
+                }
+            });
+            let footprint_feature: Feature = Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::GeometryCollection(vec![
+                    lot_polygon.as_ref().map(multipolygon_to_geojson),
+                    Some(multipolygon_to_geojson(&building_shape)),
+                ].into_iter().flatten().collect()))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None
+            };
+            Ok(footprint_feature)
+        })
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let geojson = FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        println!("{}", serde_json::to_string(&geojson).unwrap());
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Statistics on the SF Land Use parcels (the original `parcelscan` subcommands)
+
+use crate::cli::dataset_path;
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowErrorHandler};
+use crate::sflanduse::LandUseRecord;
+use clap::{App, ArgMatches, SubCommand};
+use csv::Reader;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+
+pub const HOUSES_ON_STANDARD_LOTS: &str = "houses-on-standard-lots";
+pub const DENSITY_HISTOGRAM: &str = "density-historgram";
+
+fn houses_on_standard_lots(mut rdr: Reader<File>, handler: &RowErrorHandler) -> Result<(), Box<dyn Error>> {
+    let mut num_normal_lots = 0;
+    let mut num_lots = 0;
+    let mut num_res_lots = 0;
+    let mut map: BTreeMap<usize, Vec<LandUseRecord>> = BTreeMap::new();
+    for result in handler.records::<_, LandUseRecord>(&mut rdr) {
+        // The iterator yields Result<Row, RowError>, so we check the
+        // error here.
+        let record = result?.record;
+        //        info!("{:?}", record);
+        if record.landuse.is_residential() {
+            num_res_lots += 1;
+        }
+        if 2100. < record.shape_area && record.shape_area < 2600. && record.landuse.is_residential() {
+            num_normal_lots += 1;
+            map.entry(record.resunits).or_insert(vec![]).push(record);
+        }
+        num_lots += 1;
+    }
+    handler.log_summary();
+    info!("{} total lots, {} residential", num_lots, num_res_lots);
+    info!("Found {} average-sized lots", num_normal_lots);
+    for (units, records) in map.iter() {
+        let mut records_sorted_by_date = records.iter().collect::<Vec<_>>();
+        records_sorted_by_date.sort_by_key(|record| record.yrbuilt);
+        records_sorted_by_date.reverse();
+        let records_fmt = records_sorted_by_date
+            .iter()
+            .take(5)
+            .map(|record| {
+                format!(
+                    "{}-{} {} {} ({}, {})",
+                    record
+                        .from_st
+                        .map(|x| format!("{}", x))
+                        .unwrap_or("".to_string()),
+                    record
+                        .to_st
+                        .map(|x| format!("{}", x))
+                        .unwrap_or("".to_string()),
+                    record.street,
+                    record.st_type,
+                    record.blklot,
+                    record.yrbuilt,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "{} units: {} records e.g. {}",
+            units,
+            records.len(),
+            records_fmt
+        )
+    }
+    Ok(())
+}
+fn density_historgram(mut rdr: Reader<File>, handler: &RowErrorHandler) -> Result<(), Box<dyn Error>> {
+    let mut num_lots = 0;
+    let mut num_res_lots = 0;
+    let mut map: BTreeMap<i64, Vec<LandUseRecord>> = BTreeMap::new();
+    for result in handler.records::<_, LandUseRecord>(&mut rdr) {
+        // The iterator yields Result<Row, RowError>, so we check the
+        // error here.
+        let record = result?.record;
+        //        info!("{:?}", record);
+        if record.landuse.is_residential() {
+            num_res_lots += 1;
+            map.entry((record.resunits as f64 / record.shape_area * 2500. * 4.0).round() as i64)
+                .or_insert(vec![])
+                .push(record);
+        }
+        num_lots += 1;
+    }
+    handler.log_summary();
+    info!("{} total lots, {} residential", num_lots, num_res_lots);
+    for (units_times4, records) in map.iter() {
+        let units = *units_times4 as f64 / 4.0;
+        let mut records_sorted_by_date = records.iter().collect::<Vec<_>>();
+        records_sorted_by_date.sort_by_key(|record| record.yrbuilt);
+        records_sorted_by_date.reverse();
+        let records_fmt = records_sorted_by_date
+            .iter()
+            .take(5)
+            .map(|record| {
+                format!(
+                    "{}-{} {} {} ({}, {})",
+                    record
+                        .from_st
+                        .map(|x| format!("{}", x))
+                        .unwrap_or("".to_string()),
+                    record
+                        .to_st
+                        .map(|x| format!("{}", x))
+                        .unwrap_or("".to_string()),
+                    record.street,
+                    record.st_type,
+                    record.blklot,
+                    record.yrbuilt,
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "{} units: {} records e.g. {}",
+            units,
+            records.len(),
+            records_fmt
+        )
+    }
+    Ok(())
+}
+
+/// The land use subcommands, which are at the top level of `parcelscan`
+pub fn subcommands<'a, 'b>() -> Vec<App<'a, 'b>> {
+    vec![
+        SubCommand::with_name(HOUSES_ON_STANDARD_LOTS)
+            .about("Show statistics about standard-sized lots"),
+        SubCommand::with_name(DENSITY_HISTOGRAM)
+            .about("Show statistics about all residences"),
+    ]
+}
+
+/// Run the land use subcommand `name`
+pub fn run(name: &str, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let input = dataset_path(matches, Dataset::LandUse)?;
+    info!("Opening {}", input.display());
+    let file = File::open(input)?;
+    let rdr = csv::Reader::from_reader(file);
+    let handler = RowErrorHandler::new("land_use", &row_error_policy(matches));
+    match name {
+        HOUSES_ON_STANDARD_LOTS => houses_on_standard_lots(rdr, &handler),
+        DENSITY_HISTOGRAM => density_historgram(rdr, &handler),
+        _ => panic!("Should not happen"),
+    }
+}
@@ -0,0 +1,17 @@
+//! The analyses, each a subcommand of `parcelscan` and also its own binary in `src/bin`.
+//!
+//! Each module has a `NAME`, an `app()` with its subcommands and options,
+//! and a `run()` that runs the subcommand that was matched.
+//! Datasets, row error handling and caching are the global options from `cli::global_args`.
+
+pub mod approvaltimes;
+pub mod approvedapartments;
+pub mod assessor;
+pub mod datasets;
+pub mod highlotcoverage;
+pub mod landuse;
+pub mod nonconforming;
+pub mod parcelscanchicago;
+pub mod peskinexpansionsimpact;
+pub mod projects;
+pub mod zoningcapacity;
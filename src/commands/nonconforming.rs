@@ -0,0 +1,311 @@
+//! Report of existing buildings that exceed what their zoning district allows
+
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use geo::algorithm::area::Area;
+use geo::MultiPolygon;
+use geojson::{Feature, FeatureCollection};
+use crate::cli::{dataset_path, output_arg};
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+use crate::geo_util::{multipolygon_to_geojson, to_ft};
+use crate::lot_sides::{get_rear_side, lot_depth, rear_yard_depth, side_setbacks};
+use crate::nonconforming::{check_parcel, summarize_by_district, ExistingParcel, Tolerances, Violation};
+use crate::parcel_join::{join_parcels_by_key, JoinStats, ParcelJoinMode, ParcelKeyIndex};
+use crate::polygon_wrapper::PolygonWrapper;
+use crate::sfbuidingfootprints::BuildingFootprintsRecord;
+use crate::sfheightbulk::{get_height_limit, HeightBulkDistrict};
+use crate::sflanduse::LandUseRecord;
+use crate::sfzoningdistricts::{get_zoning, ZoningCode, ZoningDistrict};
+use crate::spatial_layer::SpatialLayer;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+/// The part of one footprint that stands on a parcel
+struct BuildingPart<'a> {
+    parcel: &'a PolygonWrapper<LandUseRecord>,
+    building_part_ft: MultiPolygon<f64>,
+    /// ft.
+    height: f64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct OutputRow {
+    blklot: String,
+    address: String,
+    zoning: String,
+    height_district: Option<String>,
+    units: usize,
+    lot_area: f64,
+    lot_coverage: f64,
+    rear_yard_depth: Option<f64>,
+    lot_depth: Option<f64>,
+    height: Option<f64>,
+    /// human-readable list of violations
+    reasons: String,
+    violations: Vec<Violation>,
+}
+
+struct Options<'a> {
+    parcel_join_mode: ParcelJoinMode,
+    min_overlap_confidence: f64,
+    tolerances: Tolerances,
+    summary_path: Option<&'a Path>,
+    geojson_path: Option<&'a Path>,
+}
+
+fn nonconforming(
+    land_use_path: &Path,
+    zoning_districts_path: &Path,
+    height_bulk_path: &Path,
+    footprints_path: &Path,
+    options: &Options,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error>> {
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+
+    info!("Scanning Zoning_Districts to make lookup table of zoning");
+    let zoning_districts = SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?;
+
+    info!("Scanning Height and Bulk Districts");
+    let height_bulk_districts = SpatialLayer::<HeightBulkDistrict>::load_cached("height_bulk_districts", height_bulk_path, row_error_policy, cache)?;
+
+    info!("Scanning footprints");
+    let footprints = SpatialLayer::<BuildingFootprintsRecord>::load_cached("footprints", footprints_path, row_error_policy, cache)?;
+
+    info!("Assigning footprints to parcels");
+    let parcel_keys = ParcelKeyIndex::new(&parcels);
+    let join_stats = JoinStats::default();
+    let building_parts: Vec<BuildingPart> = footprints
+        .iter()
+        .par_bridge()
+        .flat_map(|footprint| {
+            let record = &footprint.value;
+            let blklot = record.blklot();
+            let join = join_parcels_by_key(&parcels, &parcel_keys, blklot.as_deref(), &footprint.multi_polygon, options.parcel_join_mode, &join_stats);
+            if !join_stats.is_confident(&join, options.min_overlap_confidence) {
+                // the footprint is mostly off its lot(s), so the datasets disagree
+                return vec![];
+            }
+            join.matches
+                .into_iter()
+                .map(|parcel_match| BuildingPart {
+                    parcel: parcel_match.parcel,
+                    building_part_ft: to_ft(&parcel_match.building_part),
+                    height: record.hgt_maxcm / 2.54 / 12.0,
+                })
+                .collect()
+        })
+        .collect();
+    join_stats.log_summary();
+    let mut parts_by_parcel: HashMap<&str, Vec<&BuildingPart>> = HashMap::new();
+    for building_part in &building_parts {
+        parts_by_parcel
+            .entry(&*building_part.parcel.value.blklot)
+            .or_default()
+            .push(building_part);
+    }
+
+    info!("Checking parcels against their zoning");
+    let mut rows: Vec<(&PolygonWrapper<LandUseRecord>, &ZoningCode, OutputRow)> = parcels
+        .iter()
+        .par_bridge()
+        .filter(|parcel| !parcel.value.landuse.is_right_of_way())
+        .filter_map(|parcel| {
+            let zoning = get_zoning(&zoning_districts, &parcel.multi_polygon)
+                .map(|zoning_district| &zoning_district.zoning_sim)?;
+            let height_bulk = get_height_limit(&height_bulk_districts, &parcel.multi_polygon)
+                .map(|height_bulk_district| &height_bulk_district.height);
+            let parts: &[&BuildingPart] = parts_by_parcel
+                .get(&*parcel.value.blklot)
+                .map(|parts| &parts[..])
+                .unwrap_or(&[]);
+            let (rear_yard_depth, lot_depth) = if parts.is_empty() {
+                (None, None)
+            } else {
+                let sides = get_rear_side(&parcels, parcel);
+                let rear_yard_depth = parts
+                    .iter()
+                    .filter_map(|part| rear_yard_depth(&side_setbacks(&sides, &part.building_part_ft)))
+                    .fold(None, |min: Option<f64>, depth| Some(min.map_or(depth, |min| min.min(depth))));
+                (rear_yard_depth, lot_depth(&sides))
+            };
+            let existing = ExistingParcel {
+                units: parcel.value.resunits,
+                lot_area: parcel.value.shape_area,
+                building_area: parts
+                    .iter()
+                    .map(|part| part.building_part_ft.0.iter().map(|polygon| polygon.area().abs()).sum::<f64>())
+                    .sum(),
+                rear_yard_depth,
+                lot_depth,
+                height: parts
+                    .iter()
+                    .map(|part| part.height)
+                    .fold(None, |max: Option<f64>, height| Some(max.map_or(height, |max| max.max(height)))),
+            };
+            let violations = check_parcel(&existing, zoning, height_bulk, &options.tolerances);
+            let row = OutputRow {
+                blklot: parcel.value.blklot.clone(),
+                address: parcel.value.address(),
+                zoning: zoning.to_string(),
+                height_district: height_bulk.map(|height_bulk| height_bulk.label.clone()),
+                units: existing.units,
+                lot_area: existing.lot_area,
+                lot_coverage: if existing.lot_area > 0.0 { existing.building_area / existing.lot_area } else { 0.0 },
+                rear_yard_depth: existing.rear_yard_depth,
+                lot_depth: existing.lot_depth,
+                height: existing.height,
+                reasons: violations.iter().map(Violation::to_string).collect::<Vec<_>>().join("; "),
+                violations,
+            };
+            Some((parcel, zoning, row))
+        })
+        .collect();
+    rows.sort_by(|(_, _, a), (_, _, b)| a.blklot.cmp(&b.blklot));
+
+    let summaries = summarize_by_district(
+        rows.iter().map(|(_, zoning, row)| (*zoning, row.units, &row.violations[..]))
+    );
+    for summary in summaries.values() {
+        if summary.nonconforming_parcels == 0 {
+            continue;
+        }
+        info!(
+            "{}: {}/{} parcels nonconforming ({}/{} units); density: {}, lot coverage: {}, rear yard: {}, height: {}",
+            summary.zoning,
+            summary.nonconforming_parcels,
+            summary.parcels,
+            summary.nonconforming_units,
+            summary.units,
+            summary.density_violations,
+            summary.lot_coverage_violations,
+            summary.rear_yard_violations,
+            summary.height_violations
+        );
+    }
+    if let Some(summary_path) = options.summary_path {
+        info!("Writing {}", summary_path.display());
+        let mut writer = csv::Writer::from_path(summary_path)?;
+        for summary in summaries.values() {
+            writer.serialize(summary)?;
+        }
+        writer.flush()?;
+    }
+    if let Some(geojson_path) = options.geojson_path {
+        info!("Writing {}", geojson_path.display());
+        let features = rows
+            .iter()
+            .filter(|(_, _, row)| !row.violations.is_empty())
+            .map(|(parcel, _, row)| -> Result<Feature, serde_json::Error> {
+                let properties = match serde_json::to_value(row)? {
+                    serde_json::Value::Object(map) => map,
+                    _ => panic!("object should have turned into json object"),
+                };
+                Ok(Feature {
+                    bbox: None,
+                    geometry: Some(multipolygon_to_geojson(&parcel.multi_polygon)),
+                    id: None,
+                    properties: Some(properties),
+                    foreign_members: None,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let geojson = FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        serde_json::to_writer(File::create(geojson_path)?, &geojson)?;
+    }
+    Ok(())
+}
+
+pub const NAME: &str = "nonconforming";
+const MAIN_COMMAND: &str = "report";
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Existing buildings that exceed what their zoning district allows")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name(MAIN_COMMAND)
+            .arg(Arg::with_name("parcel-join")
+                .long("parcel-join")
+                .help("How to assign footprints without a usable mblr key to LandUse parcels: the parcel containing the footprint centroid, the parcel with the largest overlap, or split the footprint across all parcels it overlaps")
+                .takes_value(true)
+                .possible_values(&["centroid", "largest-overlap", "split"])
+                .default_value("split")
+            )
+            .arg(Arg::with_name("min-overlap-confidence")
+                .long("min-overlap-confidence")
+                .help("Skip footprints that have less than this fraction (0-1) of their area on their assigned parcel(s)")
+                .takes_value(true)
+                .default_value("0.5")
+            )
+            .arg(Arg::with_name("height-tolerance")
+                .long("height-tolerance")
+                .help("Feet that a building may exceed its height limit, to allow for parapets and penthouses that are exempt from the limit")
+                .takes_value(true)
+                .default_value("16")
+            )
+            .arg(Arg::with_name("lot-coverage-tolerance")
+                .long("lot-coverage-tolerance")
+                .help("Fraction (0-1) of the lot area that buildings may exceed the maximum lot coverage by")
+                .takes_value(true)
+                .default_value("0.05")
+            )
+            .arg(Arg::with_name("rear-yard-tolerance")
+                .long("rear-yard-tolerance")
+                .help("Feet that a rear yard may be shallower than required")
+                .takes_value(true)
+                .default_value("2")
+            )
+            .arg(output_arg("summary", "csv file output, one row per zoning district"))
+            .arg(output_arg("geojson", "geojson file output, one feature per nonconforming parcel"))
+            .about("List parcels whose units, lot coverage, rear yard or height exceed their zoning")
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches(MAIN_COMMAND) {
+        let options = Options {
+            parcel_join_mode: value_t!(matches.value_of("parcel-join"), ParcelJoinMode)
+                .expect("Expected value for parcel-join"),
+            min_overlap_confidence: value_t!(matches.value_of("min-overlap-confidence"), f64)
+                .expect("Expected value for min-overlap-confidence"),
+            tolerances: Tolerances {
+                height: value_t!(matches.value_of("height-tolerance"), f64)
+                    .expect("Expected value for height-tolerance"),
+                lot_coverage: value_t!(matches.value_of("lot-coverage-tolerance"), f64)
+                    .expect("Expected value for lot-coverage-tolerance"),
+                rear_yard: value_t!(matches.value_of("rear-yard-tolerance"), f64)
+                    .expect("Expected value for rear-yard-tolerance"),
+            },
+            summary_path: matches.value_of_os("summary").map(Path::new),
+            geojson_path: matches.value_of_os("geojson").map(Path::new),
+        };
+        nonconforming(
+            &dataset_path(matches, Dataset::LandUse)?,
+            &dataset_path(matches, Dataset::ZoningDistricts)?,
+            &dataset_path(matches, Dataset::HeightBulk)?,
+            &dataset_path(matches, Dataset::Footprints)?,
+            &options,
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
@@ -0,0 +1,392 @@
+//! Residential expansions that would probably be prohibited by the Peskin ordinance (Board File 181216)
+
+use clap::App;
+use clap::AppSettings;
+use clap::Arg;
+use clap::ArgMatches;
+use clap::SubCommand;
+use geo::algorithm::bounding_rect::BoundingRect;
+use geo::algorithm::centroid::Centroid;
+use geo::algorithm::contains::Contains;
+use geo::algorithm::euclidean_distance::EuclideanDistance;
+use geo::algorithm::map_coords::MapCoords;
+use geo::algorithm::closest_point::ClosestPoint;
+use geo::prelude::Area;
+use geo::Geometry;
+use geo::MultiPolygon;
+use geo::Point;
+use geo::Closest;
+use crate::sflanduse::LandUseRecord;
+use crate::sfplanningacela::PPTSRecord;
+use proj::Proj;
+use rstar::RTreeObject;
+use rstar::AABB;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+use wkt::Wkt;
+use crate::polygon_wrapper::parse_wkt_to_multipolygon;
+use crate::sfzoningdistricts::{ZoningCode, ZoningDistrict, get_zoning};
+use crate::spatial_layer::SpatialLayer;
+use rstar::PointDistance;
+use std::io::Read;
+use crate::geo_util::default_projection;
+use crate::cli::{dataset_path, output_arg, output_file};
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowErrorHandler, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+
+fn get_neighboring_parcels<'a>(
+    parcels: &'a SpatialLayer<LandUseRecord>,
+    shape: &MultiPolygon<f64>,
+    deg_to_ft_proj: &Proj,
+    radius_ft: f64,
+) -> Result<Vec<&'a LandUseRecord>, Box<dyn Error + Send + Sync + 'static>> {
+    let centroid: Point<f64> = shape
+        .centroid()
+        .expect("multipolygon should have at least one point");
+    let centroid_ft = deg_to_ft_proj
+        .project(geo_types::Point::new(centroid.0.x, centroid.0.y), false)
+        .map(|proj_point| Point::new(proj_point.0.x, proj_point.0.y))
+        .expect("Failed to project LandUse parcel");
+    let p1_ft = centroid_ft - Point::from((radius_ft, radius_ft));
+    let p2_ft = centroid_ft + Point::from((radius_ft, radius_ft));
+    let p1_deg = deg_to_ft_proj
+        .project(geo_types::Point::new(p1_ft.0.x, p1_ft.0.y), true)
+        .map(|proj_point| Point::new(proj_point.0.x, proj_point.0.y))
+        .expect("Failed to reverse project LandUse parcel");
+    let p2_deg = deg_to_ft_proj
+        .project(geo_types::Point::new(p2_ft.0.x, p2_ft.0.y), true)
+        .map(|proj_point| Point::new(proj_point.0.x, proj_point.0.y))
+        .expect("Failed to reverse project LandUse parcel");
+    let bbox = AABB::from_corners([p1_deg.0.x, p1_deg.0.y], [p2_deg.0.x, p2_deg.0.y]);
+    let mut neighboring_parcels = vec![];
+    for parcel_wrapper in parcels.in_envelope(&bbox) {
+        let other_centroid = parcel_wrapper
+            .multi_polygon
+            .centroid()
+            .expect("PPTS multipolygon should have at least one point");
+        let other_centroid_ft = deg_to_ft_proj
+            .project(
+                geo_types::Point::new(other_centroid.0.x, other_centroid.0.y),
+                false,
+            )
+            .map(|proj_point| Point::new(proj_point.0.x, proj_point.0.y))
+            .expect("Failed to project PPTS parcel");
+        if shape.contains(&other_centroid) || parcel_wrapper.multi_polygon.contains(&centroid) {
+            // skip self parcel
+            continue;
+        } else if centroid_ft.euclidean_distance(&other_centroid_ft) < radius_ft {
+            neighboring_parcels.push(&parcel_wrapper.value);
+        }
+    }
+    Ok(neighboring_parcels)
+}
+
+/// FAR above which an expansion of a building with `housing_units` units is a “major expansion”
+/// under the proposed Sec. 317, or None if the district has no such limit
+fn major_expansion_far(zoning_district: &ZoningCode, housing_units: i64) -> Option<f64> {
+    match zoning_district {
+        ZoningCode::Rh1D => Some(0.5),
+        ZoningCode::Rh1 => Some(0.6),
+        ZoningCode::Rh2 if housing_units == 1 => Some(0.6),
+        ZoningCode::Rh2 => Some(1.2),
+        ZoningCode::Rh3 if housing_units == 1 => Some(0.6),
+        ZoningCode::Rh3 if housing_units == 2 => Some(1.2),
+        ZoningCode::Rh3 => Some(1.8),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct OutputRow {
+    address: String,
+    zoning_district: String,
+    date: String,
+    num_neighbor_units: usize,
+    mean_unit_size: f64,
+    neighbor_mean_unit_size: f64,
+    proposed_far: f64,
+    building_sqft_exist: f64,
+    building_sqft_prop: f64,
+    is_demolition: bool,
+    is_major_expansion: bool,
+    market_rate_units_exist: i64,
+    market_rate_units_prop: i64,
+    affordable_units_exist: i64,
+    affordable_units_prop: i64,
+    is_prohibited: bool,
+}
+
+fn expansions(
+    mut planning_rdr: csv::Reader<File>,
+    land_use_path: &Path,
+    zoning_districts_path: &Path,
+    mut output_write: Option<csv::Writer<File>>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    info!("Expansions");
+    let proj = default_projection();
+
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+
+    info!("Scanning Zoning_Districts to make lookup table of zoning");
+    let zoning_districts = SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?;
+
+
+    info!("Scanning PPTS records of applications");
+    let mut num_prohibited_expansions = 0;
+    let mut num_ok_expansions = 0;
+    let mut num_prohibited_units = 0;
+    let mut num_ok_units = 0;
+    let mut num_prohibited_aff_units = 0;
+    let mut num_ok_aff_units = 0;
+    let planning_errors = RowErrorHandler::new("planning", row_error_policy);
+    for result in planning_errors.records::<_, PPTSRecord>(&mut planning_rdr) {
+        // The iterator yields Result<Row, RowError>, so we check the
+        // error here.
+        let row = result?;
+        let record = &row.record;
+        if !record.record_status.is_approved() {
+            continue;
+        }
+        let is_residential_expansion = record.land_use_residential_exist.unwrap_or(0.0) > 0.0
+            && record.land_use_residential_net.unwrap_or(0.0) > 0.0;
+        if !is_residential_expansion {
+            continue;
+        }
+        let approx_old_bldg_area = record.land_use_rc_exist.unwrap_or(0.0)
+            + record.land_use_residential_exist.unwrap_or(0.0)
+            + record.land_use_cie_exist.unwrap_or(0.0)
+            + record.land_use_pdr_exist.unwrap_or(0.0)
+            + record.land_use_office_exist.unwrap_or(0.0)
+            + record.land_use_medical_exist.unwrap_or(0.0)
+            + record.land_use_visitor_exist.unwrap_or(0.0)
+            + record.land_use_parking_spaces_exist.unwrap_or(0.0)
+            + 0.0;
+        let approx_new_bldg_area = record.land_use_rc_prop.unwrap_or(0.0)
+            + record.land_use_residential_prop.unwrap_or(0.0)
+            + record.land_use_cie_prop.unwrap_or(0.0)
+            + record.land_use_pdr_prop.unwrap_or(0.0)
+            + record.land_use_office_prop.unwrap_or(0.0)
+            + record.land_use_medical_prop.unwrap_or(0.0)
+            + record.land_use_visitor_prop.unwrap_or(0.0)
+            + record.land_use_parking_spaces_prop.unwrap_or(0.0)
+            + 0.0;
+        let approx_net_bldg_area = approx_new_bldg_area - approx_old_bldg_area;
+        let is_building_expansion = approx_net_bldg_area > 0.0;
+        let is_demolition = record.demolition;
+        if !is_building_expansion && !is_demolition {
+            continue;
+        }
+        let shape: MultiPolygon<f64> = match parse_wkt_to_multipolygon(&record.the_geom) {
+            Ok(shape) => shape,
+            Err(err) => {
+                planning_errors.reject_row(&row, "geometry", &err.to_string())?;
+                continue;
+            }
+        };
+        let shape_ft = shape.map_coords(|&(lat, lon)| {
+            // convert to the old version of geo_types used by proj, and then convert back
+            // to the new re-exported one used by geo
+            let point = proj
+                .project(geo_types::Point::new(lat, lon), false)
+                .unwrap_or_else(|err| {
+                    panic!(format!("Projection failed on ({} {}): {}", lat, lon, err));
+                });
+            (point.x(), point.y())
+        });
+        let parcel_area = shape_ft.area().abs(); // note: area() is signed area
+        let far = approx_new_bldg_area / parcel_area;
+
+        let neighbors = get_neighboring_parcels(&parcels, &shape, &proj, 300f64)?;
+        let (num_neighbor_units, neighbor_bldgsqft) = neighbors
+            .iter()
+            .map(|land_use_record| {
+                (land_use_record.resunits, land_use_record.bldgsqft)
+            })
+            .fold::<(usize, usize), _>((0usize, 0usize), |acc, item| {
+                (acc.0 + item.0, acc.1 + item.1)
+            })
+            ;
+        let neighbor_mean_unit_size: f64 = if num_neighbor_units > 0 {
+            neighbor_bldgsqft as f64 / num_neighbor_units as f64
+        } else {
+            std::f64::INFINITY
+        };
+
+        let market_rate_units_exist = record.prj_feature_market_rate_exist.unwrap_or(0.0) as i64;
+        let affordable_units_exist = record.prj_feature_affordable_exist.unwrap_or(0.0) as i64;
+        let market_rate_units_prop = record.prj_feature_market_rate_prop.unwrap_or(0.0) as i64;
+        let affordable_units_prop = record.prj_feature_affordable_prop.unwrap_or(0.0) as i64;
+        let num_aff_units_change = affordable_units_prop - affordable_units_exist;
+        let housing_units_prop = market_rate_units_prop + affordable_units_prop;
+        let num_units_change =
+            market_rate_units_prop - market_rate_units_exist + num_aff_units_change;
+        let zoning_district = get_zoning(&zoning_districts, &shape)
+            .map(|zoning_district| &zoning_district.zoning_sim);
+        let adus = record.residential_adu_studio_prop.unwrap_or_default() +
+            record.residential_adu_1br_prop.unwrap_or_default() +
+            record.residential_adu_2br_prop.unwrap_or_default() +
+            record.residential_adu_3br_prop.unwrap_or_default();
+
+        let major_expansion_threshold_pct = 10.0;
+        let building_expand_pct = approx_net_bldg_area / approx_old_bldg_area * 100.0;
+        let is_major_expansion = building_expand_pct >= major_expansion_threshold_pct &&
+            zoning_district
+                .and_then(|zoning_district| major_expansion_far(zoning_district, housing_units_prop))
+                .map(|max_far| far > max_far)
+                .unwrap_or(false) &&
+            adus == 0
+        ;
+        let mean_unit_size = if housing_units_prop > 0 {approx_new_bldg_area / housing_units_prop as f64} else {0.0};
+        let is_prohibited = mean_unit_size > f64::min(neighbor_mean_unit_size, 1200.0)
+            && (is_major_expansion && housing_units_prop >= 2 || is_demolition);
+        if is_prohibited {
+            num_prohibited_expansions += 1;
+            num_prohibited_units += num_units_change;
+            num_prohibited_aff_units += num_aff_units_change;
+        } else {
+            num_ok_expansions += 1;
+            num_ok_units += num_units_change;
+            num_ok_aff_units += num_aff_units_change;
+        }
+        let o = OutputRow {
+            address: record.address.clone(),
+            zoning_district: zoning_district.map(ZoningCode::to_string).unwrap_or_default(),
+            date: record.date_opened.date().to_string(),
+            num_neighbor_units,
+            mean_unit_size,
+            neighbor_mean_unit_size,
+            proposed_far: far,
+            building_sqft_exist: approx_old_bldg_area,
+            building_sqft_prop: approx_new_bldg_area,
+            is_demolition,
+            is_major_expansion,
+            market_rate_units_exist,
+            market_rate_units_prop,
+            affordable_units_exist,
+            affordable_units_prop,
+            is_prohibited,
+        };
+        print_row(&o);
+        if let Some(output_write) = output_write.as_mut() {
+            output_write.serialize(o)?;
+        }
+    }
+    planning_errors.log_summary();
+    let frac =
+        num_prohibited_expansions as f64 / (num_ok_expansions + num_prohibited_expansions) as f64;
+    info!(
+        "ok expansions: {}; prohibited expansions: {} ({}%)",
+        num_ok_expansions,
+        num_prohibited_expansions,
+        (frac * 100.0) as i32
+    );
+    let net_units_frac = num_prohibited_units as f64 / (num_ok_units + num_prohibited_units) as f64;
+    info!(
+        "ok net units: {}; prohibited net units: {} ({}%)",
+        num_ok_units,
+        num_prohibited_units,
+        (net_units_frac * 100.0) as i32
+    );
+    let net_aff_units_frac =
+        num_prohibited_aff_units as f64 / (num_ok_aff_units + num_prohibited_aff_units) as f64;
+    info!(
+        "ok net “affordable” units: {}; prohibited net units: {} ({}%)",
+        num_ok_aff_units,
+        num_prohibited_aff_units,
+        (net_aff_units_frac * 100.0) as i32
+    );
+    Ok(())
+}
+fn print_row(o: &OutputRow) {
+    let prohibited_msg = if o.is_prohibited {"Prohibited"}
+    else if o.is_major_expansion {"Conditional"}
+    else {"Probably OK"};
+    let project_type = if o.is_demolition {"demolition"}
+    else if o.is_major_expansion {"major expansion"}
+    else {"other"};
+    println!(
+        "{prohibited_msg} {project_type}: {date} address: {address}, zone {zoning_district}, far {proposed_far:.02}, unit size: {mean_unit_size:.02}, neighbor mean size: {neighbor_mean_unit_size:.02} ({num_neighbor_units} units), bldg growth {approx_net_bldg_area:.0}sqft ({building_expand_pct:.0}%), mktrateunit: {market_rate_units_exist}→{market_rate_units_prop}, affunit:{affordable_units_exist}→{affordable_units_prop}",
+        prohibited_msg = prohibited_msg,
+        project_type = project_type,
+        date = o.date,
+        address = o.address,
+        zoning_district = o.zoning_district,
+        proposed_far = o.proposed_far,
+        mean_unit_size = o.mean_unit_size,
+        neighbor_mean_unit_size = o.neighbor_mean_unit_size,
+        num_neighbor_units = o.num_neighbor_units,
+        approx_net_bldg_area = o.building_sqft_prop - o.building_sqft_exist,
+        building_expand_pct = (o.building_sqft_prop - o.building_sqft_exist) / o.building_sqft_exist * 100.0,
+        market_rate_units_exist = o.market_rate_units_exist,
+        market_rate_units_prop = o.market_rate_units_prop,
+        affordable_units_exist = o.affordable_units_exist,
+        affordable_units_prop = o.affordable_units_prop,
+    );
+}
+
+pub const NAME: &str = "peskinexpansionsimpact";
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Show numbers of residential expansions that are probably prohibited by proposal")
+        .author("Yonathan.")
+        .after_help("Print projects most likely affected by Peskin ordinance (Board File 181216). Note: everything is output using the logger, so you should set RUST_LOG=parcelscan=info to see the output.")
+        .subcommand(SubCommand::with_name("expansions")
+            .arg(output_arg("out-projects", "csv file output"))
+            .about("Show information about expansions")
+        )
+        .subcommand(SubCommand::with_name("reprint")
+            .arg(Arg::with_name("projects")
+                .long("projects")
+                .help("csv file input, which was output by expansions")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("expansions") {
+        let planning = dataset_path(matches, Dataset::Ppts)?;
+        let zoning_districts = dataset_path(matches, Dataset::ZoningDistricts)?;
+        let land_use_path = dataset_path(matches, Dataset::LandUse)?;
+        info!(
+            "Opening {} and {}",
+            planning.display(),
+            land_use_path.display()
+        );
+        let planning_file = File::open(planning)?;
+        let planning_rdr = csv::Reader::from_reader(planning_file);
+        let out_projects_writer_opt = output_file(matches, "out-projects")?.map(csv::Writer::from_writer);
+        expansions(
+            planning_rdr,
+            &land_use_path,
+            &zoning_districts,
+            out_projects_writer_opt,
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        ).map_err(|err| err as Box<dyn Error>)?;
+    } else if let Some(matches) = matches.subcommand_matches("reprint") {
+        let projects_path = matches.value_of_os("projects").expect("required arg should exist");
+        let projects_file: Box<dyn Read> = if projects_path == "-" {
+            Box::new(std::io::stdin())
+        } else {
+            Box::new(File::open(projects_path)?)
+        };
+        let mut projects_rdr = csv::Reader::from_reader(projects_file);
+        for result in projects_rdr.deserialize::<OutputRow>() {
+            let o = result?;
+            print_row(&o);
+        }
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! PPTS records grouped into projects by their parent and child records
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use crate::approval_times::Percentiles;
+use crate::cli::{dataset_path, output_arg, output_file};
+use crate::csv_loader::{row_error_policy, RowErrorHandler, RowErrorPolicy};
+use crate::manifest::Dataset;
+use crate::sfbuildingpermits::{BuildingPermitRecord, BuildingPermits, ProjectPermits, Stage};
+use crate::sfplanningacela::{PPTSRecord, Project, ProjectGraph};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+pub const NAME: &str = "projects";
+
+#[derive(Clone, Debug, Serialize)]
+struct OutputRow {
+    record_id: String,
+    address: String,
+    record_status: String,
+    date_opened: String,
+    date_approved: Option<String>,
+    units_prop: f64,
+    net_units: f64,
+    affordable_units_prop: f64,
+    /// record IDs of all entitlements, root first
+    records: String,
+    description: String,
+}
+
+impl From<&Project> for OutputRow {
+    fn from(project: &Project) -> Self {
+        OutputRow {
+            record_id: project.record_id.clone(),
+            address: project.address.clone(),
+            record_status: project.record_status.to_string(),
+            date_opened: project.date_opened.format("%Y-%m-%d").to_string(),
+            date_approved: project.date_approved.map(|d| d.format("%Y-%m-%d").to_string()),
+            units_prop: project.units_prop,
+            net_units: project.net_units,
+            affordable_units_prop: project.affordable_units_prop,
+            records: project
+                .entitlements
+                .iter()
+                .map(|entitlement| entitlement.record_id.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            description: project.description.clone(),
+        }
+    }
+}
+
+pub fn load_project_graph(planning: &Path, row_error_policy: &RowErrorPolicy) -> Result<ProjectGraph, Box<dyn Error>> {
+    info!("Opening acela: {acela}", acela = planning.display());
+    let mut planning_rdr = csv::Reader::from_path(planning)?;
+    let planning_errors = RowErrorHandler::new("planning", row_error_policy);
+    let records = planning_errors
+        .records::<_, PPTSRecord>(&mut planning_rdr)
+        .map(|result| result.map(|row| row.record))
+        .collect::<Result<Vec<_>, _>>()?;
+    planning_errors.log_summary();
+    Ok(ProjectGraph::new(records))
+}
+
+pub fn load_building_permits(path: &Path, row_error_policy: &RowErrorPolicy) -> Result<BuildingPermits, Box<dyn Error>> {
+    info!("Opening building permits: {}", path.display());
+    let mut rdr = csv::Reader::from_path(path)?;
+    let errors = RowErrorHandler::new("building_permits", row_error_policy);
+    let records = errors
+        .records::<_, BuildingPermitRecord>(&mut rdr)
+        .map(|result| result.map(|row| row.record))
+        .collect::<Result<Vec<_>, _>>()?;
+    errors.log_summary();
+    Ok(BuildingPermits::new(records))
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct PermitsRow {
+    record_id: String,
+    address: String,
+    units_prop: f64,
+    date_approved: Option<String>,
+    stage: &'static str,
+    permits: String,
+    missing_permits: String,
+    first_issued: Option<String>,
+    last_completed: Option<String>,
+    days_to_issued: Option<i64>,
+    days_to_completed: Option<i64>,
+    permit_proposed_units: Option<f64>,
+}
+
+/// Follow each approved project to its building permits, and summarize how many got built
+fn project_permits(
+    graph: &ProjectGraph,
+    permits: &BuildingPermits,
+    min_units: f64,
+    mut output_write: Option<csv::Writer<File>>,
+) -> Result<(), Box<dyn Error>> {
+    let format_date = |date: &chrono::DateTime<chrono::Utc>| date.format("%Y-%m-%d").to_string();
+    let mut num_by_stage: BTreeMap<Stage, usize> = BTreeMap::new();
+    let mut days_to_issued = vec![];
+    let mut days_to_completed = vec![];
+    for project in graph.projects() {
+        if project.date_approved.is_none() || project.units_prop < min_units {
+            continue;
+        }
+        let project_permits = ProjectPermits::new(&project, permits);
+        *num_by_stage.entry(project_permits.stage).or_insert(0) += 1;
+        days_to_issued.extend(project_permits.days_to_issued);
+        days_to_completed.extend(project_permits.days_to_completed);
+        let o = PermitsRow {
+            record_id: project.record_id.clone(),
+            address: project.address.clone(),
+            units_prop: project.units_prop,
+            date_approved: project.date_approved.as_ref().map(format_date),
+            stage: project_permits.stage.as_str(),
+            permits: project_permits
+                .permits
+                .iter()
+                .map(|permit| permit.permit_number.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            missing_permits: project_permits.missing.join(","),
+            first_issued: project_permits.first_issued.as_ref().map(format_date),
+            last_completed: project_permits.last_completed.as_ref().map(format_date),
+            days_to_issued: project_permits.days_to_issued,
+            days_to_completed: project_permits.days_to_completed,
+            permit_proposed_units: project_permits.proposed_units,
+        };
+        println!(
+            "{record_id}: {stage}, approved: {date_approved}, units: {units_prop}, permits: {permits}, address: {address}",
+            record_id = o.record_id,
+            stage = o.stage,
+            date_approved = o.date_approved.as_deref().unwrap_or("none"),
+            units_prop = o.units_prop,
+            permits = o.permits,
+            address = o.address,
+        );
+        if let Some(output_write) = output_write.as_mut() {
+            output_write.serialize(o)?;
+        }
+    }
+    let num_projects: usize = num_by_stage.values().sum();
+    println!();
+    println!("{} approved projects", num_projects);
+    for stage in Stage::ALL.iter() {
+        let num = num_by_stage.get(stage).cloned().unwrap_or(0);
+        println!(
+            "    {:<10} {} ({:.0}%)",
+            stage.as_str(),
+            num,
+            num as f64 / num_projects.max(1) as f64 * 100.0
+        );
+    }
+    if let Some(percentiles) = Percentiles::new(&days_to_issued) {
+        println!("days from approval to first issued permit: {}", percentiles);
+    }
+    if let Some(percentiles) = Percentiles::new(&days_to_completed) {
+        println!("days from first issued permit to completion: {}", percentiles);
+    }
+    Ok(())
+}
+
+fn print_tree(graph: &ProjectGraph, record_id: &str) -> Result<(), Box<dyn Error>> {
+    if graph.get(record_id).is_none() {
+        return Err(format!("no PPTS record {}", record_id).into());
+    }
+    let root = graph.root(record_id);
+    for (depth, record) in graph.tree(root) {
+        println!(
+            "{indent}{record_id} {category} {status} opened: {date_opened}, closed: {date_closed}, address: {address}",
+            indent = "  ".repeat(depth),
+            record_id = record.record_id,
+            category = record.record_type_category,
+            status = record.record_status,
+            date_opened = record.date_opened.format("%Y-%m-%d"),
+            date_closed = record.date_closed.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "none".to_string()),
+            address = record.address,
+        );
+    }
+    if let Some(project) = graph.project(root) {
+        println!(
+            "project {}: status: {}, approved: {}, units: {}, net units: {}, affordable units: {}",
+            project.record_id,
+            project.record_status,
+            project.date_approved.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(|| "none".to_string()),
+            project.units_prop,
+            project.net_units,
+            project.affordable_units_prop,
+        );
+    }
+    Ok(())
+}
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Group PPTS records into projects")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name("tree")
+            .about("Print the project tree that contains a record")
+            .arg(Arg::with_name("record-id")
+                .help("PPTS record ID, e.g. 2018-015340ENV")
+                .required(true)
+                .takes_value(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("list")
+            .about("One row per project, with the unit counts and approval dates of all of its records")
+            .arg(output_arg("out-projects", "csv file output"))
+        )
+        .subcommand(SubCommand::with_name("permits")
+            .about("Follow approved projects to their DBI building permits to see how many were permitted and completed")
+            .arg(Arg::with_name("min-units")
+                .long("min-units")
+                .help("Only projects that propose at least this many units")
+                .takes_value(true)
+                .default_value("0")
+            )
+            .arg(output_arg("out-projects", "csv file output"))
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches("tree") {
+        let graph = load_project_graph(&dataset_path(matches, Dataset::Ppts)?, &row_error_policy(matches))?;
+        let record_id = matches.value_of("record-id").expect("required arg should exist");
+        print_tree(&graph, record_id)?;
+    } else if let Some(matches) = matches.subcommand_matches("list") {
+        let graph = load_project_graph(&dataset_path(matches, Dataset::Ppts)?, &row_error_policy(matches))?;
+        let mut output_write = output_file(matches, "out-projects")?.map(csv::Writer::from_writer);
+        let projects = graph.projects();
+        info!("{} records in {} projects", graph.len(), projects.len());
+        for project in &projects {
+            let o = OutputRow::from(project);
+            println!(
+                "{record_id}: status: {record_status}, opened: {date_opened}, approved: {date_approved}, units: {units_prop}, net units: {net_units}, records: {records}, address: {address}",
+                record_id = o.record_id,
+                record_status = o.record_status,
+                date_opened = o.date_opened,
+                date_approved = o.date_approved.as_deref().unwrap_or("none"),
+                units_prop = o.units_prop,
+                net_units = o.net_units,
+                records = o.records,
+                address = o.address,
+            );
+            if let Some(output_write) = output_write.as_mut() {
+                output_write.serialize(o)?;
+            }
+        }
+    } else if let Some(matches) = matches.subcommand_matches("permits") {
+        let row_error_policy = row_error_policy(matches);
+        let graph = load_project_graph(&dataset_path(matches, Dataset::Ppts)?, &row_error_policy)?;
+        let permits = load_building_permits(&dataset_path(matches, Dataset::BuildingPermits)?, &row_error_policy)?;
+        info!("{} building permits", permits.len());
+        let min_units = value_t!(matches.value_of("min-units"), f64).expect("Expected value for min-units");
+        let output_write = output_file(matches, "out-projects")?.map(csv::Writer::from_writer);
+        project_permits(&graph, &permits, min_units, output_write)?;
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
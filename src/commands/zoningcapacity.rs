@@ -0,0 +1,141 @@
+//! Maximum units and floor area that zoning allows on each parcel
+
+use clap::App;
+use clap::AppSettings;
+use clap::ArgMatches;
+use clap::SubCommand;
+use geojson::{Feature, FeatureCollection};
+use crate::cli::{dataset_path, output_arg};
+use crate::manifest::Dataset;
+use crate::csv_loader::{row_error_policy, RowErrorPolicy};
+use crate::dataset_cache::{dataset_cache, DatasetCache};
+use crate::geo_util::multipolygon_to_geojson;
+use crate::polygon_wrapper::PolygonWrapper;
+use crate::sfheightbulk::{get_height_limit, HeightBulkDistrict};
+use crate::sflanduse::LandUseRecord;
+use crate::sfzoningdistricts::{get_zoning, ZoningDistrict};
+use crate::spatial_layer::SpatialLayer;
+use crate::zoning_capacity::{parcel_capacity, ParcelCapacity};
+use rayon::prelude::*;
+use std::error::Error;
+use std::fs::File;
+use std::path::Path;
+
+fn capacity(
+    land_use_path: &Path,
+    zoning_districts_path: &Path,
+    height_bulk_path: &Path,
+    out_path: Option<&Path>,
+    geojson_path: Option<&Path>,
+    row_error_policy: &RowErrorPolicy,
+    cache: &DatasetCache,
+) -> Result<(), Box<dyn Error>> {
+    info!("Scanning LandUse table of all parcels");
+    let parcels = SpatialLayer::<LandUseRecord>::load_cached("land_use", land_use_path, row_error_policy, cache)?;
+
+    info!("Scanning Zoning_Districts to make lookup table of zoning");
+    let zoning_districts = SpatialLayer::<ZoningDistrict>::load_cached("zoning_districts", zoning_districts_path, row_error_policy, cache)?;
+
+    info!("Scanning Height and Bulk Districts");
+    let height_bulk_districts = SpatialLayer::<HeightBulkDistrict>::load_cached("height_bulk_districts", height_bulk_path, row_error_policy, cache)?;
+
+    let mut rows: Vec<(&PolygonWrapper<LandUseRecord>, ParcelCapacity)> = parcels
+        .iter()
+        .par_bridge()
+        .filter(|parcel| !parcel.value.landuse.is_right_of_way())
+        .map(|parcel| {
+            let zoning = get_zoning(&zoning_districts, &parcel.multi_polygon)
+                .map(|zoning_district| &zoning_district.zoning_sim);
+            let height_bulk = get_height_limit(&height_bulk_districts, &parcel.multi_polygon)
+                .map(|height_bulk_district| &height_bulk_district.height);
+            (parcel, parcel_capacity(&parcel.value, zoning, height_bulk))
+        })
+        .collect();
+    rows.sort_by(|(_, a), (_, b)| a.blklot.cmp(&b.blklot));
+
+    let (existing_units, max_units) = rows
+        .iter()
+        .filter_map(|(_, row)| row.max_units.map(|max_units| (row.existing_units, max_units as usize)))
+        .fold((0, 0), |(existing, max), (row_existing, row_max)| (existing + row_existing, max + row_max));
+    let num_no_density_limit = rows.iter().filter(|(_, row)| row.no_density_limit).count();
+    let num_unknown = rows
+        .iter()
+        .filter(|(_, row)| row.max_units.is_none() && !row.no_density_limit)
+        .count();
+    info!(
+        "{} parcels; density-limited parcels have {} units and allow {}; {} parcels have no density limit; {} parcels have unknown zoning",
+        rows.len(),
+        existing_units,
+        max_units,
+        num_no_density_limit,
+        num_unknown
+    );
+
+    if let Some(out_path) = out_path {
+        info!("Writing {}", out_path.display());
+        let mut writer = csv::Writer::from_path(out_path)?;
+        for (_, row) in &rows {
+            writer.serialize(row)?;
+        }
+        writer.flush()?;
+    }
+    if let Some(geojson_path) = geojson_path {
+        info!("Writing {}", geojson_path.display());
+        let features = rows
+            .iter()
+            .map(|(parcel, row)| -> Result<Feature, serde_json::Error> {
+                let properties = match serde_json::to_value(row)? {
+                    serde_json::Value::Object(map) => map,
+                    _ => panic!("object should have turned into json object"),
+                };
+                Ok(Feature {
+                    bbox: None,
+                    geometry: Some(multipolygon_to_geojson(&parcel.multi_polygon)),
+                    id: None,
+                    properties: Some(properties),
+                    foreign_members: None,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let geojson = FeatureCollection {
+            bbox: None,
+            features,
+            foreign_members: None,
+        };
+        serde_json::to_writer(File::create(geojson_path)?, &geojson)?;
+    }
+    Ok(())
+}
+
+pub const NAME: &str = "zoningcapacity";
+const MAIN_COMMAND: &str = "capacity";
+
+pub fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new(NAME)
+        .version("0.0")
+        .about("Maximum units and floor area that zoning allows on each parcel")
+        .author("Yonathan.")
+        .subcommand(SubCommand::with_name(MAIN_COMMAND)
+            .arg(output_arg("out", "csv file output, one row per parcel"))
+            .arg(output_arg("geojson", "geojson file output, one feature per parcel"))
+            .about("Compute the zoning capacity of every parcel")
+        )
+        .setting(AppSettings::SubcommandRequired)
+}
+
+pub fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(matches) = matches.subcommand_matches(MAIN_COMMAND) {
+        capacity(
+            &dataset_path(matches, Dataset::LandUse)?,
+            &dataset_path(matches, Dataset::ZoningDistricts)?,
+            &dataset_path(matches, Dataset::HeightBulk)?,
+            matches.value_of_os("out").map(Path::new),
+            matches.value_of_os("geojson").map(Path::new),
+            &row_error_policy(matches),
+            &dataset_cache(matches),
+        )?;
+    } else {
+        panic!("Should not happen");
+    }
+    Ok(())
+}
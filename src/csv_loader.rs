@@ -0,0 +1,391 @@
+//! Shared reader for the DataSF csv files that decides what to do with rows that fail to parse.
+//!
+//! A bad row can abort the scan, be skipped with a counted warning,
+//! or be copied to a quarantine csv file along with the error and line number.
+
+use clap::{Arg, ArgMatches};
+use csv::{DeserializeErrorKind, ErrorKind, StringRecord};
+use crate::schema::{HeaderDiff, RecordSchema};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use thiserror::Error;
+
+/// Number of rejected rows per error kind that are logged individually before going quiet
+const MAX_WARNINGS_PER_KIND: usize = 10;
+
+pub const ON_ROW_ERROR_ARG: &str = "on-row-error";
+pub const QUARANTINE_DIR_ARG: &str = "quarantine-dir";
+
+#[derive(Clone, Debug)]
+pub enum RowErrorPolicy {
+    /// Stop at the first bad row
+    Fail,
+    /// Log and count bad rows, and keep going
+    Skip,
+    /// Like Skip, but also write the rejected rows to `<dir>/<dataset>_rejects.csv`
+    Quarantine(PathBuf),
+}
+
+#[derive(Error, Debug)]
+pub enum RowError {
+    #[error("{dataset} line {line}: {kind}: {message}")]
+    Rejected {
+        dataset: String,
+        line: u64,
+        kind: String,
+        message: String,
+    },
+    #[error("{dataset}: {diff}")]
+    IncompatibleHeader { dataset: String, diff: HeaderDiff },
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("io error writing quarantine file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// A successfully deserialized row, with enough context to reject it later
+/// (e.g. if its geometry turns out to be bad)
+pub struct Row<T> {
+    pub line: u64,
+    pub raw: StringRecord,
+    pub record: T,
+}
+
+struct RejectState {
+    headers: StringRecord,
+    quarantine: Option<csv::Writer<File>>,
+    rejects: BTreeMap<String, usize>,
+}
+
+/// Applies a `RowErrorPolicy` to the rows of one dataset and counts the rejects.
+/// Rejecting takes `&self` so that rows can be rejected from rayon workers.
+pub struct RowErrorHandler {
+    dataset: String,
+    policy: RowErrorPolicy,
+    state: Mutex<RejectState>,
+}
+
+impl RowErrorHandler {
+    pub fn new(dataset: &str, policy: &RowErrorPolicy) -> Self {
+        RowErrorHandler {
+            dataset: dataset.to_string(),
+            policy: policy.clone(),
+            state: Mutex::new(RejectState {
+                headers: StringRecord::new(),
+                quarantine: None,
+                rejects: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Iterate over the records of `rdr`, passing rows that fail to deserialize to this handler.
+    /// Columns that the file names differently than `T` (see `HeaderDiff`) are renamed first.
+    /// If the file is missing columns that `T` requires, the first item is
+    /// `RowError::IncompatibleHeader` regardless of the policy, since every row would fail.
+    pub fn records<'a, R: Read, T: RecordSchema>(
+        &'a self,
+        rdr: &'a mut csv::Reader<R>,
+    ) -> Records<'a, R, T> {
+        Records {
+            rdr,
+            headers: None,
+            failed: false,
+            handler: self,
+            _phantom_t: PhantomData,
+        }
+    }
+
+    /// Record a row that deserialized but could not be used (e.g. bad geometry).
+    /// Returns Err if the policy is Fail.
+    pub fn reject_row<T>(&self, row: &Row<T>, kind: &str, message: &str) -> Result<(), RowError> {
+        self.reject(&row.raw, row.line, kind, message)
+    }
+
+    /// Record a bad row. Returns Err if the policy is Fail.
+    pub fn reject(
+        &self,
+        raw: &StringRecord,
+        line: u64,
+        kind: &str,
+        message: &str,
+    ) -> Result<(), RowError> {
+        if let RowErrorPolicy::Fail = self.policy {
+            return Err(RowError::Rejected {
+                dataset: self.dataset.clone(),
+                line,
+                kind: kind.to_string(),
+                message: message.to_string(),
+            });
+        }
+        let mut state = self.state.lock().expect("RowErrorHandler lock poisoned");
+        let count = {
+            let count = state.rejects.entry(kind.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        if count <= MAX_WARNINGS_PER_KIND {
+            warn!("Skipping {} line {}: {}: {}", self.dataset, line, kind, message);
+        } else {
+            debug!("Skipping {} line {}: {}: {}", self.dataset, line, kind, message);
+        }
+        if let RowErrorPolicy::Quarantine(ref dir) = self.policy {
+            if state.quarantine.is_none() {
+                std::fs::create_dir_all(dir)?;
+                let path = dir.join(format!("{}_rejects.csv", self.dataset));
+                info!("Writing rejected {} rows to {}", self.dataset, path.display());
+                let mut writer = csv::WriterBuilder::new()
+                    .flexible(true)
+                    .from_path(path)?;
+                let mut header = StringRecord::from(vec!["line", "error_kind", "error"]);
+                header.extend(state.headers.iter());
+                writer.write_record(&header)?;
+                state.quarantine = Some(writer);
+            }
+            let writer = state.quarantine.as_mut().expect("quarantine writer was just created");
+            let line_str = line.to_string();
+            let mut out = StringRecord::from(vec![&*line_str, kind, message]);
+            out.extend(raw.iter());
+            writer.write_record(&out)?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn num_rejects(&self) -> usize {
+        let state = self.state.lock().expect("RowErrorHandler lock poisoned");
+        state.rejects.values().sum()
+    }
+
+    /// Log the number of rejected rows per error kind
+    pub fn log_summary(&self) {
+        let state = self.state.lock().expect("RowErrorHandler lock poisoned");
+        if state.rejects.is_empty() {
+            return;
+        }
+        let total: usize = state.rejects.values().sum();
+        warn!("{}: rejected {} rows", self.dataset, total);
+        for (kind, count) in state.rejects.iter() {
+            warn!("{}:   {}: {}", self.dataset, kind, count);
+        }
+    }
+}
+
+/// Iterator returned by `RowErrorHandler::records`
+pub struct Records<'a, R, T> {
+    rdr: &'a mut csv::Reader<R>,
+    headers: Option<StringRecord>,
+    /// the header was incompatible, so there are no rows
+    failed: bool,
+    handler: &'a RowErrorHandler,
+    _phantom_t: PhantomData<T>,
+}
+
+impl<'a, R: Read, T: RecordSchema> Records<'a, R, T> {
+    fn read_headers(&mut self) -> Result<(), RowError> {
+        if self.headers.is_none() {
+            let headers = self.rdr.headers()?.clone();
+            self.handler.state.lock().expect("RowErrorHandler lock poisoned").headers = headers.clone();
+            let names: Vec<String> = headers.iter().map(String::from).collect();
+            let diff = HeaderDiff::for_record::<T>(&names);
+            if !diff.is_compatible() {
+                return Err(RowError::IncompatibleHeader {
+                    dataset: self.handler.dataset.clone(),
+                    diff,
+                });
+            }
+            diff.log(&self.handler.dataset);
+            self.headers = Some(StringRecord::from(diff.rename_header(&names)));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: Read, T: RecordSchema> Iterator for Records<'a, R, T> {
+    type Item = Result<Row<T>, RowError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        if let Err(err) = self.read_headers() {
+            self.failed = true;
+            return Some(Err(err));
+        }
+        let headers = self.headers.as_ref().expect("headers were just read");
+        loop {
+            let mut raw = StringRecord::new();
+            match self.rdr.read_record(&mut raw) {
+                Ok(false) => return None,
+                Ok(true) => {}
+                Err(err) => {
+                    if let ErrorKind::Io(_) = err.kind() {
+                        return Some(Err(RowError::Csv(err)));
+                    }
+                    // the reader has already moved past the bad record,
+                    // so its own position is only a fallback
+                    let line = raw
+                        .position()
+                        .or_else(|| err.position())
+                        .map(|pos| pos.line())
+                        .unwrap_or_else(|| self.rdr.position().line());
+                    let kind = error_kind_name(&err, headers);
+                    if let Err(err) = self.handler.reject(&raw, line, &kind, &err.to_string()) {
+                        return Some(Err(err));
+                    }
+                    continue;
+                }
+            }
+            let line = raw.position().map(|pos| pos.line()).unwrap_or_else(|| self.rdr.position().line());
+            match raw.deserialize::<T>(Some(headers)) {
+                Ok(record) => return Some(Ok(Row { line, raw, record })),
+                Err(err) => {
+                    let kind = error_kind_name(&err, headers);
+                    if let Err(err) = self.handler.reject(&raw, line, &kind, &err.to_string()) {
+                        return Some(Err(err));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Short description of a csv error that is used to group rejects in the summary,
+/// e.g. "ParseFloat in SHAPE_Area"
+fn error_kind_name(err: &csv::Error, headers: &StringRecord) -> String {
+    match err.kind() {
+        ErrorKind::Deserialize { err, .. } => {
+            let kind = match err.kind() {
+                DeserializeErrorKind::Message(_) => "Message",
+                DeserializeErrorKind::Unsupported(_) => "Unsupported",
+                DeserializeErrorKind::UnexpectedEndOfRow => "UnexpectedEndOfRow",
+                DeserializeErrorKind::InvalidUtf8(_) => "InvalidUtf8",
+                DeserializeErrorKind::ParseBool(_) => "ParseBool",
+                DeserializeErrorKind::ParseInt(_) => "ParseInt",
+                DeserializeErrorKind::ParseFloat(_) => "ParseFloat",
+            };
+            match err.field().and_then(|i| headers.get(i as usize)) {
+                Some(field) => format!("{} in {}", kind, field),
+                None => kind.to_string(),
+            }
+        }
+        ErrorKind::UnequalLengths { .. } => "UnequalLengths".to_string(),
+        ErrorKind::Utf8 { .. } => "Utf8".to_string(),
+        _ => "Other".to_string(),
+    }
+}
+
+/// `--on-row-error` and `--quarantine-dir` arguments shared by all the binaries
+pub fn row_error_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(ON_ROW_ERROR_ARG)
+            .long(ON_ROW_ERROR_ARG)
+            .help("What to do with csv rows that fail to parse: fail, skip (with a warning), or quarantine (write them to --quarantine-dir)")
+            .takes_value(true)
+            .possible_values(&["fail", "skip", "quarantine"])
+            .default_value("skip"),
+        Arg::with_name(QUARANTINE_DIR_ARG)
+            .long(QUARANTINE_DIR_ARG)
+            .help("Directory in which to write <dataset>_rejects.csv files when --on-row-error=quarantine")
+            .takes_value(true)
+            .required_if(ON_ROW_ERROR_ARG, "quarantine"),
+    ]
+}
+
+pub fn row_error_policy(matches: &ArgMatches) -> RowErrorPolicy {
+    match matches.value_of(ON_ROW_ERROR_ARG).unwrap_or("skip") {
+        "fail" => RowErrorPolicy::Fail,
+        "quarantine" => RowErrorPolicy::Quarantine(PathBuf::from(
+            matches
+                .value_of_os(QUARANTINE_DIR_ARG)
+                .expect("quarantine-dir is required with --on-row-error=quarantine"),
+        )),
+        _ => RowErrorPolicy::Skip,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RowErrorHandler, RowErrorPolicy};
+    use crate::schema::RecordSchema;
+    use csv::{Reader, ReaderBuilder, StringRecord};
+
+    #[derive(Debug, Deserialize)]
+    struct TestRecord {
+        name: String,
+        area: f64,
+    }
+    impl RecordSchema for TestRecord {}
+    const TEST_LINES: &str = "name,area
+a,1.5
+b,oops
+c,3
+";
+
+    #[test]
+    fn test_skip() {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let handler = RowErrorHandler::new("test", &RowErrorPolicy::Skip);
+        let rows = handler
+            .records::<_, TestRecord>(&mut rdr)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("skip policy should not fail");
+        assert_eq!(vec!["a", "c"], rows.iter().map(|row| &*row.record.name).collect::<Vec<_>>());
+        assert_eq!(4, rows[1].line);
+        assert_eq!(3.0, rows[1].record.area);
+        assert_eq!(1, handler.num_rejects());
+    }
+
+    #[test]
+    fn test_fail() {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let handler = RowErrorHandler::new("test", &RowErrorPolicy::Fail);
+        let result = handler
+            .records::<_, TestRecord>(&mut rdr)
+            .collect::<Result<Vec<_>, _>>();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_incompatible_header() {
+        let mut rdr = Reader::from_reader("name,size\na,1.5\n".as_bytes());
+        let handler = RowErrorHandler::new("test", &RowErrorPolicy::Skip);
+        let mut records = handler.records::<_, TestRecord>(&mut rdr);
+        let err = records.next().expect("an error").err().expect("area is missing");
+        assert_eq!("test: missing columns area; extra columns size", err.to_string());
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_quarantine() {
+        let dir = std::env::temp_dir().join(format!("parcelscan-quarantine-{}", std::process::id()));
+        let mut rdr = Reader::from_reader("name,area\na,1.5,extra\nb,oops\nc,3\n".as_bytes());
+        let handler = RowErrorHandler::new("test", &RowErrorPolicy::Quarantine(dir.clone()));
+        let rows = handler
+            .records::<_, TestRecord>(&mut rdr)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("quarantine policy should not fail");
+        assert_eq!(vec!["c"], rows.iter().map(|row| &*row.record.name).collect::<Vec<_>>());
+        assert_eq!(2, handler.num_rejects());
+
+        let mut quarantine = ReaderBuilder::new()
+            .flexible(true)
+            .from_path(dir.join("test_rejects.csv"))
+            .expect("quarantine file");
+        let header = quarantine.headers().expect("quarantine header").clone();
+        assert_eq!(StringRecord::from(vec!["line", "error_kind", "error", "name", "area"]), header);
+        let rejects: Vec<StringRecord> = quarantine.records().collect::<Result<_, _>>().expect("quarantine rows");
+        assert_eq!(2, rejects.len());
+        // a row that could not be read, on the first data line
+        assert_eq!(vec!["2", "UnequalLengths"], rejects[0].iter().take(2).collect::<Vec<_>>());
+        assert!(rejects[0][2].contains("found record with 3 fields"), "{:?}", rejects[0]);
+        assert_eq!(vec!["a", "1.5", "extra"], rejects[0].iter().skip(3).collect::<Vec<_>>());
+        // a row that could not be deserialized
+        assert_eq!(vec!["3", "ParseFloat in area"], rejects[1].iter().take(2).collect::<Vec<_>>());
+        assert!(rejects[1][2].contains("invalid float literal"), "{:?}", rejects[1]);
+        assert_eq!(vec!["b", "oops"], rejects[1].iter().skip(3).collect::<Vec<_>>());
+        std::fs::remove_dir_all(&dir).expect("remove quarantine dir");
+    }
+}
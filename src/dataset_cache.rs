@@ -0,0 +1,245 @@
+//! On-disk cache of parsed datasets, so that reruns don't have to reparse all the WKT
+//! and rebuild the rtrees.
+//!
+//! Each dataset is cached in `<cache dir>/<dataset>.bin` along with the path, size and mtime
+//! of the csv file it was built from; the cache is rebuilt when the csv file changes.
+//! Rows that were rejected while building the cache are not reported again on later runs,
+//! so the cache is only read with `--on-row-error=skip`; the other policies reparse the csv file
+//! (to fail on the first bad row or to rewrite the quarantine file) and then update the cache.
+
+use clap::{Arg, ArgMatches};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use thiserror::Error;
+
+pub const CACHE_DIR_ARG: &str = "cache-dir";
+pub const NO_CACHE_ARG: &str = "no-cache";
+pub const REBUILD_CACHE_ARG: &str = "rebuild-cache";
+const DEFAULT_CACHE_DIR: &str = ".parcelscan-cache";
+
+/// Bump this whenever the layout of a cached type changes (e.g. a field is added to a record),
+/// since bincode is not self-describing
+const CACHE_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CacheMode {
+    /// Read the cache if it is fresh; otherwise build and write it
+    Use,
+    /// Neither read nor write the cache
+    Disabled,
+    /// Ignore any existing cache, then build and write it
+    Rebuild,
+}
+
+#[derive(Error, Debug)]
+pub enum CacheError {
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+    #[error("bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+}
+
+/// Written at the start of each cache file to tell whether it is stale
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct CacheKey {
+    version: u32,
+    type_name: String,
+    path: PathBuf,
+    size: u64,
+    mtime: SystemTime,
+}
+
+impl CacheKey {
+    fn new<V>(source: &Path) -> io::Result<Self> {
+        let path = source.canonicalize()?;
+        let metadata = fs::metadata(&path)?;
+        Ok(CacheKey {
+            version: CACHE_VERSION,
+            type_name: std::any::type_name::<V>().to_string(),
+            path,
+            size: metadata.len(),
+            mtime: metadata.modified()?,
+        })
+    }
+}
+
+pub struct DatasetCache {
+    dir: PathBuf,
+    mode: CacheMode,
+}
+
+impl DatasetCache {
+    pub fn new<P: Into<PathBuf>>(dir: P, mode: CacheMode) -> Self {
+        DatasetCache {
+            dir: dir.into(),
+            mode,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        DatasetCache::new(DEFAULT_CACHE_DIR, CacheMode::Disabled)
+    }
+
+    /// The same cache, but existing cache files are ignored as with `CacheMode::Rebuild`
+    pub fn rebuilding(&self) -> DatasetCache {
+        let mode = match self.mode {
+            CacheMode::Disabled => CacheMode::Disabled,
+            _ => CacheMode::Rebuild,
+        };
+        DatasetCache::new(self.dir.clone(), mode)
+    }
+
+    fn cache_path(&self, dataset: &str) -> PathBuf {
+        self.dir.join(format!("{}.bin", dataset))
+    }
+
+    /// Return the cached value for `dataset` if it was built from the current version of
+    /// `source`; otherwise call `build` and cache its result.
+    /// Failing to read or write the cache is logged but is not an error.
+    pub fn load_or_build<V, E, F>(&self, dataset: &str, source: &Path, build: F) -> Result<V, E>
+    where
+        V: Serialize + DeserializeOwned,
+        F: FnOnce() -> Result<V, E>,
+    {
+        if self.mode == CacheMode::Disabled {
+            return build();
+        }
+        let key = match CacheKey::new::<V>(source) {
+            Ok(key) => key,
+            Err(err) => {
+                warn!("Not caching {}: {}", dataset, err);
+                return build();
+            }
+        };
+        let cache_path = self.cache_path(dataset);
+        if self.mode == CacheMode::Use {
+            match read_cache(&cache_path, &key) {
+                Ok(Some(value)) => {
+                    info!("Loaded {} from cache {}", dataset, cache_path.display());
+                    return Ok(value);
+                }
+                Ok(None) => info!("Cache {} is missing or stale", cache_path.display()),
+                Err(err) => warn!("Failed to read cache {}: {}", cache_path.display(), err),
+            }
+        }
+        let value = build()?;
+        info!("Writing {} to cache {}", dataset, cache_path.display());
+        if let Err(err) = write_cache(&cache_path, &key, &value) {
+            warn!("Failed to write cache {}: {}", cache_path.display(), err);
+        }
+        Ok(value)
+    }
+}
+
+fn read_cache<V: DeserializeOwned>(path: &Path, key: &CacheKey) -> Result<Option<V>, CacheError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut reader = BufReader::new(file);
+    let cached_key: CacheKey = bincode::deserialize_from(&mut reader)?;
+    if &cached_key != key {
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize_from(reader)?))
+}
+
+fn write_cache<V: Serialize>(path: &Path, key: &CacheKey, value: &V) -> Result<(), CacheError> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // write to a temporary file first so that an interrupted run doesn't leave a truncated cache
+    let tmp_path = path.with_extension("bin.tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        bincode::serialize_into(&mut writer, key)?;
+        bincode::serialize_into(&mut writer, value)?;
+        writer.flush()?;
+    }
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// `--cache-dir`, `--no-cache` and `--rebuild-cache` arguments shared by the binaries
+pub fn cache_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(CACHE_DIR_ARG)
+            .long(CACHE_DIR_ARG)
+            .help("Directory in which to cache parsed datasets")
+            .takes_value(true)
+            .default_value(DEFAULT_CACHE_DIR),
+        Arg::with_name(NO_CACHE_ARG)
+            .long(NO_CACHE_ARG)
+            .help("Parse the csv files without reading or writing the cache")
+            .conflicts_with(REBUILD_CACHE_ARG),
+        Arg::with_name(REBUILD_CACHE_ARG)
+            .long(REBUILD_CACHE_ARG)
+            .help("Reparse the csv files and overwrite the cache"),
+    ]
+}
+
+pub fn dataset_cache(matches: &ArgMatches) -> DatasetCache {
+    let mode = if matches.is_present(NO_CACHE_ARG) {
+        CacheMode::Disabled
+    } else if matches.is_present(REBUILD_CACHE_ARG) {
+        CacheMode::Rebuild
+    } else {
+        CacheMode::Use
+    };
+    let dir = matches
+        .value_of_os(CACHE_DIR_ARG)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CACHE_DIR));
+    DatasetCache::new(dir, mode)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CacheMode, DatasetCache};
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("parcelscan-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("failed to create temp dir");
+        dir
+    }
+
+    #[test]
+    fn test_load_or_build() -> Result<(), std::io::Error> {
+        let dir = temp_dir("cache");
+        let source = dir.join("source.csv");
+        fs::write(&source, "a\n1\n")?;
+        let num_builds = Cell::new(0);
+        let build = || -> Result<Vec<String>, std::io::Error> {
+            num_builds.set(num_builds.get() + 1);
+            Ok(fs::read_to_string(&source)?.lines().map(String::from).collect())
+        };
+        let cache = DatasetCache::new(dir.join("cache"), CacheMode::Use);
+        assert_eq!(vec!["a", "1"], cache.load_or_build("test", &source, build)?);
+        assert_eq!(vec!["a", "1"], cache.load_or_build("test", &source, build)?);
+        assert_eq!(1, num_builds.get());
+
+        // a different size invalidates the cache
+        fs::write(&source, "a\n12\n")?;
+        assert_eq!(vec!["a", "12"], cache.load_or_build("test", &source, build)?);
+        assert_eq!(2, num_builds.get());
+
+        let rebuild = DatasetCache::new(dir.join("cache"), CacheMode::Rebuild);
+        rebuild.load_or_build("test", &source, build)?;
+        assert_eq!(3, num_builds.get());
+        let disabled = DatasetCache::new(dir.join("cache"), CacheMode::Disabled);
+        disabled.load_or_build("test", &source, build)?;
+        assert_eq!(4, num_builds.get());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
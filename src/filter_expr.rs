@@ -0,0 +1,313 @@
+//! A small expression language for filtering output rows without recompiling, e.g.
+//! `zoning == "RH-1" && resunits >= 4 && yrbuilt < 1930`.
+//!
+//! Identifiers name fields of the row as serialized to JSON (`a.b` looks inside nested objects).
+//! Literals are numbers, double- or single-quoted strings, `true`, `false` and `null`.
+//! Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `&&`, `||`, `!` and parentheses.
+//! A missing field or an empty (`null`) value is only equal to `null`,
+//! so `height < 40` is false for a row without a height.
+
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+#[error("invalid filter at character {position}: {message}")]
+pub struct FilterParseError {
+    pub position: usize,
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FilterExpr {
+    Literal(Value),
+    Field(Vec<String>),
+    Compare(Box<FilterExpr>, CompareOp, Box<FilterExpr>),
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Compare(CompareOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<(usize, Token)>, FilterParseError> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let two: String = chars[i..chars.len().min(i + 2)].iter().collect();
+        let token = match (c, &*two) {
+            (c, _) if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            (_, "==") => { i += 2; Token::Compare(CompareOp::Eq) }
+            (_, "!=") => { i += 2; Token::Compare(CompareOp::Ne) }
+            (_, "<=") => { i += 2; Token::Compare(CompareOp::Le) }
+            (_, ">=") => { i += 2; Token::Compare(CompareOp::Ge) }
+            (_, "&&") => { i += 2; Token::And }
+            (_, "||") => { i += 2; Token::Or }
+            ('<', _) => { i += 1; Token::Compare(CompareOp::Lt) }
+            ('>', _) => { i += 1; Token::Compare(CompareOp::Gt) }
+            ('!', _) => { i += 1; Token::Not }
+            ('(', _) => { i += 1; Token::LParen }
+            (')', _) => { i += 1; Token::RParen }
+            ('"', _) | ('\'', _) => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&x| x == c)
+                    .map(|offset| i + 1 + offset)
+                    .ok_or_else(|| FilterParseError {
+                        position: start,
+                        message: "unterminated string".to_string(),
+                    })?;
+                let string: String = chars[i + 1..end].iter().collect();
+                i = end + 1;
+                Token::Str(string)
+            }
+            (c, _) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let number: String = chars[start..i].iter().collect();
+                Token::Number(number.parse().map_err(|_| FilterParseError {
+                    position: start,
+                    message: format!("invalid number {}", number),
+                })?)
+            }
+            (c, _) if c.is_alphabetic() || c == '_' => {
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                    i += 1;
+                }
+                Token::Ident(chars[start..i].iter().collect())
+            }
+            (c, _) => {
+                return Err(FilterParseError {
+                    position: start,
+                    message: format!("unexpected character {:?}", c),
+                })
+            }
+        };
+        tokens.push((start, token));
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    len: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn error(&self, message: &str) -> FilterParseError {
+        FilterParseError {
+            position: self.tokens.get(self.pos).map(|(position, _)| *position).unwrap_or(self.len),
+            message: message.to_string(),
+        }
+    }
+
+    fn or(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<FilterExpr, FilterParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(FilterExpr::Not(Box::new(self.not()?)));
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let left = self.primary()?;
+        if let Some(&Token::Compare(op)) = self.peek() {
+            self.pos += 1;
+            let right = self.primary()?;
+            return Ok(FilterExpr::Compare(Box::new(left), op, Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn primary(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let token = self.peek().cloned().ok_or_else(|| self.error("unexpected end of filter"))?;
+        let expr = match token {
+            Token::Number(n) => FilterExpr::Literal(Value::from(n)),
+            Token::Str(s) => FilterExpr::Literal(Value::String(s)),
+            Token::Ident(ident) => match &*ident {
+                "true" => FilterExpr::Literal(Value::Bool(true)),
+                "false" => FilterExpr::Literal(Value::Bool(false)),
+                "null" => FilterExpr::Literal(Value::Null),
+                _ => FilterExpr::Field(ident.split('.').map(String::from).collect()),
+            },
+            Token::LParen => {
+                self.pos += 1;
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    return Err(self.error("expected )"));
+                }
+                expr
+            }
+            _ => return Err(self.error("expected a field, literal or (")),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+impl FromStr for FilterExpr {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            len: s.chars().count(),
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected token"));
+        }
+        Ok(expr)
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => false,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+impl FilterExpr {
+    fn eval(&self, row: &Value) -> Value {
+        match self {
+            FilterExpr::Literal(value) => value.clone(),
+            FilterExpr::Field(path) => path
+                .iter()
+                .try_fold(row, |value, key| value.get(key))
+                .cloned()
+                .unwrap_or(Value::Null),
+            FilterExpr::Compare(left, op, right) => {
+                let ordering = compare(&left.eval(row), &right.eval(row));
+                Value::Bool(match op {
+                    CompareOp::Eq => ordering == Some(Ordering::Equal),
+                    CompareOp::Ne => ordering != Some(Ordering::Equal),
+                    CompareOp::Lt => ordering == Some(Ordering::Less),
+                    CompareOp::Le => ordering == Some(Ordering::Less) || ordering == Some(Ordering::Equal),
+                    CompareOp::Gt => ordering == Some(Ordering::Greater),
+                    CompareOp::Ge => ordering == Some(Ordering::Greater) || ordering == Some(Ordering::Equal),
+                })
+            }
+            FilterExpr::And(left, right) => Value::Bool(truthy(&left.eval(row)) && truthy(&right.eval(row))),
+            FilterExpr::Or(left, right) => Value::Bool(truthy(&left.eval(row)) || truthy(&right.eval(row))),
+            FilterExpr::Not(expr) => Value::Bool(!truthy(&expr.eval(row))),
+        }
+    }
+
+    /// Whether the row (a JSON object) passes the filter
+    pub fn matches_value(&self, row: &Value) -> bool {
+        truthy(&self.eval(row))
+    }
+
+    /// Whether the row passes the filter, after serializing it to JSON
+    pub fn matches<T: Serialize>(&self, row: &T) -> Result<bool, serde_json::Error> {
+        Ok(self.matches_value(&serde_json::to_value(row)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FilterExpr, FilterParseError};
+    use serde_json::json;
+
+    #[test]
+    fn test_filter() {
+        let row = json!({
+            "zoning": "RH-1",
+            "resunits": 4,
+            "yrbuilt": 1925,
+            "lot_area": 2500.0,
+            "height": null,
+            "setbacks": {"rear": 12.5},
+        });
+        let matches = |filter: &str| filter.parse::<FilterExpr>().expect("filter should parse").matches_value(&row);
+        assert!(matches("zoning == \"RH-1\" && resunits >= 4 && yrbuilt < 1930"));
+        assert!(!matches("zoning == 'RH-2' || resunits < 4"));
+        assert!(matches("!(lot_area < 2490) && setbacks.rear <= 15"));
+        assert!(matches("lot_area > -1"));
+        assert!(!matches("height < 40"));
+        assert!(matches("height == null && missing_field == null"));
+        assert!(matches("resunits"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(FilterParseError { position: 10, message: "unterminated string".to_string() }),
+            "zoning == \"RH-1".parse::<FilterExpr>()
+        );
+        assert_eq!(
+            Some(15),
+            "(resunits >= 4 ".parse::<FilterExpr>().err().map(|err| err.position)
+        );
+        assert!("resunits >= 4 4".parse::<FilterExpr>().is_err());
+        assert!("resunits $ 4".parse::<FilterExpr>().is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use geo::algorithm::map_coords::MapCoords;
+use geo::{MultiPolygon, Point};
+use geojson::{Geometry, PolygonType, Position};
 use proj::Proj;
 
 /// projection for converting latitude and longitude (in degrees) into feet
@@ -15,3 +18,42 @@ pub fn default_projection() -> Proj {
     .expect("Failed to create projection");
     proj
 }
+
+thread_local! {static PROJ: Proj = default_projection();}
+
+/// Project a point from longitude and latitude into feet, using this thread's `default_projection`
+pub fn point_to_ft(point: Point<f64>) -> Point<f64> {
+    PROJ.with(|proj| proj.project(point, false))
+        .unwrap_or_else(|err| panic!("Projection failed on ({} {}): {}", point.x(), point.y(), err))
+}
+
+/// Inverse of `point_to_ft`
+pub fn point_from_ft(point: Point<f64>) -> Point<f64> {
+    PROJ.with(|proj| proj.project(point, true))
+        .unwrap_or_else(|err| panic!("Projection failed on ({} {}): {}", point.x(), point.y(), err))
+}
+
+/// Project a multipolygon from longitude and latitude into feet
+pub fn to_ft(multi_polygon: &MultiPolygon<f64>) -> MultiPolygon<f64> {
+    multi_polygon.map_coords(|&(lon, lat)| {
+        let point = point_to_ft(Point::new(lon, lat));
+        (point.x(), point.y())
+    })
+}
+
+/// GeoJSON geometry of the exterior rings of a multipolygon
+pub fn multipolygon_to_geojson(multi_polygon: &MultiPolygon<f64>) -> Geometry {
+    Geometry::new(geojson::Value::MultiPolygon(
+        multi_polygon.0.iter().map(|polygon| {
+            // only do the exterior ring, so the polygon_type has only one element
+            let polygon_type: PolygonType = vec![
+                polygon.exterior().0.iter().map(|coord| {
+                    let pos: Position = vec![coord.x, coord.y];
+                    pos
+                }).collect()
+            ];
+            polygon_type
+        })
+            .collect()
+    ))
+}
@@ -1,4 +1,6 @@
 extern crate calamine;
+#[macro_use]
+extern crate clap;
 extern crate chrono;
 extern crate conv;
 extern crate csv;
@@ -10,11 +12,30 @@ extern crate log;
 
 extern crate wkt;
 
+pub mod approval_times;
+pub mod assessment_gap;
+pub mod cli;
+pub mod commands;
+pub mod csv_loader;
+pub mod dataset_cache;
+pub mod filter_expr;
 pub mod geo_util;
+pub mod lot_sides;
+pub mod manifest;
+pub mod nonconforming;
+pub mod parcel_join;
 pub mod polygon_wrapper;
+pub mod roll_diff;
+pub mod schema;
+pub mod sfheightbulk;
 pub mod sfassessormap;
+pub mod sfbuildingpermits;
 pub mod sflanduse;
 pub mod sfplanningacela;
+pub mod spatial_layer;
+#[cfg(test)]
+mod test_util;
 pub mod xlsxdeserialize;
+pub mod zoning_capacity;
 pub mod sfzoningdistricts;
 pub mod sfbuidingfootprints;
\ No newline at end of file
@@ -0,0 +1,155 @@
+//! Classify the edges of a lot as front, side or rear, and measure how far a building
+//! is set back from each of them.
+
+use crate::geo_util::{point_from_ft, point_to_ft};
+use crate::polygon_wrapper::PolygonWrapper;
+use crate::sflanduse::LandUseRecord;
+use crate::spatial_layer::SpatialLayer;
+use geo::algorithm::euclidean_distance::EuclideanDistance;
+use geo::algorithm::winding_order::Winding;
+use geo::{Line, MultiPolygon, Point};
+use ordered_float::OrderedFloat;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum SideType {
+    Front,
+    Side,
+    Rear,
+}
+/// An edge of a lot's exterior ring, in longitude and latitude
+pub struct SideWithType {
+    pub edge: Line<f64>,
+    pub side_type: SideType,
+}
+
+/// Classify each edge of the lot as front (there is no parcel on the other side, so it faces a street),
+/// side (adjacent to a front edge) or rear
+pub fn get_rear_side(
+    parcels: &SpatialLayer<LandUseRecord>,
+    land_use_polygon: &PolygonWrapper<LandUseRecord>,
+) -> Vec<SideWithType> {
+    land_use_polygon.multi_polygon.0.iter().flat_map(|polygon| {
+        let mut exterior = polygon.exterior().clone();
+        exterior.make_ccw_winding();
+        let all_edges: Vec<Line<f64>> = exterior.lines().collect();
+        let mut edges_face_street: Vec<bool> = vec![];
+        for i in 0..all_edges.len() {
+            let line = all_edges[i];
+            let start: Point<f64> = line.start.into();
+            let end: Point<f64> = line.end.into();
+            let next: Point<f64> = all_edges[(i + 1) % all_edges.len()].end.into();
+
+            // the following calculations are in a feet projection
+            let start_ft: Point<f64> = point_to_ft(start);
+            let end_ft: Point<f64> = point_to_ft(end);
+            let next_ft: Point<f64> = point_to_ft(next);
+            let vector: Point<f64> = end_ft - start_ft;
+            let next_vector: Point<f64> = next_ft - end_ft;
+            let next_vector_projection = vector.dot(next_vector) / (vector.0.x.hypot(vector.0.y));
+            let next_vector_parallel_part = Point::new(vector.0.x * next_vector_projection, vector.0.y * next_vector_projection);
+            let next_vector_perpendicular_part = next_vector - next_vector_parallel_part;
+            let next_vector_perpendicular_part_len = next_vector_perpendicular_part.x().hypot(next_vector_perpendicular_part.y());
+            // TODO: just use polygon CCW winding instead
+            let perpendicular_unit_vec = Point::new(
+                next_vector_perpendicular_part.x()/next_vector_perpendicular_part_len,
+                next_vector_perpendicular_part.y()/next_vector_perpendicular_part_len);
+            // note: we subtract to go outside the polygon rather than inside
+            let point_right_outside_ft = start_ft +
+                Point::new(vector.x()/2.0, vector.y()/2.0) -
+                Point::new(perpendicular_unit_vec.x()*10.0, perpendicular_unit_vec.y()*10.0);
+
+            let point_right_outside = point_from_ft(point_right_outside_ft);
+            let edge_faces_street = parcels.at_point(&point_right_outside).is_none();
+            edges_face_street.push(edge_faces_street);
+        }
+        let mut rear_edges = vec![];
+        for i in 0..edges_face_street.len() {
+            let edge = all_edges[i];
+            let annotation = if edges_face_street[i] {
+                SideType::Front
+            } else if edges_face_street[(i + 1) % edges_face_street.len()] {
+                SideType::Side
+            } else if edges_face_street[(i - 1 + edges_face_street.len()) % edges_face_street.len()] {
+                SideType::Side
+            } else {
+                SideType::Rear
+            };
+            rear_edges.push(SideWithType {edge, side_type: annotation });
+        }
+        rear_edges
+    }).collect()
+}
+
+/// Distance in ft. from a building to one side of its lot
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SetbackAndAnnotation {
+    pub side_type: SideType,
+    pub setback: f64,
+}
+
+fn line_to_ft(line: &Line<f64>) -> Line<f64> {
+    Line::new::<Point<f64>>(point_to_ft(line.start.into()), point_to_ft(line.end.into()))
+}
+
+/// The shortest distance from `building_ft` (in ft.) to each side of the lot
+pub fn side_setbacks(sides: &[SideWithType], building_ft: &MultiPolygon<f64>) -> Vec<SetbackAndAnnotation> {
+    sides.iter()
+        .flat_map(|side_and_annotation| {
+            let line_ft = line_to_ft(&side_and_annotation.edge);
+            let setback = building_ft.0.iter()
+                .flat_map(|polygon_ft|
+                    polygon_ft
+                        .exterior().points_iter()
+                        .map(|building_pt_ft|
+                            line_ft.euclidean_distance(&building_pt_ft)
+                        )
+                        .map(Into::<OrderedFloat<f64>>::into)
+                        .min()
+                )
+                .min();
+            setback.map(|setback| {
+                SetbackAndAnnotation {
+                    side_type: side_and_annotation.side_type,
+                    setback: setback.into_inner()
+                }
+            })
+        })
+        .collect()
+}
+
+/// The smallest setback from a side of the given type, if the lot has any
+pub fn min_setback(setbacks: &[SetbackAndAnnotation], side_type: SideType) -> Option<f64> {
+    setbacks.iter()
+        .filter(|side| side.side_type == side_type)
+        .map(|side| OrderedFloat(side.setback))
+        .min()
+        .map(OrderedFloat::into_inner)
+}
+
+/// Depth in ft. of the yard between the building and the rear lot line(s), if the lot has any
+pub fn rear_yard_depth(setbacks: &[SetbackAndAnnotation]) -> Option<f64> {
+    min_setback(setbacks, SideType::Rear)
+}
+
+/// Approximate depth in ft. of the lot: the farthest that a rear corner is from the front lot line(s).
+/// None for lots without both a front and a rear (e.g. corner lots and lots without street frontage).
+pub fn lot_depth(sides: &[SideWithType]) -> Option<f64> {
+    let fronts_ft: Vec<Line<f64>> = sides.iter()
+        .filter(|side| side.side_type == SideType::Front)
+        .map(|side| line_to_ft(&side.edge))
+        .collect();
+    sides.iter()
+        .filter(|side| side.side_type == SideType::Rear)
+        .flat_map(|side| {
+            let line_ft = line_to_ft(&side.edge);
+            vec![line_ft.start, line_ft.end]
+        })
+        .flat_map(|corner_ft| {
+            let corner_ft: Point<f64> = corner_ft.into();
+            fronts_ft.iter()
+                .map(|front_ft| OrderedFloat(front_ft.euclidean_distance(&corner_ft)))
+                .min()
+        })
+        .max()
+        .map(OrderedFloat::into_inner)
+}
@@ -0,0 +1,223 @@
+//! `parcelscan.toml`: where the local copy of each dataset is, so the paths don't have to be
+//! passed to every command. Each dataset is a table named after the dataset:
+//!
+//! ```toml
+//! [land_use]
+//! path = "~/Downloads/LandUse2016.csv"
+//! schema_version = 1
+//! sha256 = "0f3c…"  # optional; checked by `parcelscan datasets check`
+//! ```
+//!
+//! Relative paths are relative to the directory of the manifest.
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+pub const DEFAULT_MANIFEST: &str = "parcelscan.toml";
+
+/// The datasets that the analyses read
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dataset {
+    LandUse,
+    ZoningDistricts,
+    HeightBulk,
+    Footprints,
+    Ppts,
+    BuildingPermits,
+    AssessorRoll,
+    ChicagoBuildings,
+    CookParcels,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 9] = [
+        Dataset::LandUse,
+        Dataset::ZoningDistricts,
+        Dataset::HeightBulk,
+        Dataset::Footprints,
+        Dataset::Ppts,
+        Dataset::BuildingPermits,
+        Dataset::AssessorRoll,
+        Dataset::ChicagoBuildings,
+        Dataset::CookParcels,
+    ];
+
+    /// Name of the dataset in the manifest (and in the cache and quarantine files)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dataset::LandUse => "land_use",
+            Dataset::ZoningDistricts => "zoning_districts",
+            Dataset::HeightBulk => "height_bulk",
+            Dataset::Footprints => "footprints",
+            Dataset::Ppts => "ppts",
+            Dataset::BuildingPermits => "building_permits",
+            Dataset::AssessorRoll => "assessor_roll",
+            Dataset::ChicagoBuildings => "chicago_buildings",
+            Dataset::CookParcels => "cook_parcels",
+        }
+    }
+
+    /// Command line argument that overrides the manifest
+    pub fn arg_name(&self) -> &'static str {
+        match self {
+            Dataset::LandUse => "land-use",
+            Dataset::ZoningDistricts => "zoning-districts",
+            Dataset::HeightBulk => "height-bulk",
+            Dataset::Footprints => "footprints",
+            Dataset::Ppts => "planning",
+            Dataset::BuildingPermits => "building-permits",
+            Dataset::AssessorRoll => "assessor-roll",
+            Dataset::ChicagoBuildings => "buildings",
+            Dataset::CookParcels => "parcels",
+        }
+    }
+
+    /// Version of the dataset's columns that the record struct reads.
+    /// Bump it when a record struct is changed to read a newer export.
+    pub fn schema_version(&self) -> u32 {
+        1
+    }
+}
+
+impl fmt::Display for Dataset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ManifestError {
+    #[error("could not read manifest {0}: {1}")]
+    Io(PathBuf, #[source] io::Error),
+    #[error("invalid manifest {0}: {1}")]
+    Toml(PathBuf, #[source] toml::de::Error),
+    #[error("unknown dataset {0} in manifest")]
+    UnknownDataset(String),
+}
+
+/// One dataset in the manifest
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DatasetEntry {
+    pub path: PathBuf,
+    pub schema_version: u32,
+    /// hex SHA-256 of the file
+    pub sha256: Option<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DatasetManifest {
+    /// where the manifest was read from
+    pub path: PathBuf,
+    pub datasets: BTreeMap<Dataset, DatasetEntry>,
+}
+
+impl DatasetManifest {
+    pub fn load(path: &Path) -> Result<Self, ManifestError> {
+        let contents = fs::read_to_string(path).map_err(|err| ManifestError::Io(path.to_path_buf(), err))?;
+        DatasetManifest::parse(path, &contents)
+    }
+
+    /// Parse the contents of the manifest at `path`
+    pub fn parse(path: &Path, contents: &str) -> Result<Self, ManifestError> {
+        let entries: BTreeMap<String, DatasetEntry> =
+            toml::from_str(contents).map_err(|err| ManifestError::Toml(path.to_path_buf(), err))?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut datasets = BTreeMap::new();
+        for (name, mut entry) in entries {
+            let dataset = Dataset::ALL
+                .iter()
+                .find(|dataset| dataset.as_str() == name)
+                .ok_or_else(|| ManifestError::UnknownDataset(name.clone()))?;
+            entry.path = resolve_path(base_dir, &entry.path);
+            datasets.insert(*dataset, entry);
+        }
+        Ok(DatasetManifest {
+            path: path.to_path_buf(),
+            datasets,
+        })
+    }
+
+    pub fn get(&self, dataset: Dataset) -> Option<&DatasetEntry> {
+        self.datasets.get(&dataset)
+    }
+}
+
+/// Expand `~/` and make `path` relative to `base_dir`
+fn resolve_path(base_dir: &Path, path: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return Path::new(&home).join(rest);
+        }
+    }
+    base_dir.join(path)
+}
+
+/// Hex SHA-256 of the contents of the file
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 16];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.input(&buf[..n]);
+    }
+    Ok(hasher
+        .result()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{sha256_file, Dataset, DatasetManifest, ManifestError};
+    use std::path::Path;
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = DatasetManifest::parse(
+            Path::new("/data/parcelscan.toml"),
+            r#"
+            [land_use]
+            path = "LandUse2016.csv"
+            schema_version = 1
+            sha256 = "abc123"
+
+            [ppts]
+            path = "/downloads/PPTS_Records_data.csv"
+            schema_version = 1
+            "#,
+        )
+        .expect("manifest should parse");
+        let land_use = manifest.get(Dataset::LandUse).expect("land_use");
+        assert_eq!(Path::new("/data/LandUse2016.csv"), land_use.path);
+        assert_eq!(Some("abc123"), land_use.sha256.as_deref());
+        assert_eq!(Path::new("/downloads/PPTS_Records_data.csv"), manifest.get(Dataset::Ppts).expect("ppts").path);
+        assert!(manifest.get(Dataset::Footprints).is_none());
+
+        match DatasetManifest::parse(Path::new("parcelscan.toml"), "[parcels]\npath = \"x.csv\"\nschema_version = 1\n") {
+            Err(ManifestError::UnknownDataset(name)) => assert_eq!("parcels", name),
+            other => panic!("expected unknown dataset, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sha256_file() {
+        let path = std::env::temp_dir().join("parcelscan_test_sha256.txt");
+        std::fs::write(&path, "abc").expect("write temp file");
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            sha256_file(&path).expect("hash")
+        );
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,230 @@
+//! Existing buildings that exceed what their zoning district allows today
+//! (“legal nonconforming” buildings, which could not be built under current zoning).
+//!
+//! A parcel is checked against its district's density limit, lot coverage, rear yard and height limit.
+//! Footprints are digitized independently of the lot lines and their LiDAR heights include
+//! exempt rooftop features, so each check allows a tolerance before it reports a violation.
+
+use crate::sfheightbulk::HeightBulk;
+use crate::sfzoningdistricts::ZoningCode;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// One way in which a parcel exceeds its zoning
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "reason", rename_all = "kebab-case")]
+pub enum Violation {
+    /// more units than the density limit allows
+    Density { units: usize, max_units: u32 },
+    /// buildings cover more of the lot than the rear yard leaves buildable
+    LotCoverage { coverage: f64, max_coverage: f64 },
+    /// the rear yard is shallower than required, in ft.
+    RearYard { depth: f64, required_depth: f64 },
+    /// the tallest building is taller than the height limit, in ft.
+    Height { height: f64, height_limit: f64 },
+}
+
+impl Violation {
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Violation::Density { .. } => "density",
+            Violation::LotCoverage { .. } => "lot-coverage",
+            Violation::RearYard { .. } => "rear-yard",
+            Violation::Height { .. } => "height",
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::Density { units, max_units } =>
+                write!(f, "{} units > {} allowed", units, max_units),
+            Violation::LotCoverage { coverage, max_coverage } =>
+                write!(f, "lot coverage {:.0}% > {:.0}%", coverage * 100.0, max_coverage * 100.0),
+            Violation::RearYard { depth, required_depth } =>
+                write!(f, "rear yard {:.0} ft. < {:.0} ft.", depth, required_depth),
+            Violation::Height { height, height_limit } =>
+                write!(f, "height {:.0} ft. > {:.0} ft.", height, height_limit),
+        }
+    }
+}
+
+/// How far past a limit a parcel must be before it is reported
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerances {
+    /// ft. above the height limit, for parapets and penthouses that are exempt from the limit
+    pub height: f64,
+    /// fraction of the lot area
+    pub lot_coverage: f64,
+    /// ft. of rear yard depth
+    pub rear_yard: f64,
+}
+
+impl Default for Tolerances {
+    fn default() -> Self {
+        Tolerances {
+            height: 16.0,
+            lot_coverage: 0.05,
+            rear_yard: 2.0,
+        }
+    }
+}
+
+/// What is on a parcel today. Measurements that couldn't be made (e.g. the rear yard of a corner lot) are None.
+#[derive(Clone, Debug, Default)]
+pub struct ExistingParcel {
+    pub units: usize,
+    /// sq. ft.
+    pub lot_area: f64,
+    /// total footprint area of the buildings on the lot, in sq. ft.
+    pub building_area: f64,
+    /// ft. between the buildings and the rear lot line
+    pub rear_yard_depth: Option<f64>,
+    /// ft. from the front to the rear lot line
+    pub lot_depth: Option<f64>,
+    /// height of the tallest building, in ft.
+    pub height: Option<f64>,
+}
+
+/// Every way in which `parcel` exceeds the base zoning of `zoning` and the height limit of `height_bulk`
+pub fn check_parcel(
+    parcel: &ExistingParcel,
+    zoning: &ZoningCode,
+    height_bulk: Option<&HeightBulk>,
+    tolerances: &Tolerances,
+) -> Vec<Violation> {
+    let mut violations = vec![];
+    if let Some(rules) = zoning.rules() {
+        if let Some(max_units) = rules.density.max_units(parcel.lot_area) {
+            if parcel.units > max_units as usize {
+                violations.push(Violation::Density { units: parcel.units, max_units });
+            }
+        }
+        if parcel.lot_area > 0.0 && parcel.building_area > 0.0 {
+            let coverage = parcel.building_area / parcel.lot_area;
+            let max_coverage = rules.max_lot_coverage();
+            if coverage > max_coverage + tolerances.lot_coverage {
+                violations.push(Violation::LotCoverage { coverage, max_coverage });
+            }
+        }
+        if let (Some(rear_yard), Some(depth), Some(lot_depth)) =
+            (rules.rear_yard, parcel.rear_yard_depth, parcel.lot_depth)
+        {
+            let required_depth = rear_yard.depth(lot_depth);
+            if depth + tolerances.rear_yard < required_depth {
+                violations.push(Violation::RearYard { depth, required_depth });
+            }
+        }
+    }
+    if let (Some(height), Some(height_limit)) =
+        (parcel.height, height_bulk.and_then(|height_bulk| height_bulk.height_limit))
+    {
+        if height > height_limit + tolerances.height {
+            violations.push(Violation::Height { height, height_limit });
+        }
+    }
+    violations
+}
+
+/// Counts of nonconforming parcels and units in one zoning district
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct DistrictSummary {
+    pub zoning: String,
+    pub parcels: usize,
+    pub units: usize,
+    pub nonconforming_parcels: usize,
+    pub nonconforming_units: usize,
+    pub density_violations: usize,
+    pub lot_coverage_violations: usize,
+    pub rear_yard_violations: usize,
+    pub height_violations: usize,
+}
+
+impl DistrictSummary {
+    pub fn add(&mut self, units: usize, violations: &[Violation]) {
+        self.parcels += 1;
+        self.units += units;
+        if !violations.is_empty() {
+            self.nonconforming_parcels += 1;
+            self.nonconforming_units += units;
+        }
+        for violation in violations {
+            match violation {
+                Violation::Density { .. } => self.density_violations += 1,
+                Violation::LotCoverage { .. } => self.lot_coverage_violations += 1,
+                Violation::RearYard { .. } => self.rear_yard_violations += 1,
+                Violation::Height { .. } => self.height_violations += 1,
+            }
+        }
+    }
+}
+
+/// Summarize (zoning district, units, violations) of each parcel by district
+pub fn summarize_by_district<'a, I>(parcels: I) -> BTreeMap<String, DistrictSummary>
+where
+    I: IntoIterator<Item = (&'a ZoningCode, usize, &'a [Violation])>,
+{
+    let mut summaries: BTreeMap<String, DistrictSummary> = BTreeMap::new();
+    for (zoning, units, violations) in parcels {
+        summaries
+            .entry(zoning.to_string())
+            .or_insert_with(|| DistrictSummary {
+                zoning: zoning.to_string(),
+                ..Default::default()
+            })
+            .add(units, violations);
+    }
+    summaries
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_parcel, summarize_by_district, ExistingParcel, Tolerances, Violation};
+    use crate::sfheightbulk::HeightBulk;
+    use crate::sfzoningdistricts::ZoningCode;
+
+    #[test]
+    fn test_check_parcel() {
+        // a 25x100 ft. RH-2 lot with a 4-unit, 50 ft. building covering 85% of the lot
+        let parcel = ExistingParcel {
+            units: 4,
+            lot_area: 2500.0,
+            building_area: 2125.0,
+            rear_yard_depth: Some(15.0),
+            lot_depth: Some(100.0),
+            height: Some(50.0),
+        };
+        let violations = check_parcel(&parcel, &ZoningCode::Rh2, Some(&HeightBulk::from("40-X")), &Tolerances::default());
+        let reasons: Vec<&str> = violations.iter().map(Violation::reason).collect();
+        assert_eq!(vec!["density", "lot-coverage", "rear-yard"], reasons);
+        assert_eq!("4 units > 2 allowed", violations[0].to_string());
+
+        let violations = check_parcel(&parcel, &ZoningCode::Nct3, Some(&HeightBulk::from("30-X")), &Tolerances::default());
+        let reasons: Vec<&str> = violations.iter().map(Violation::reason).collect();
+        // NCT-3 has no density limit and a 25% rear yard
+        assert_eq!(vec!["lot-coverage", "rear-yard", "height"], reasons);
+
+        let conforming = ExistingParcel {
+            units: 2,
+            building_area: 1600.0,
+            rear_yard_depth: Some(35.0),
+            height: Some(30.0),
+            ..parcel
+        };
+        assert!(check_parcel(&conforming, &ZoningCode::Rh2, Some(&HeightBulk::from("40-X")), &Tolerances::default()).is_empty());
+    }
+
+    #[test]
+    fn test_summarize_by_district() {
+        let violation = [Violation::Density { units: 4, max_units: 2 }];
+        let summaries = summarize_by_district(vec![
+            (&ZoningCode::Rh2, 4, &violation[..]),
+            (&ZoningCode::Rh2, 1, &[][..]),
+            (&ZoningCode::Rh3, 3, &[][..]),
+        ]);
+        let rh2 = &summaries["RH-2"];
+        assert_eq!((2, 5, 1, 4, 1), (rh2.parcels, rh2.units, rh2.nonconforming_parcels, rh2.nonconforming_units, rh2.density_violations));
+        assert_eq!(0, summaries["RH-3"].nonconforming_parcels);
+    }
+}
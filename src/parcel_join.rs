@@ -0,0 +1,375 @@
+//! Assign building footprints to the parcels they stand on.
+//!
+//! Looking up the parcel at a footprint's centroid picks the wrong lot for L-shaped buildings
+//! and buildings that span lot lines, and finds nothing when the centroid lands in a street,
+//! so the default is to intersect the footprint with every candidate parcel instead.
+//! When the footprint carries the Assessor block/lot of its parcel, `join_parcels_by_key`
+//! uses that first and falls back to geometry.
+
+use crate::polygon_wrapper::PolygonWrapper;
+use crate::sflanduse::{normalize_blklot, LandUseRecord};
+use crate::spatial_layer::SpatialLayer;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use geo::algorithm::area::Area;
+use geo::algorithm::bounding_rect::BoundingRect;
+use geo::MultiPolygon;
+use geo_booleanop::boolean::BooleanOp;
+use rstar::AABB;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+
+/// Parcels covering less than this fraction of a footprint are ignored when splitting,
+/// since adjacent lot lines and footprints are digitized independently and leave slivers
+pub const MIN_SPLIT_FRACTION: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParcelJoinMode {
+    /// The parcel containing the footprint's centroid
+    Centroid,
+    /// The parcel with the largest intersection with the footprint
+    LargestOverlap,
+    /// Every parcel that intersects the footprint, each getting its part of the building
+    Split,
+}
+
+impl FromStr for ParcelJoinMode {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "centroid" => Ok(ParcelJoinMode::Centroid),
+            "largest-overlap" => Ok(ParcelJoinMode::LargestOverlap),
+            "split" => Ok(ParcelJoinMode::Split),
+            _ => Err(format!("unknown parcel join mode {}", s)),
+        }
+    }
+}
+
+/// The part of a footprint that lies on one parcel
+pub struct ParcelMatch<'a, T> {
+    pub parcel: &'a PolygonWrapper<T>,
+    /// Intersection of the footprint and the parcel
+    pub building_part: MultiPolygon<f64>,
+    /// Fraction (0-1) of the footprint's area that lies on this parcel
+    pub overlap_fraction: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JoinMethod {
+    /// Matched on the Assessor block/lot
+    Key,
+    /// Matched by location
+    Geometry,
+}
+
+pub struct ParcelJoin<'a, T> {
+    /// At most one match unless the mode is Split (and the join was by geometry)
+    pub matches: Vec<ParcelMatch<'a, T>>,
+    /// Fraction (0-1) of the footprint's area that lies on the matched parcels.
+    /// Low confidence means the building spans lot lines or the datasets disagree.
+    pub confidence: f64,
+    pub method: JoinMethod,
+}
+
+impl<'a, T> ParcelJoin<'a, T> {
+    fn new(matches: Vec<ParcelMatch<'a, T>>, method: JoinMethod) -> Self {
+        let confidence = matches
+            .iter()
+            .map(|parcel_match| parcel_match.overlap_fraction)
+            .sum::<f64>()
+            .min(1.0);
+        ParcelJoin {
+            matches,
+            confidence,
+            method,
+        }
+    }
+}
+
+/// Intersection of two shapes; None if the clipping algorithm gave up on the geometry
+fn intersection(a: &MultiPolygon<f64>, b: &MultiPolygon<f64>) -> Option<MultiPolygon<f64>> {
+    // the Martinez-Rueda implementation panics on some degenerate polygons;
+    // treat those as not overlapping rather than aborting the whole scan
+    panic::catch_unwind(AssertUnwindSafe(|| a.intersection(b))).ok()
+}
+
+fn unsigned_area(multi_polygon: &MultiPolygon<f64>) -> f64 {
+    multi_polygon.0.iter().map(|polygon| polygon.area().abs()).sum()
+}
+
+fn overlap<'a, T>(shape: &MultiPolygon<f64>, parcel: &'a PolygonWrapper<T>) -> Option<ParcelMatch<'a, T>> {
+    let building_part = intersection(shape, &parcel.multi_polygon)?;
+    let shape_area = unsigned_area(shape);
+    let overlap_fraction = if shape_area > 0.0 {
+        (unsigned_area(&building_part) / shape_area).min(1.0)
+    } else {
+        0.0
+    };
+    Some(ParcelMatch {
+        parcel,
+        building_part,
+        overlap_fraction,
+    })
+}
+
+/// Find the parcel(s) that the footprint `shape` stands on
+pub fn join_parcels<'a, T>(
+    parcels: &'a SpatialLayer<T>,
+    shape: &MultiPolygon<f64>,
+    mode: ParcelJoinMode,
+) -> ParcelJoin<'a, T> {
+    let overlap = |parcel| overlap(shape, parcel);
+    let matches: Vec<ParcelMatch<T>> = match mode {
+        ParcelJoinMode::Centroid => parcels.at_centroid(shape).and_then(overlap).into_iter().collect(),
+        ParcelJoinMode::LargestOverlap | ParcelJoinMode::Split => {
+            let bounding_rect = match shape.bounding_rect() {
+                Some(bounding_rect) => bounding_rect,
+                None => return ParcelJoin::new(vec![], JoinMethod::Geometry),
+            };
+            let envelope = AABB::from_corners(
+                [bounding_rect.min().x, bounding_rect.min().y],
+                [bounding_rect.max().x, bounding_rect.max().y],
+            );
+            let mut candidates: Vec<ParcelMatch<T>> = parcels
+                .in_envelope(&envelope)
+                .filter_map(overlap)
+                .filter(|parcel_match| parcel_match.overlap_fraction > 0.0)
+                .collect();
+            candidates.sort_by(|a, b| b.overlap_fraction.partial_cmp(&a.overlap_fraction).expect("overlap should not be NaN"));
+            if mode == ParcelJoinMode::LargestOverlap {
+                candidates.truncate(1);
+            } else {
+                candidates.retain(|parcel_match| parcel_match.overlap_fraction >= MIN_SPLIT_FRACTION);
+            }
+            candidates
+        }
+    };
+    ParcelJoin::new(matches, JoinMethod::Geometry)
+}
+
+/// LandUse parcels by normalized block/lot.
+/// Condominium lots share the map lot (`MAPBLKLOT`) of their parent lot, so they resolve to the parent.
+pub struct ParcelKeyIndex<'a> {
+    by_blklot: HashMap<String, &'a PolygonWrapper<LandUseRecord>>,
+}
+
+impl<'a> ParcelKeyIndex<'a> {
+    pub fn new(parcels: &'a SpatialLayer<LandUseRecord>) -> Self {
+        let mut by_blklot = HashMap::new();
+        for parcel in parcels.iter() {
+            if let Some(blklot) = normalize_blklot(&parcel.value.blklot) {
+                by_blklot.insert(blklot, parcel);
+            }
+        }
+        // second pass, so that parents are all indexed before their condo lots are redirected
+        let mut condo_parents = vec![];
+        for parcel in parcels.iter() {
+            let blklot = normalize_blklot(&parcel.value.blklot);
+            let mapblklot = normalize_blklot(&parcel.value.mapblklot);
+            if let (Some(blklot), Some(mapblklot)) = (blklot, mapblklot) {
+                if blklot != mapblklot {
+                    let parent = by_blklot.get(&mapblklot).cloned().unwrap_or(parcel);
+                    condo_parents.push((blklot, parent));
+                    condo_parents.push((mapblklot, parent));
+                }
+            }
+        }
+        by_blklot.extend(condo_parents);
+        ParcelKeyIndex { by_blklot }
+    }
+
+    pub fn get(&self, blklot: &str) -> Option<&'a PolygonWrapper<LandUseRecord>> {
+        normalize_blklot(blklot).and_then(|blklot| self.by_blklot.get(&blklot).cloned())
+    }
+}
+
+/// Counts of how footprints were joined to parcels, shared across rayon workers
+#[derive(Default)]
+pub struct JoinStats {
+    key: AtomicUsize,
+    geometry: AtomicUsize,
+    unmatched: AtomicUsize,
+    mismatches: AtomicUsize,
+    low_confidence: AtomicUsize,
+}
+
+impl JoinStats {
+    /// Whether `join` has at least `min_confidence` of the footprint on its parcel(s).
+    /// Joins below it are counted as skipped.
+    pub fn is_confident<T>(&self, join: &ParcelJoin<T>, min_confidence: f64) -> bool {
+        if join.confidence < min_confidence {
+            self.low_confidence.fetch_add(1, Ordering::Relaxed);
+            false
+        } else {
+            true
+        }
+    }
+
+    pub fn log_summary(&self) {
+        info!(
+            "Parcel joins: {} by block/lot key, {} by geometry, {} unmatched; {} key joins disagree with the geometry join",
+            self.key.load(Ordering::Relaxed),
+            self.geometry.load(Ordering::Relaxed),
+            self.unmatched.load(Ordering::Relaxed),
+            self.mismatches.load(Ordering::Relaxed),
+        );
+        let low_confidence = self.low_confidence.load(Ordering::Relaxed);
+        if low_confidence > 0 {
+            warn!("Skipped {} footprints below the minimum overlap confidence", low_confidence);
+        }
+    }
+}
+
+/// Join a footprint to the parcel with block/lot `blklot` if there is one,
+/// or else by geometry as in `join_parcels`.
+/// Key joins are also compared with the geometry join (its largest piece when splitting),
+/// and disagreements are counted.
+pub fn join_parcels_by_key<'a>(
+    parcels: &'a SpatialLayer<LandUseRecord>,
+    keys: &ParcelKeyIndex<'a>,
+    blklot: Option<&str>,
+    shape: &MultiPolygon<f64>,
+    mode: ParcelJoinMode,
+    stats: &JoinStats,
+) -> ParcelJoin<'a, LandUseRecord> {
+    let geometry_join = join_parcels(parcels, shape, mode);
+    let key_parcel = blklot.and_then(|blklot| keys.get(blklot));
+    match key_parcel {
+        Some(key_parcel) => {
+            stats.key.fetch_add(1, Ordering::Relaxed);
+            if let Some(geometry_match) = geometry_join.matches.first() {
+                // compare map lots, since condo lots share their parent's shape
+                let map_lot = |parcel: &PolygonWrapper<LandUseRecord>| normalize_blklot(&parcel.value.mapblklot);
+                if map_lot(geometry_match.parcel) != map_lot(key_parcel) {
+                    stats.mismatches.fetch_add(1, Ordering::Relaxed);
+                    debug!(
+                        "Footprint key {} is on parcel {} but overlaps {} the most",
+                        blklot.unwrap_or(""),
+                        key_parcel.value.blklot,
+                        geometry_match.parcel.value.blklot
+                    );
+                }
+            }
+            ParcelJoin::new(overlap(shape, key_parcel).into_iter().collect(), JoinMethod::Key)
+        }
+        None => {
+            if geometry_join.matches.is_empty() {
+                stats.unmatched.fetch_add(1, Ordering::Relaxed);
+            } else {
+                stats.geometry.fetch_add(1, Ordering::Relaxed);
+            }
+            geometry_join
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{join_parcels, join_parcels_by_key, JoinMethod, JoinStats, ParcelJoin, ParcelJoinMode, ParcelKeyIndex};
+    use crate::polygon_wrapper::{parse_wkt_to_multipolygon, PolygonWrapper};
+    use crate::test_util::land_use;
+    use crate::spatial_layer::SpatialLayer;
+    use std::sync::atomic::Ordering;
+    use geo::algorithm::area::Area;
+
+    fn layer() -> SpatialLayer<&'static str> {
+        let lots = vec![
+            ("left", "POLYGON ((0 0, 10 0, 10 10, 0 10, 0 0))"),
+            ("right", "POLYGON ((10 0, 20 0, 20 10, 10 10, 10 0))"),
+        ];
+        SpatialLayer::from_polygons(
+            lots.into_iter()
+                .map(|(name, wkt)| PolygonWrapper::new(parse_wkt_to_multipolygon(wkt).unwrap(), name))
+                .collect(),
+        )
+    }
+
+    fn names<T: Copy>(join: &ParcelJoin<T>) -> Vec<T> {
+        join.matches.iter().map(|m| m.parcel.value).collect()
+    }
+
+    #[test]
+    fn test_l_shaped_building() {
+        // building area is 18 + 56 = 74, of which 9 + 56 = 65 is on the right lot
+        let building = parse_wkt_to_multipolygon("POLYGON ((1 1, 19 1, 19 9, 11 9, 11 2, 1 2, 1 1))").unwrap();
+        let parcels = layer();
+
+        let largest = join_parcels(&parcels, &building, ParcelJoinMode::LargestOverlap);
+        assert_eq!(vec!["right"], names(&largest));
+        assert!((largest.confidence - 65.0 / 74.0).abs() < 1e-9);
+        assert!((largest.matches[0].building_part.area().abs() - 65.0).abs() < 1e-9);
+
+        let split = join_parcels(&parcels, &building, ParcelJoinMode::Split);
+        assert_eq!(vec!["right", "left"], names(&split));
+        assert!((split.matches[1].overlap_fraction - 9.0 / 74.0).abs() < 1e-9);
+        assert!((split.confidence - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_centroid_in_street() {
+        // 20 sq. units are on the left lot and 28 overhang the street, where the centroid is
+        let building = parse_wkt_to_multipolygon("POLYGON ((1 5, 5 5, 5 17, 1 17, 1 5))").unwrap();
+        let parcels = layer();
+        let centroid = join_parcels(&parcels, &building, ParcelJoinMode::Centroid);
+        assert!(centroid.matches.is_empty());
+        assert_eq!(0.0, centroid.confidence);
+
+        let largest = join_parcels(&parcels, &building, ParcelJoinMode::LargestOverlap);
+        assert_eq!(vec!["left"], names(&largest));
+        assert!((largest.confidence - 20.0 / 48.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_building_across_three_lots() {
+        let parcels = SpatialLayer::from_polygons(
+            vec![
+                ("left", "POLYGON ((0 0, 10 0, 10 10, 0 10, 0 0))"),
+                ("middle", "POLYGON ((10 0, 13 0, 13 10, 10 10, 10 0))"),
+                ("right", "POLYGON ((13 0, 23 0, 23 10, 13 10, 13 0))"),
+            ]
+            .into_iter()
+            .map(|(name, wkt)| PolygonWrapper::new(parse_wkt_to_multipolygon(wkt).unwrap(), name))
+            .collect(),
+        );
+        // 40% of the building is on the left lot and 30% on each of the others
+        let building = parse_wkt_to_multipolygon("POLYGON ((6 1, 16 1, 16 2, 6 2, 6 1))").unwrap();
+        let largest = join_parcels(&parcels, &building, ParcelJoinMode::LargestOverlap);
+        assert_eq!(vec!["left"], names(&largest));
+        assert!((largest.confidence - 0.4).abs() < 1e-9);
+
+        let stats = JoinStats::default();
+        assert!(stats.is_confident(&largest, 0.0));
+        assert!(!stats.is_confident(&largest, 0.5));
+        assert_eq!(1, stats.low_confidence.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn test_join_by_key() {
+        let parcels = SpatialLayer::from_polygons(vec![
+            land_use("0001001", "0001001", "POLYGON ((0 0, 10 0, 10 10, 0 10, 0 0))"),
+            land_use("0001002", "0001002", "POLYGON ((10 0, 20 0, 20 10, 10 10, 10 0))"),
+            land_use("0001101", "0001002", "POLYGON ((10 0, 20 0, 20 10, 10 10, 10 0))"),
+        ]);
+        let keys = ParcelKeyIndex::new(&parcels);
+        assert_eq!("0001002", keys.get("0001101").unwrap().value.blklot, "condo lot should map to its parent");
+        let stats = JoinStats::default();
+        let building = parse_wkt_to_multipolygon("POLYGON ((1 1, 9 1, 9 9, 1 9, 1 1))").unwrap();
+
+        let join = join_parcels_by_key(&parcels, &keys, Some("0001001"), &building, ParcelJoinMode::LargestOverlap, &stats);
+        assert_eq!(JoinMethod::Key, join.method);
+        assert_eq!("0001001", join.matches[0].parcel.value.blklot);
+
+        // the key disagrees with the geometry, so the key wins with low confidence
+        let join = join_parcels_by_key(&parcels, &keys, Some("0001101"), &building, ParcelJoinMode::LargestOverlap, &stats);
+        assert_eq!(JoinMethod::Key, join.method);
+        assert_eq!(0.0, join.confidence);
+
+        let join = join_parcels_by_key(&parcels, &keys, Some("9999999"), &building, ParcelJoinMode::LargestOverlap, &stats);
+        assert_eq!(JoinMethod::Geometry, join.method);
+        assert_eq!("0001001", join.matches[0].parcel.value.blklot);
+
+        assert_eq!(2, stats.key.load(Ordering::Relaxed));
+        assert_eq!(1, stats.geometry.load(Ordering::Relaxed));
+        assert_eq!(1, stats.mismatches.load(Ordering::Relaxed));
+    }
+}
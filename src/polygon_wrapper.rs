@@ -6,9 +6,10 @@ use geo::algorithm::contains::Contains;
 use geo::algorithm::closest_point::ClosestPoint;
 use geo::algorithm::euclidean_distance::EuclideanDistance;
 use geo::prelude::Area;
-use std::error::Error;
+use thiserror::Error;
 use wkt::Wkt;
 
+#[derive(Serialize, Deserialize)]
 pub struct PolygonWrapper<T> {
     pub multi_polygon: MultiPolygon<f64>,
     pub bounding_box: AABB<[f64; 2]>,
@@ -44,35 +45,110 @@ impl<T> PointDistance for PolygonWrapper<T> {
         } else {
             match self.multi_polygon.closest_point(&point) {
                 Closest::Intersection(_) => 0.0,
-                Closest::SinglePoint(p) => point.euclidean_distance(&p),
+                Closest::SinglePoint(p) => {
+                    // rstar expects the squared distance
+                    let distance = point.euclidean_distance(&p);
+                    distance * distance
+                }
                 Closest::Indeterminate => panic!("MultiPolygon should contain at least 1 point"),
             }
         }
     }
 }
 
-pub fn parse_wkt_to_multipolygon(the_geom: &str) -> Result<MultiPolygon<f64>, Box<dyn Error + Send + Sync + 'static>> {
-    // TODO: take out the expect()s, and return Err instead
-    let parsed_wkt: Wkt<f64> = Wkt::from_str(the_geom).expect("Could not parse WKT");
+/// Reasons that a WKT geometry column could not be turned into a `MultiPolygon`
+#[derive(Error, Debug)]
+pub enum GeometryParseError {
+    #[error("invalid WKT: {0}")]
+    InvalidWkt(&'static str),
+    #[error("expected one multipolygon; got an empty geometry")]
+    Empty,
+    #[error("expected a MultiPolygon or Polygon; got {0}")]
+    UnexpectedKind(&'static str),
+    #[error("failed to convert WKT to geo_types: {0}")]
+    Conversion(String),
+}
+
+fn geometry_kind_name(geometry: &Geometry<f64>) -> &'static str {
+    match geometry {
+        Geometry::Point(_) => "Point",
+        Geometry::Line(_) => "Line",
+        Geometry::LineString(_) => "LineString",
+        Geometry::Polygon(_) => "Polygon",
+        Geometry::MultiPoint(_) => "MultiPoint",
+        Geometry::MultiLineString(_) => "MultiLineString",
+        Geometry::MultiPolygon(_) => "MultiPolygon",
+        Geometry::GeometryCollection(_) => "GeometryCollection",
+    }
+}
+
+/// Parse a `the_geom` column into a MultiPolygon.
+/// A `POLYGON` is promoted to a `MultiPolygon` with one member.
+pub fn parse_wkt_to_multipolygon(the_geom: &str) -> Result<MultiPolygon<f64>, GeometryParseError> {
+    let parsed_wkt: Wkt<f64> = Wkt::from_str(the_geom).map_err(GeometryParseError::InvalidWkt)?;
     let wkt_shape = parsed_wkt
         .items
         .into_iter()
         .next()
-        .expect("Expected one multipolygon; got none");
+        .ok_or(GeometryParseError::Empty)?;
     let multi_polygon = match wkt::conversion::try_into_geometry(&wkt_shape)
-        .expect("Failed to convert polygon wkt to geo_types")
+        .map_err(|e| GeometryParseError::Conversion(e.to_string()))?
     {
-        Geometry::MultiPolygon(multi_polygon) => {
-            trace!(
-                "multipolygon {:?} area = {}sqft",
-                multi_polygon,
-                multi_polygon.area().round()
-            );
-            multi_polygon
+        Geometry::MultiPolygon(multi_polygon) => multi_polygon,
+        Geometry::Polygon(polygon) => MultiPolygon(vec![polygon]),
+        Geometry::GeometryCollection(ref collection) if collection.0.is_empty() => {
+            return Err(GeometryParseError::Empty);
         }
-        _ => {
-            panic!("Expected only multipolygons in parcel file");
+        other => {
+            return Err(GeometryParseError::UnexpectedKind(geometry_kind_name(&other)));
         }
     };
+    if multi_polygon.0.is_empty() {
+        return Err(GeometryParseError::Empty);
+    }
+    trace!(
+        "multipolygon {:?} area = {}sqft",
+        multi_polygon,
+        multi_polygon.area().round()
+    );
     Ok(multi_polygon)
 }
+
+#[cfg(test)]
+mod test {
+    use super::{parse_wkt_to_multipolygon, GeometryParseError};
+
+    #[test]
+    fn test_parse_multipolygon() -> Result<(), GeometryParseError> {
+        let multi_polygon = parse_wkt_to_multipolygon("MULTIPOLYGON (((0 0, 1 0, 1 1, 0 0)))")?;
+        assert_eq!(1, multi_polygon.0.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_polygon_is_promoted() -> Result<(), GeometryParseError> {
+        let multi_polygon = parse_wkt_to_multipolygon("POLYGON ((0 0, 1 0, 1 1, 0 0))")?;
+        assert_eq!(1, multi_polygon.0.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        match parse_wkt_to_multipolygon("MULTIPOLYGON (((0 0, 1 0") {
+            Err(GeometryParseError::InvalidWkt(_)) => {}
+            other => panic!("expected InvalidWkt; got {:?}", other),
+        }
+        match parse_wkt_to_multipolygon("") {
+            Err(GeometryParseError::Empty) | Err(GeometryParseError::InvalidWkt(_)) => {}
+            other => panic!("expected Empty; got {:?}", other),
+        }
+        match parse_wkt_to_multipolygon("GEOMETRYCOLLECTION EMPTY") {
+            Err(GeometryParseError::Empty) => {}
+            other => panic!("expected Empty; got {:?}", other),
+        }
+        match parse_wkt_to_multipolygon("POINT (1 2)") {
+            Err(GeometryParseError::UnexpectedKind("Point")) => {}
+            other => panic!("expected UnexpectedKind; got {:?}", other),
+        }
+    }
+}
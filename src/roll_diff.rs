@@ -0,0 +1,297 @@
+//! Changes to properties between two roll years of the Assessor's secured roll.
+//!
+//! Properties are matched by APN. Properties whose units, area, year built or class code changed
+//! show unit mergers, demolitions and new construction.
+//! APNs that appear or disappear within a block are lot splits (including condominium maps) and mergers.
+
+use crate::sfassessormap::{ClassCode, TaxProperty};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// What changed about an APN
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Change {
+    /// the APN is new in the later roll year
+    Added,
+    /// the APN is missing from the later roll year
+    Removed,
+    Units,
+    Sqft,
+    YearBuilt,
+    ClassCode,
+}
+
+impl Change {
+    pub const ALL: [Change; 6] = [
+        Change::Added,
+        Change::Removed,
+        Change::Units,
+        Change::Sqft,
+        Change::YearBuilt,
+        Change::ClassCode,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Added => "added",
+            Change::Removed => "removed",
+            Change::Units => "units",
+            Change::Sqft => "sqft",
+            Change::YearBuilt => "year-built",
+            Change::ClassCode => "class-code",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An APN in two roll years. `before` or `after` is None if the APN was added or removed.
+#[derive(Clone, Debug)]
+pub struct ApnDiff<'a> {
+    pub apn: String,
+    pub before: Option<&'a TaxProperty>,
+    pub after: Option<&'a TaxProperty>,
+    pub changes: Vec<Change>,
+}
+
+fn units(property: Option<&TaxProperty>) -> f64 {
+    property.and_then(|property| property.units).unwrap_or(0.0)
+}
+
+impl<'a> ApnDiff<'a> {
+    pub fn new(apn: String, before: Option<&'a TaxProperty>, after: Option<&'a TaxProperty>) -> Self {
+        let mut changes = vec![];
+        match (before, after) {
+            (Some(before), Some(after)) => {
+                if before.units.unwrap_or(0.0) != after.units.unwrap_or(0.0) {
+                    changes.push(Change::Units);
+                }
+                if before.sqft != after.sqft {
+                    changes.push(Change::Sqft);
+                }
+                if before.yrblt != after.yrblt {
+                    changes.push(Change::YearBuilt);
+                }
+                if before.rp1clacde != after.rp1clacde {
+                    changes.push(Change::ClassCode);
+                }
+            }
+            (None, Some(_)) => changes.push(Change::Added),
+            (Some(_), None) => changes.push(Change::Removed),
+            (None, None) => {}
+        }
+        ApnDiff {
+            apn,
+            before,
+            after,
+            changes,
+        }
+    }
+
+    pub fn block(&self) -> &str {
+        block(&self.apn)
+    }
+
+    /// Units after minus units before
+    pub fn units_change(&self) -> f64 {
+        units(self.after) - units(self.before)
+    }
+
+    /// The building was replaced: the year built moved up to the earlier roll year or later,
+    /// as opposed to a correction to an older year
+    pub fn is_new_construction(&self) -> bool {
+        let before = self.before.and_then(|p| p.yrblt.map(|yrblt| (yrblt, p.rollyear.unwrap_or(0))));
+        match (before, self.after.and_then(|p| p.yrblt)) {
+            (Some((before, before_roll_year)), Some(after)) => after > before && after >= before_roll_year,
+            _ => false,
+        }
+    }
+
+    pub fn class_codes(&self) -> (Option<&'a ClassCode>, Option<&'a ClassCode>) {
+        (self.before.map(|p| &p.rp1clacde), self.after.map(|p| &p.rp1clacde))
+    }
+}
+
+/// Block of a normalized APN: the first four digits and the letter suffix if there is one,
+/// e.g. `3027A` of `3027A118`
+pub fn block(apn: &str) -> &str {
+    match apn.chars().nth(4) {
+        Some(c) if c.is_ascii_alphabetic() => &apn[..5],
+        _ => &apn[..apn.len().min(4)],
+    }
+}
+
+/// The properties of one roll year by normalized APN.
+/// If an APN is listed more than once, the first row is used and the duplicates are logged.
+pub fn roll_year_by_apn(roll: &[TaxProperty], roll_year: u32) -> BTreeMap<String, &TaxProperty> {
+    let mut by_apn = BTreeMap::new();
+    let mut duplicates = 0;
+    for property in roll.iter().filter(|property| property.rollyear == Some(roll_year)) {
+        if let Some(apn) = property.apn() {
+            match by_apn.entry(apn) {
+                Entry::Vacant(entry) => {
+                    entry.insert(property);
+                }
+                Entry::Occupied(entry) => {
+                    duplicates += 1;
+                    debug!("Roll year {}: APN {} is listed more than once", roll_year, entry.key());
+                }
+            }
+        }
+    }
+    if duplicates > 0 {
+        warn!(
+            "Roll year {}: ignored {} rows whose APN was already listed; only the first row of each APN is compared",
+            roll_year, duplicates
+        );
+    }
+    by_apn
+}
+
+/// The APNs that changed from roll year `from` to roll year `to`, in APN order
+pub fn diff_roll_years(roll: &[TaxProperty], from: u32, to: u32) -> Vec<ApnDiff<'_>> {
+    let before = roll_year_by_apn(roll, from);
+    let after = roll_year_by_apn(roll, to);
+    let mut apns: Vec<&String> = before.keys().chain(after.keys()).collect();
+    apns.sort();
+    apns.dedup();
+    apns.into_iter()
+        .map(|apn| ApnDiff::new(apn.clone(), before.get(apn).cloned(), after.get(apn).cloned()))
+        .filter(|diff| !diff.changes.is_empty())
+        .collect()
+}
+
+/// How the lots of a block changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlockChangeKind {
+    /// more APNs appeared than disappeared, e.g. a lot split or a condominium map
+    Split,
+    /// more APNs disappeared than appeared
+    Merge,
+    /// as many APNs appeared as disappeared
+    Renumbered,
+}
+
+impl BlockChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockChangeKind::Split => "split",
+            BlockChangeKind::Merge => "merge",
+            BlockChangeKind::Renumbered => "renumbered",
+        }
+    }
+}
+
+/// The APNs that appeared or disappeared in a block
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockChange {
+    pub block: String,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// units of the added APNs minus units of the removed APNs
+    pub units_change: f64,
+}
+
+impl BlockChange {
+    pub fn kind(&self) -> BlockChangeKind {
+        if self.added.len() > self.removed.len() {
+            BlockChangeKind::Split
+        } else if self.added.len() < self.removed.len() {
+            BlockChangeKind::Merge
+        } else {
+            BlockChangeKind::Renumbered
+        }
+    }
+}
+
+/// The blocks where APNs were added or removed, in block order
+pub fn block_changes(diffs: &[ApnDiff]) -> Vec<BlockChange> {
+    let mut blocks: BTreeMap<&str, BlockChange> = BTreeMap::new();
+    for diff in diffs {
+        let is_added = diff.changes.contains(&Change::Added);
+        let is_removed = diff.changes.contains(&Change::Removed);
+        if !is_added && !is_removed {
+            continue;
+        }
+        let block_change = blocks.entry(diff.block()).or_insert_with(|| BlockChange {
+            block: diff.block().to_string(),
+            ..BlockChange::default()
+        });
+        if is_added {
+            block_change.added.push(diff.apn.clone());
+        } else {
+            block_change.removed.push(diff.apn.clone());
+        }
+        block_change.units_change += diff.units_change();
+    }
+    blocks.into_values().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{block, block_changes, diff_roll_years, roll_year_by_apn, BlockChangeKind, Change};
+    use crate::test_util::tax_property;
+    use calamine::DataType;
+
+    #[test]
+    fn test_diff_roll_years() {
+        let s = |s: &str| DataType::String(s.to_string());
+        let f = DataType::Float;
+        let roll = vec![
+            // a flat merged into a single family home
+            tax_property(&[("RP1PRCLID", s("3731 012")), ("ROLLYEAR", f(2017.0)), ("UNITS", f(2.0)), ("RP1CLACDE", s("F")), ("YRBLT", f(1907.0))]),
+            tax_property(&[("RP1PRCLID", s("3731 012")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(1.0)), ("RP1CLACDE", s("D")), ("YRBLT", f(1907.0))]),
+            // an unchanged lot
+            tax_property(&[("RP1PRCLID", s("3731 013")), ("ROLLYEAR", f(2017.0)), ("UNITS", f(1.0))]),
+            tax_property(&[("RP1PRCLID", s("3731 013")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(1.0))]),
+            // a lot mapped into condominiums
+            tax_property(&[("RP1PRCLID", s("3027A014")), ("ROLLYEAR", f(2017.0)), ("UNITS", f(1.0))]),
+            tax_property(&[("RP1PRCLID", s("3027A101")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(1.0))]),
+            tax_property(&[("RP1PRCLID", s("3027A102")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(1.0))]),
+            // replaced by a new building
+            tax_property(&[("RP1PRCLID", s("0001 001")), ("ROLLYEAR", f(2017.0)), ("UNITS", f(0.0)), ("YRBLT", f(1920.0))]),
+            tax_property(&[("RP1PRCLID", s("0001 001")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(8.0)), ("YRBLT", f(2018.0))]),
+        ];
+        let diffs = diff_roll_years(&roll, 2017, 2018);
+        let apns: Vec<&str> = diffs.iter().map(|diff| diff.apn.as_str()).collect();
+        assert_eq!(vec!["0001001", "3027A014", "3027A101", "3027A102", "3731012"], apns);
+        assert_eq!(vec![Change::Units, Change::YearBuilt], diffs[0].changes);
+        assert!(diffs[0].is_new_construction());
+        assert_eq!(vec![Change::Removed], diffs[1].changes);
+        assert_eq!(vec![Change::Units, Change::ClassCode], diffs[4].changes);
+        assert_eq!(-1.0, diffs[4].units_change());
+        assert!(!diffs[4].is_new_construction());
+
+        let blocks = block_changes(&diffs);
+        assert_eq!(1, blocks.len());
+        assert_eq!("3027A", blocks[0].block);
+        assert_eq!(BlockChangeKind::Split, blocks[0].kind());
+        assert_eq!(1.0, blocks[0].units_change);
+    }
+
+    #[test]
+    fn test_duplicate_apn() {
+        let s = |s: &str| DataType::String(s.to_string());
+        let f = DataType::Float;
+        let roll = vec![
+            tax_property(&[("RP1PRCLID", s("3731 012")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(2.0))]),
+            tax_property(&[("RP1PRCLID", s("3731-012")), ("ROLLYEAR", f(2018.0)), ("UNITS", f(3.0))]),
+            tax_property(&[("RP1PRCLID", s("3731 012")), ("ROLLYEAR", f(2017.0)), ("UNITS", f(1.0))]),
+        ];
+        let by_apn = roll_year_by_apn(&roll, 2018);
+        assert_eq!(1, by_apn.len());
+        assert_eq!(Some(2.0), by_apn["3731012"].units);
+    }
+
+    #[test]
+    fn test_block() {
+        assert_eq!("3027A", block("3027A118"));
+        assert_eq!("3731", block("3731012"));
+        assert_eq!("12", block("12"));
+    }
+}
@@ -0,0 +1,575 @@
+//! The columns that each record struct expects, for checking downloaded files before parsing them.
+//!
+//! DataSF renames columns between exports (e.g. `SHAPE_Leng` vs `shape_Length`, `the_geom` vs `shape`).
+//! `HeaderDiff` compares the header of a file to the columns of a `RecordSchema`:
+//! columns whose names differ only in case or punctuation, or that are listed in the
+//! record's alias table, are renamed so that both older and newer exports load;
+//! other missing columns get a suggestion of the most similar extra column.
+//! Only missing columns that the record cannot do without (not `Option` or `#[serde(default)]`)
+//! make a file incompatible.
+
+use calamine::{open_workbook_auto, DataType, Reader};
+use serde::de::value::Error;
+use serde::de::value::StrDeserializer;
+use serde::de::{self, Deserialize, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Visitor};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+/// Other names of a column in older or newer exports, as (column, aliases)
+pub type ColumnAliases = &'static [(&'static str, &'static [&'static str])];
+
+/// A record type that is read from the columns of a file
+pub trait RecordSchema: DeserializeOwned {
+    /// The columns that the record reads
+    fn columns() -> &'static [&'static str] {
+        expected_columns::<Self>()
+    }
+
+    /// Other names that exports of the dataset have used for the columns
+    fn column_aliases() -> ColumnAliases {
+        &[]
+    }
+
+    /// The columns that the record can do without, e.g. `Option` fields
+    fn optional_columns() -> Vec<&'static str> {
+        optional_columns::<Self>(Self::columns())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HeaderError {
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("xlsx error: {0}")]
+    Xlsx(String),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// A Deserializer that only records the field names of the struct that is deserialized from it
+struct FieldNamesDeserializer<'a> {
+    fields: &'a mut &'static [&'static str],
+}
+
+impl<'de, 'a> Deserializer<'de> for FieldNamesDeserializer<'a> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(de::Error::custom("expected a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        *self.fields = fields;
+        Err(de::Error::custom("only reading the field names"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// The columns (serde field names, after renames) that the record struct `T` reads.
+/// Aliases are not included.
+pub fn expected_columns<'de, T: Deserialize<'de>>() -> &'static [&'static str] {
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldNamesDeserializer { fields: &mut fields });
+    fields
+}
+
+/// Error of deserializing a record from `ProbeRecord`
+#[derive(Debug)]
+struct ProbeError {
+    /// the field that the record needed and did not get
+    missing_field: Option<&'static str>,
+    message: String,
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+impl de::Error for ProbeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        ProbeError {
+            missing_field: None,
+            message: msg.to_string(),
+        }
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        ProbeError {
+            missing_field: Some(field),
+            message: format!("missing field `{}`", field),
+        }
+    }
+}
+
+/// Stand-in cell values, tried in turn until the field accepts one
+/// (e.g. dates in the `my_date_format` of PPTS do not accept blanks)
+const PROBE_VALUES: &[&str] = &["", "0", "01/01/2000 12:00:00 AM +0000"];
+
+/// A cell with a stand-in value: the first of `PROBE_VALUES` for strings, and 0 or empty for the rest
+struct ProbeValue(&'static str);
+
+impl<'de> Deserializer<'de> for ProbeValue {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_str(self.0)
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_bool(false)
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_i64(0)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_u64(0)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_f64(0.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_none()
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_seq(de::value::SeqDeserializer::new(std::iter::empty::<&str>()))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ProbeError> {
+        let variant: StrDeserializer<ProbeError> = variants.first().cloned().unwrap_or("").into_deserializer();
+        visitor.visit_enum(variant)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i128 u8 u16 u32 u128 f32 char str string
+        bytes byte_buf unit_struct tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// A record with stand-in values for some of its columns, as (column, index into `PROBE_VALUES`)
+struct ProbeRecord<'a> {
+    columns: &'a [(&'static str, usize)],
+    /// index of the next column
+    next: usize,
+}
+
+impl<'de, 'a> Deserializer<'de> for &mut ProbeRecord<'a> {
+    type Error = ProbeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, ProbeError> {
+        visitor.visit_map(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for ProbeRecord<'a> {
+    type Error = ProbeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, ProbeError> {
+        match self.columns.get(self.next) {
+            Some((column, _)) => {
+                self.next += 1;
+                let key: StrDeserializer<ProbeError> = column.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, ProbeError> {
+        let (_, value) = self.columns[self.next - 1];
+        seed.deserialize(ProbeValue(PROBE_VALUES[value]))
+    }
+}
+
+/// The `columns` of `T` that it can be deserialized without (`Option` and `#[serde(default)]` fields).
+/// Starting with no columns, `T` is deserialized from stand-in values of the columns that it said
+/// were missing until it succeeds. If a field rejects every stand-in value,
+/// all of the columns are taken to be required.
+pub fn optional_columns<T: DeserializeOwned>(columns: &[&'static str]) -> Vec<&'static str> {
+    let mut required: Vec<(&'static str, usize)> = vec![];
+    loop {
+        let mut record = ProbeRecord {
+            columns: &required,
+            next: 0,
+        };
+        let result = T::deserialize(&mut record);
+        let failed_column = record.next.checked_sub(1);
+        match result {
+            Ok(_) => {
+                return columns
+                    .iter()
+                    .filter(|column| !required.iter().any(|(required, _)| required == *column))
+                    .cloned()
+                    .collect()
+            }
+            Err(ProbeError {
+                missing_field: Some(field),
+                ..
+            }) if !required.iter().any(|(required, _)| *required == field) => required.push((field, 0)),
+            Err(_) => match failed_column {
+                Some(i) if required[i].1 + 1 < PROBE_VALUES.len() => required[i].1 += 1,
+                _ => return vec![],
+            },
+        }
+    }
+}
+
+/// The header row of a csv file
+pub fn read_csv_header(path: &Path) -> Result<Vec<String>, HeaderError> {
+    let mut rdr = csv::Reader::from_path(path)?;
+    Ok(rdr.headers()?.iter().map(String::from).collect())
+}
+
+/// The first row of the first sheet of a spreadsheet
+pub fn read_xlsx_header(path: &Path) -> Result<Vec<String>, HeaderError> {
+    let mut workbook = open_workbook_auto(path).map_err(|err| HeaderError::Xlsx(format!("{:?}", err)))?;
+    let sheet_name = workbook
+        .sheet_names()
+        .first()
+        .cloned()
+        .ok_or_else(|| HeaderError::Xlsx("no worksheets".to_string()))?;
+    let sheet = workbook
+        .worksheet_range(&sheet_name)
+        .ok_or_else(|| HeaderError::Xlsx(format!("missing worksheet {}", sheet_name)))?
+        .map_err(|err| HeaderError::Xlsx(format!("{:?}", err)))?;
+    let header = match sheet.rows().next() {
+        Some(row) => row
+            .iter()
+            .map(|cell: &DataType| cell.get_string().unwrap_or("").to_string())
+            .collect(),
+        None => vec![],
+    };
+    Ok(header)
+}
+
+/// Lowercase without punctuation, so that `SHAPE_Area` matches `shape_area` and `ShapeArea`
+fn normalize_column(column: &str) -> String {
+    column
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Minimum Jaro-Winkler similarity of normalized names for an extra column to be suggested
+const MIN_SUGGESTION_SIMILARITY: f64 = 0.8;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissingColumn {
+    pub column: String,
+    /// the most similar extra column in the file
+    pub suggestion: Option<String>,
+}
+
+impl fmt::Display for MissingColumn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.suggestion {
+            Some(suggestion) => write!(f, "{} (did you mean {}?)", self.column, suggestion),
+            None => f.write_str(&self.column),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenamedColumn {
+    /// the name that the record reads
+    pub column: String,
+    /// the name in the file
+    pub actual: String,
+}
+
+/// How the header of a file differs from the columns that a record reads
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderDiff {
+    /// columns that the record cannot do without
+    pub missing: Vec<MissingColumn>,
+    /// optional columns that are read as blank
+    pub missing_optional: Vec<MissingColumn>,
+    /// columns of the file that the record doesn't read
+    pub extra: Vec<String>,
+    /// columns that were found under another name
+    pub renamed: Vec<RenamedColumn>,
+}
+
+impl HeaderDiff {
+    pub fn new(columns: &[&str], optional: &[&str], aliases: ColumnAliases, header: &[String]) -> Self {
+        let mut used: Vec<bool> = header.iter().map(|name| columns.contains(&&**name)).collect();
+        let mut diff = HeaderDiff::default();
+        let mut missing = vec![];
+        for &column in columns {
+            if header.iter().any(|name| name == column) {
+                continue;
+            }
+            let names: Vec<String> = std::iter::once(column)
+                .chain(
+                    aliases
+                        .iter()
+                        .filter(|(aliased, _)| *aliased == column)
+                        .flat_map(|(_, names)| names.iter().cloned()),
+                )
+                .map(normalize_column)
+                .collect();
+            let found = header
+                .iter()
+                .enumerate()
+                .position(|(i, name)| !used[i] && names.contains(&normalize_column(name)));
+            match found {
+                Some(i) => {
+                    used[i] = true;
+                    diff.renamed.push(RenamedColumn {
+                        column: column.to_string(),
+                        actual: header[i].clone(),
+                    });
+                }
+                None => missing.push(column),
+            }
+        }
+        diff.extra = header
+            .iter()
+            .zip(used.iter())
+            .filter(|(_, &used)| !used)
+            .map(|(name, _)| name.clone())
+            .collect();
+        let (missing, missing_optional): (Vec<&str>, Vec<&str>) =
+            missing.into_iter().partition(|column| !optional.contains(column));
+        let suggest = |column: &str| {
+            let normalized = normalize_column(column);
+            let suggestion = diff
+                .extra
+                .iter()
+                .map(|name| (strsim::jaro_winkler(&normalized, &normalize_column(name)), name))
+                .filter(|(similarity, _)| *similarity >= MIN_SUGGESTION_SIMILARITY)
+                .max_by(|(a, _), (b, _)| a.partial_cmp(b).expect("similarity is not NaN"))
+                .map(|(_, name)| name.clone());
+            MissingColumn {
+                column: column.to_string(),
+                suggestion,
+            }
+        };
+        let missing: Vec<MissingColumn> = missing.into_iter().map(&suggest).collect();
+        let missing_optional: Vec<MissingColumn> = missing_optional.into_iter().map(&suggest).collect();
+        diff.missing = missing;
+        diff.missing_optional = missing_optional;
+        diff
+    }
+
+    /// Diff `header` against the columns of `T`
+    pub fn for_record<T: RecordSchema>(header: &[String]) -> Self {
+        HeaderDiff::new(T::columns(), &T::optional_columns(), T::column_aliases(), header)
+    }
+
+    /// True if the header has all the required columns (possibly renamed)
+    pub fn is_compatible(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// True if the header has the record's `column` (possibly renamed)
+    pub fn has_column(&self, column: &str) -> bool {
+        !self
+            .missing
+            .iter()
+            .chain(&self.missing_optional)
+            .any(|missing| missing.column == column)
+    }
+
+    /// `header` with the renamed columns given the names that the record reads
+    pub fn rename_header(&self, header: &[String]) -> Vec<String> {
+        header
+            .iter()
+            .map(|name| {
+                self.renamed
+                    .iter()
+                    .find(|renamed| renamed.actual == *name)
+                    .map(|renamed| renamed.column.clone())
+                    .unwrap_or_else(|| name.clone())
+            })
+            .collect()
+    }
+
+    /// Log the differences that matter: missing columns as warnings, renames as info
+    pub fn log(&self, dataset: &str) {
+        if !self.missing.is_empty() || !self.missing_optional.is_empty() {
+            warn!("{}: {}", dataset, self);
+        } else if !self.renamed.is_empty() {
+            info!("{}: {}", dataset, self);
+        } else if !self.extra.is_empty() {
+            debug!("{}: {}", dataset, self);
+        }
+    }
+}
+
+impl fmt::Display for HeaderDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut parts = vec![];
+        if !self.missing.is_empty() {
+            let missing: Vec<String> = self.missing.iter().map(|missing| missing.to_string()).collect();
+            parts.push(format!("missing columns {}", missing.join(", ")));
+        }
+        if !self.missing_optional.is_empty() {
+            let missing: Vec<String> = self.missing_optional.iter().map(|missing| missing.to_string()).collect();
+            parts.push(format!("missing optional columns {}", missing.join(", ")));
+        }
+        if !self.renamed.is_empty() {
+            let renamed: Vec<String> = self
+                .renamed
+                .iter()
+                .map(|renamed| format!("{} as {}", renamed.column, renamed.actual))
+                .collect();
+            parts.push(format!("renamed columns {}", renamed.join(", ")));
+        }
+        if !self.extra.is_empty() {
+            parts.push(format!("extra columns {}", self.extra.join(", ")));
+        }
+        if parts.is_empty() {
+            f.write_str("header matches")
+        } else {
+            f.write_str(&parts.join("; "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{expected_columns, optional_columns, HeaderDiff, MissingColumn, RecordSchema, RenamedColumn};
+    use crate::sfassessormap::TaxProperty;
+    use crate::sflanduse::LandUseRecord;
+    use crate::sfzoningdistricts::ZoningDistrict;
+
+    fn header(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_expected_columns() {
+        let columns = expected_columns::<ZoningDistrict>();
+        assert!(columns.contains(&"the_geom"));
+        assert!(columns.contains(&"zoning"));
+        assert_eq!(columns, ZoningDistrict::columns());
+    }
+
+    #[test]
+    fn test_header_diff() {
+        let columns = ["OBJECTID", "the_geom", "SHAPE_Leng", "RESUNITS", "YRBUILT"];
+        let aliases: super::ColumnAliases = &[("SHAPE_Leng", &["shape_Length"])];
+        let diff = HeaderDiff::new(
+            &columns,
+            &[],
+            aliases,
+            &header(&["objectid", "the_geom", "shape_Length", "RES_UNITS", "year_built", "globalid"]),
+        );
+        assert_eq!(
+            vec![
+                RenamedColumn { column: "OBJECTID".to_string(), actual: "objectid".to_string() },
+                RenamedColumn { column: "SHAPE_Leng".to_string(), actual: "shape_Length".to_string() },
+                RenamedColumn { column: "RESUNITS".to_string(), actual: "RES_UNITS".to_string() },
+            ],
+            diff.renamed
+        );
+        assert_eq!(
+            vec![MissingColumn { column: "YRBUILT".to_string(), suggestion: Some("year_built".to_string()) }],
+            diff.missing
+        );
+        assert_eq!(header(&["year_built", "globalid"]), diff.extra);
+        assert_eq!(
+            header(&["OBJECTID", "the_geom", "SHAPE_Leng", "RESUNITS", "year_built", "globalid"]),
+            diff.rename_header(&header(&["objectid", "the_geom", "shape_Length", "RES_UNITS", "year_built", "globalid"]))
+        );
+
+        let diff = HeaderDiff::new(&columns, &[], &[], &header(&["OBJECTID", "the_geom", "SHAPE_Length", "RESUNIT", "YRBUILT"]));
+        assert_eq!(Some("RESUNIT".to_string()), diff.missing[1].suggestion);
+        assert_eq!(
+            "missing columns SHAPE_Leng (did you mean SHAPE_Length?), RESUNITS (did you mean RESUNIT?); extra columns SHAPE_Length, RESUNIT",
+            diff.to_string()
+        );
+    }
+
+    #[test]
+    fn test_land_use_aliases() {
+        let mut names: Vec<&str> = LandUseRecord::columns().to_vec();
+        for name in names.iter_mut() {
+            match *name {
+                "SHAPE_Leng" => *name = "shape_Length",
+                "the_geom" => *name = "shape",
+                _ => {}
+            }
+        }
+        let diff = HeaderDiff::for_record::<LandUseRecord>(&header(&names));
+        assert!(diff.is_compatible());
+        assert_eq!(2, diff.renamed.len());
+    }
+
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Permit {
+        id: String,
+        units: Option<u32>,
+        #[serde(default)]
+        notes: String,
+        #[serde(with = "crate::sfplanningacela::lenient_date")]
+        filed: Option<chrono::DateTime<chrono::Utc>>,
+        count: f64,
+    }
+
+    #[test]
+    fn test_optional_columns() {
+        let columns = expected_columns::<Permit>();
+        // fields with a `with` function are required even if they are Options
+        assert_eq!(vec!["units", "notes"], optional_columns::<Permit>(columns));
+        assert_eq!(vec!["FROM_ST", "TO_ST"], LandUseRecord::optional_columns());
+        assert!(TaxProperty::optional_columns().contains(&"RP1PPTVAL"));
+        assert!(!TaxProperty::optional_columns().contains(&"RP1PRCLID"));
+
+        let diff = HeaderDiff::new(columns, &["units", "notes"], &[], &header(&["id", "filed", "count", "unit"]));
+        assert!(diff.is_compatible());
+        assert_eq!(
+            vec![
+                MissingColumn { column: "units".to_string(), suggestion: Some("unit".to_string()) },
+                MissingColumn { column: "notes".to_string(), suggestion: None },
+            ],
+            diff.missing_optional
+        );
+        assert_eq!(
+            "missing optional columns units (did you mean unit?), notes; extra columns unit",
+            diff.to_string()
+        );
+    }
+}
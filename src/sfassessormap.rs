@@ -2,6 +2,20 @@
 //! by SF Office of the Assessor-Recorder
 //! Assessor excel file e.g. 2019.1.15__SF_ASR_Secured_Roll_Data_2017-2018.xlsx
 //! https://sfassessor.org/news-information/property-data-0
+//!
+//! Workbooks that cover several roll years have a sheet per year;
+//! `load_assessor_roll` reads all of them, and `join_land_use` sums one roll year by LandUse parcel.
+
+use chrono::NaiveDate;
+use crate::parcel_join::ParcelKeyIndex;
+use crate::polygon_wrapper::PolygonWrapper;
+use crate::schema::RecordSchema;
+use crate::sflanduse::{normalize_blklot, LandUseRecord};
+use crate::xlsxdeserialize::{SheetRecords, WorkbookError};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TaxProperty {
@@ -28,32 +42,32 @@ pub struct TaxProperty {
     /// Property Class Code
     /// Property type
     #[serde(rename = "RP1CLACDE")]
-    pub rp1clacde: String,
+    pub rp1clacde: ClassCode,
 
     /// Year Property Built
     /// Year improvement was built (can be blend of original and newer constructon)
     #[serde(rename = "YRBLT")]
-    pub yrblt: String,
+    pub yrblt: Option<u32>,
 
     /// Number of Bathrooms
     /// Number of bathrooms (BA with no shower or tub is 1/2 bathroom)
     #[serde(rename = "BATHS")]
-    pub baths: String,
+    pub baths: Option<f64>,
 
     /// Number of Bedrooms
     /// Number of bedrooms (bedrooms have a closet)
     #[serde(rename = "BEDS")]
-    pub beds: String,
+    pub beds: Option<u32>,
 
     /// Number of Rooms
     /// Number of rooms, excluding bathrooms, halls, closets, etc.
     #[serde(rename = "ROOMS")]
-    pub rooms: String,
+    pub rooms: Option<u32>,
 
     /// Number of Stories
     /// Number of stories
     #[serde(rename = "STOREYNO")]
-    pub storeyno: String,
+    pub storeyno: Option<f64>,
 
     /// Number of Units
     /// Number of units
@@ -73,22 +87,22 @@ pub struct TaxProperty {
     /// Lot Depth
     /// Depth of lot in linear feet
     #[serde(rename = "DEPTH")]
-    pub depth: String,
+    pub depth: Option<f64>,
 
     /// Lot Frontage
     /// Linear footage of front facing side of lot (front foot)
     #[serde(rename = "FRONT")]
-    pub front: String,
+    pub front: Option<f64>,
 
     /// Property Area in Square Feet
     /// Same as lot area
     #[serde(rename = "SQFT")]
-    pub sqft: String,
+    pub sqft: Option<f64>,
 
     /// Basement Area
     /// Square footage of basement
     #[serde(rename = "FBA")]
-    pub fba: String,
+    pub fba: Option<f64>,
 
     /// Lot Area
     /// Square footage of lot
@@ -113,7 +127,7 @@ pub struct TaxProperty {
     /// Percent of Ownership
     /// Percent of ownership
     #[serde(rename = "OWNRPRCNT")]
-    pub ownrprcnt: String,
+    pub ownrprcnt: Option<f64>,
 
     /// Closed Roll Exemption Type Code
     /// Exemption Code (see below for descriptions)
@@ -128,17 +142,17 @@ pub struct TaxProperty {
     /// Closed Roll Misc. Exemption Value
     /// Exemptions such as welfare
     #[serde(rename = "RP1EXMVL2")]
-    pub rp1exmvl2: String,
+    pub rp1exmvl2: Option<f64>,
 
     /// Closed Roll Homeowner Exemption Value
     /// Homeowner's exemption
     #[serde(rename = "RP1EXMVL1")]
-    pub rp1exmvl1: String,
+    pub rp1exmvl1: Option<f64>,
 
     /// Closed Roll Year
     /// Roll Year
     #[serde(rename = "ROLLYEAR")]
-    pub rollyear: String,
+    pub rollyear: Option<u32>,
 
     /// Current Sales Date (YYMMDD)
     /// current sale date
@@ -148,72 +162,334 @@ pub struct TaxProperty {
     /// Closed Roll Assessed Fixtures Value
     /// Assessed value of fixtures
     #[serde(rename = "RP1FXTVAL")]
-    pub rp1fxtval: String,
+    pub rp1fxtval: Option<f64>,
 
     /// Closed Roll Assessed Improvement Value
     /// Assessed value of improvements
     #[serde(rename = "RP1IMPVAL")]
-    pub rp1impval: String,
+    pub rp1impval: Option<f64>,
 
     /// Closed Roll Assessed Land Value
     /// Assessed value of land
     #[serde(rename = "RP1LNDVAL")]
-    pub rp1lndval: String,
+    pub rp1lndval: Option<f64>,
 
     /// Closed Roll Assessed Personal Prop Value
     /// Assessed value of personal property
     #[serde(rename = "RP1PPTVAL")]
-    pub rp1pptval: String,
+    pub rp1pptval: Option<f64>,
 }
 
-#[cfg(test)]
-mod test {
-    use super::super::xlsxdeserialize;
-    use super::TaxProperty;
-    use calamine::Reader;
-    use calamine::Xlsx;
-    use std::error::Error;
-    use std::fmt::Display;
-    use std::fmt::Formatter;
-    use std::fs::File;
-    use std::io::BufReader;
-    use std::path::Path;
-
-    // XlsxError does not impl Error
-    #[derive(Debug)]
-    struct WrapXlsxError(calamine::XlsxError);
-    impl Display for WrapXlsxError {
-        fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
-            write!(f, "XlsxError {:?}", self.0)
+impl RecordSchema for TaxProperty {}
+
+impl TaxProperty {
+    /// Normalized block/lot, for joining to `LandUseRecord::blklot`
+    pub fn apn(&self) -> Option<String> {
+        normalize_blklot(&self.rp1prclid)
+    }
+
+    /// Assessed value of land and improvements (fixtures and personal property are not included)
+    pub fn assessed_value(&self) -> f64 {
+        self.rp1lndval.unwrap_or(0.0) + self.rp1impval.unwrap_or(0.0)
+    }
+
+    /// Current sale date (`RECURRSALD`), the last time that the property was reassessed on a change of ownership
+    pub fn last_sale_date(&self) -> Option<NaiveDate> {
+        parse_sale_date(&self.recurrsald, self.rollyear)
+    }
+}
+
+/// A sale date in YYMMDD, or YYYYMMDD. Numeric cells lose the leading zero (e.g. `50612` for 2005-06-12).
+/// Two digit years are in the century that puts them at or before `roll_year`.
+fn parse_sale_date(s: &str, roll_year: Option<u32>) -> Option<NaiveDate> {
+    let s = s.trim();
+    if s.is_empty() || !s.chars().all(|c| c.is_ascii_digit()) || s.chars().all(|c| c == '0') {
+        return None;
+    }
+    let (year, month_day) = match s.len() {
+        8 => (s[..4].parse::<i32>().ok()?, &s[4..]),
+        len if len <= 6 => {
+            let s = format!("{:0>6}", s);
+            let yy = s[..2].parse::<i32>().ok()?;
+            let roll_year = roll_year.map_or(2099, |year| year as i32);
+            let year = if 2000 + yy <= roll_year { 2000 + yy } else { 1900 + yy };
+            return NaiveDate::from_ymd_opt(year, s[2..4].parse().ok()?, s[4..].parse().ok()?);
+        }
+        _ => return None,
+    };
+    NaiveDate::from_ymd_opt(year, month_day[..2].parse().ok()?, month_day[2..].parse().ok()?)
+}
+
+/// Property Class Code (`RP1CLACDE`), the Assessor's use of the property.
+/// Only the common residential codes have variants.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ClassCode {
+    /// D: Dwelling (single family)
+    Dwelling,
+    /// F: Flats and duplex
+    Flats,
+    /// A: Apartments
+    Apartments,
+    /// Z: Condominium
+    Condominium,
+    /// LZ: Live/work condominium
+    LiveWorkCondominium,
+    /// TH: Townhouse
+    Townhouse,
+    /// TIC: Tenancy in common
+    TenancyInCommon,
+    Other(String),
+}
+
+impl ClassCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ClassCode::Dwelling => "D",
+            ClassCode::Flats => "F",
+            ClassCode::Apartments => "A",
+            ClassCode::Condominium => "Z",
+            ClassCode::LiveWorkCondominium => "LZ",
+            ClassCode::Townhouse => "TH",
+            ClassCode::TenancyInCommon => "TIC",
+            ClassCode::Other(s) => s,
         }
     }
-    impl Error for WrapXlsxError {}
-
-    #[allow(unused)]
-    fn scan_assessor<P: AsRef<Path>>(assessor: P) -> Result<(), Box<Error>> {
-        let assessor: &Path = assessor.as_ref();
-        let mut assessor_workbook = calamine::open_workbook::<Xlsx<BufReader<File>>, _>(assessor)
-            .map_err(|e| Box::new(WrapXlsxError(e)))?;
-        info!("Expansions");
-        info!(
-            "Worksheets: {:?}; reading first sheet...",
-            assessor_workbook.sheet_names()
-        );
-        let sheet_name: String = assessor_workbook.sheet_names()[0].clone();
-        let sheet = assessor_workbook
-            .worksheet_range(&sheet_name)
-            .expect("First worksheet should exist")
-            .map_err(|e| Box::new(WrapXlsxError(e)))?;
-        info!("Sheet has size {:?}", sheet.get_size());
-        info!("Used cells: {}", sheet.used_cells().count());
-        let rows = sheet.rows();
-        for row_result in xlsxdeserialize::deserialize::<TaxProperty>(rows) {
-            let row = row_result?;
-            let _units = row.units.unwrap_or(0f64);
-            let _area = row.larea;
-            let _sqft = row.sqft;
+
+    /// Whether the code is one of the residential codes above
+    pub fn is_residential(&self) -> bool {
+        !matches!(self, ClassCode::Other(_))
+    }
+}
+
+impl From<&str> for ClassCode {
+    fn from(s: &str) -> Self {
+        match s.trim() {
+            "D" => ClassCode::Dwelling,
+            "F" => ClassCode::Flats,
+            "A" => ClassCode::Apartments,
+            "Z" => ClassCode::Condominium,
+            "LZ" => ClassCode::LiveWorkCondominium,
+            "TH" => ClassCode::Townhouse,
+            "TIC" => ClassCode::TenancyInCommon,
+            other => ClassCode::Other(other.to_string()),
         }
-        info!("done");
-        Ok(())
+    }
+}
+
+impl fmt::Display for ClassCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ClassCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ClassCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(ClassCode::from(&*s))
+    }
+}
+
+/// All of the rows of every roll sheet of a secured roll workbook.
+/// Sheets without the roll columns (e.g. notes) are skipped.
+pub fn load_assessor_roll(path: &Path) -> Result<Vec<TaxProperty>, WorkbookError> {
+    read_roll(SheetRecords::open(path)?)
+}
+
+/// Read the sheets that have an APN column, so that a roll year whose columns drifted
+/// fails instead of being dropped along with the sheets of notes
+fn read_roll(sheets: SheetRecords<TaxProperty>) -> Result<Vec<TaxProperty>, WorkbookError> {
+    sheets
+        .skip_sheets_without(&["RP1PRCLID"])
+        .map(|result| result.map(|sheet_record| sheet_record.record))
+        .collect()
+}
+
+/// The latest `ROLLYEAR` in the roll
+pub fn latest_roll_year(roll: &[TaxProperty]) -> Option<u32> {
+    roll.iter().filter_map(|property| property.rollyear).max()
+}
+
+/// The properties on one LandUse parcel in one roll year.
+/// A parcel of condominiums has a property for each unit.
+#[derive(Clone)]
+pub struct ParcelAssessment<'a> {
+    pub parcel: &'a PolygonWrapper<LandUseRecord>,
+    pub properties: Vec<&'a TaxProperty>,
+}
+
+impl<'a> ParcelAssessment<'a> {
+    pub fn land_value(&self) -> f64 {
+        self.properties.iter().filter_map(|property| property.rp1lndval).sum()
+    }
+
+    pub fn improvement_value(&self) -> f64 {
+        self.properties.iter().filter_map(|property| property.rp1impval).sum()
+    }
+
+    pub fn assessed_value(&self) -> f64 {
+        self.properties.iter().map(|property| property.assessed_value()).sum()
+    }
+
+    /// Units according to the Assessor, as opposed to `LandUseRecord::resunits`
+    pub fn units(&self) -> f64 {
+        self.properties.iter().filter_map(|property| property.units).sum()
+    }
+}
+
+/// The properties of roll year `roll_year` grouped by the LandUse parcel with the same block/lot
+/// (condominium lots resolve to their parent lot), in block/lot order,
+/// and the properties that are not on any parcel.
+pub fn join_land_use<'a>(
+    roll: &'a [TaxProperty],
+    roll_year: u32,
+    keys: &ParcelKeyIndex<'a>,
+) -> (Vec<ParcelAssessment<'a>>, Vec<&'a TaxProperty>) {
+    let mut by_blklot: BTreeMap<&'a str, ParcelAssessment<'a>> = BTreeMap::new();
+    let mut unmatched = vec![];
+    for property in roll.iter().filter(|property| property.rollyear == Some(roll_year)) {
+        match keys.get(&property.rp1prclid) {
+            Some(parcel) => by_blklot
+                .entry(parcel.value.blklot.as_str())
+                .or_insert_with(|| ParcelAssessment {
+                    parcel,
+                    properties: vec![],
+                })
+                .properties
+                .push(property),
+            None => unmatched.push(property),
+        }
+    }
+    (by_blklot.into_values().collect(), unmatched)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{join_land_use, latest_roll_year, parse_sale_date, read_roll, ClassCode, TaxProperty};
+    use crate::parcel_join::ParcelKeyIndex;
+    use crate::test_util::{land_use, roll_sheet, roll_sheet_without};
+    use crate::spatial_layer::SpatialLayer;
+    use crate::xlsxdeserialize::SheetRecords;
+    use calamine::{Cell, DataType, Range};
+    use chrono::NaiveDate;
+
+    fn roll() -> Vec<TaxProperty> {
+        let s = |s: &str| DataType::String(s.to_string());
+        let range = roll_sheet(&[
+            &[
+                ("RP1PRCLID", s("3731 012")),
+                ("RP1CLACDE", s("Z")),
+                ("YRBLT", DataType::Float(1907.0)),
+                ("BATHS", DataType::Float(1.5)),
+                ("ROLLYEAR", DataType::Float(2018.0)),
+                ("RP1LNDVAL", DataType::Float(400000.0)),
+                ("RP1IMPVAL", DataType::Int(100000)),
+                ("UNITS", DataType::Float(1.0)),
+                ("EXTRA", s("ignored")),
+            ],
+            &[
+                ("RP1PRCLID", s("3731 013")),
+                ("RP1CLACDE", s("Z")),
+                ("ROLLYEAR", DataType::Float(2018.0)),
+                ("RP1LNDVAL", DataType::Float(300000.0)),
+                ("RP1IMPVAL", s(" ")),
+                ("UNITS", DataType::Float(1.0)),
+            ],
+            &[
+                ("RP1PRCLID", s("3731 012")),
+                ("RP1CLACDE", s("Z")),
+                ("ROLLYEAR", DataType::Float(2017.0)),
+                ("RP1LNDVAL", DataType::Float(390000.0)),
+            ],
+            &[
+                ("RP1PRCLID", s("9999 001")),
+                ("RP1CLACDE", s("X")),
+                ("ROLLYEAR", DataType::Float(2018.0)),
+            ],
+        ]);
+        let notes = Range::from_sparse(vec![Cell::new((0, 0), DataType::String("Notes".to_string()))]);
+        let sheets = vec![("Notes".to_string(), notes), ("2018".to_string(), range)];
+        read_roll(SheetRecords::from_ranges(sheets)).expect("roll should parse")
+    }
+
+    #[test]
+    fn test_read_roll() {
+        let roll = roll();
+        assert_eq!(4, roll.len());
+        assert_eq!(Some("3731012".to_string()), roll[0].apn());
+        assert_eq!(ClassCode::Condominium, roll[0].rp1clacde);
+        assert_eq!(Some(1907), roll[0].yrblt);
+        assert_eq!(Some(1.5), roll[0].baths);
+        assert_eq!(None, roll[0].beds);
+        assert_eq!(2500.0, roll[0].larea);
+        assert_eq!(500000.0, roll[0].assessed_value());
+        assert_eq!(None, roll[1].rp1impval);
+        assert_eq!(ClassCode::Other("X".to_string()), roll[3].rp1clacde);
+        assert_eq!(Some(2018), latest_roll_year(&roll));
+
+        let bad = roll_sheet(&[&[("RP1PRCLID", DataType::String("3731 012".to_string())), ("YRBLT", DataType::Float(1907.5))]]);
+        let err = read_roll(SheetRecords::from_ranges(vec![("bad".to_string(), bad)])).expect_err("fractional year");
+        assert!(err.to_string().starts_with("sheet bad row 2 column YRBLT:"), "{}", err);
+    }
+
+    #[test]
+    fn test_read_drifted_roll() {
+        let s = |s: &str| DataType::String(s.to_string());
+        let row = |roll_year: f64| vec![("RP1PRCLID", s("3731 012")), ("ROLLYEAR", DataType::Float(roll_year))];
+        let notes = || Range::from_sparse(vec![Cell::new((0, 0), s("Notes"))]);
+
+        // the 2017 sheet has no personal property column, which is read as blank
+        let sheets = vec![
+            ("Notes".to_string(), notes()),
+            ("2017".to_string(), roll_sheet_without(&["RP1PPTVAL"], &[&row(2017.0)])),
+            ("2018".to_string(), roll_sheet(&[&row(2018.0)])),
+        ];
+        let roll = read_roll(SheetRecords::from_ranges(sheets)).expect("roll should parse");
+        let roll_years: Vec<Option<u32>> = roll.iter().map(|property| property.rollyear).collect();
+        assert_eq!(vec![Some(2017), Some(2018)], roll_years);
+        assert_eq!(None, roll[0].rp1pptval);
+
+        // a roll year without a required column fails instead of being skipped like the notes
+        let sheets = vec![
+            ("Notes".to_string(), notes()),
+            ("2017".to_string(), roll_sheet_without(&["LAREA"], &[&row(2017.0)])),
+        ];
+        let err = read_roll(SheetRecords::from_ranges(sheets)).expect_err("missing LAREA");
+        assert!(err.to_string().contains("LAREA"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_sale_date() {
+        let date = |y, m, d| NaiveDate::from_ymd_opt(y, m, d);
+        assert_eq!(date(2015, 6, 12), parse_sale_date("150612", Some(2018)));
+        assert_eq!(date(2005, 6, 12), parse_sale_date("50612", Some(2018)));
+        assert_eq!(date(1975, 3, 1), parse_sale_date("750301", Some(2018)));
+        assert_eq!(date(2019, 1, 2), parse_sale_date("20190102", Some(2018)));
+        assert_eq!(None, parse_sale_date("0", Some(2018)));
+        assert_eq!(None, parse_sale_date("", Some(2018)));
+        assert_eq!(None, parse_sale_date("151332", Some(2018)));
+    }
+
+    #[test]
+    fn test_join_land_use() {
+        const LOT: &str = "POLYGON ((0 0, 10 0, 10 10, 0 10, 0 0))";
+        let roll = roll();
+        // the condo lot 013 is on the map lot 012
+        let parcels = SpatialLayer::from_polygons(vec![land_use("3731012", "3731012", LOT), land_use("3731013", "3731012", LOT)]);
+        let keys = ParcelKeyIndex::new(&parcels);
+        let (assessments, unmatched) = join_land_use(&roll, 2018, &keys);
+        assert_eq!(1, assessments.len());
+        assert_eq!("3731012", assessments[0].parcel.value.blklot);
+        assert_eq!(2, assessments[0].properties.len());
+        assert_eq!(700000.0, assessments[0].land_value());
+        assert_eq!(800000.0, assessments[0].assessed_value());
+        assert_eq!(2.0, assessments[0].units());
+        assert_eq!(1, unmatched.len());
+        assert_eq!("9999 001", unmatched[0].rp1prclid);
     }
 }
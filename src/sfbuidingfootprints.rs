@@ -4,6 +4,9 @@
 //!
 
 use serde;
+use crate::sflanduse::normalize_blklot;
+use crate::schema::{ColumnAliases, RecordSchema};
+use crate::spatial_layer::HasGeometry;
 
 mod comma_float {
     use serde::{self, Deserialize, Serializer, Deserializer};
@@ -323,9 +326,41 @@ pub struct BuildingFootprintsRecord {
     pub shape: String,  // Multi-Polygon
 }
 
+impl HasGeometry for BuildingFootprintsRecord {
+    fn wkt(&self) -> &str {
+        &self.shape
+    }
+}
+
+impl RecordSchema for BuildingFootprintsRecord {
+    fn column_aliases() -> ColumnAliases {
+        &[("shape", &["the_geom", "geometry"])]
+    }
+}
+
+impl BuildingFootprintsRecord {
+    /// Normalized Assessor block/lot of the parcel that the building is on, taken from `sf_mblr`.
+    /// None if the building is in the right-of-way or the key is missing.
+    pub fn blklot(&self) -> Option<String> {
+        mblr_to_blklot(&self.sf_mblr)
+    }
+}
+
+/// Strip the `SF` prefix and any right-of-way (`ROW…`) suffix from an mblr key,
+/// e.g. `SF4570025` -> `4570025`
+fn mblr_to_blklot(mblr: &str) -> Option<String> {
+    let mblr = mblr.trim().to_ascii_uppercase();
+    let mblr = mblr.strip_prefix("SF").unwrap_or(&mblr);
+    let mblr = match mblr.find("ROW") {
+        Some(i) => &mblr[..i],
+        None => mblr,
+    };
+    normalize_blklot(mblr)
+}
+
 #[cfg(test)]
 mod test {
-    use super::BuildingFootprintsRecord;
+    use super::{mblr_to_blklot, BuildingFootprintsRecord};
     use csv::Reader;
     const TEST_LINES: &str = "\u{feff}sf16_bldgid,area_id,mblr,p2010_name,p2010_zminn88ft,p2010_zmaxn88ft,gnd_cells50cm,gnd_mincm,gnd_maxcm,gnd_rangecm,gnd_meancm,gnd_stdcm,gnd_varietycm,gnd_majoritycm,gnd_minoritycm,gnd_mediancm,cells50cm_1st,mincm_1st,maxcm_1st,rangecm_1st,meancm_1st,stdcm_1st,varietycm_1st,majoritycm_1st,minoritycm_1st,mediancm_1st,hgt_cells50cm,hgt_mincm,hgt_maxcm,hgt_rangecm,hgt_meancm,hgt_stdcm,hgt_varietycm,hgt_majoritycm,hgt_minoritycm,hgt_mediancm,gnd_min_m,median_1st_m,hgt_median_m,gnd1st_delta,peak_1st_m,globalid,shape
 201006.0000001,1,SF4570025,SanfranF_4606.flt,16.3249,66.267099999999999,\"178,250\",507,704,197,595.64277139000001,45.538494300000004,191,643,698,600,\"178,250\",946,\"2,385\",\"1,439\",\"1,532.2886171099999\",225.94217835000001,\"1,204\",\"1,405\",946,\"1,416\",\"178,250\",349,\"1,730\",\"1,381\",936.64459466999995,210.80556354999999,\"1,206\",813,349,850,5.0700000000000003,14.16,8.5,9.0899999999999999,23.850000000000001,{CF7EF595-68E6-4950-B361-CC82D77383A0},\"MULTIPOLYGON (((-122.37950387699998 37.73979945100002, -122.37951078200003 37.739791380999975, -122.37951088699998 37.739791260000004, -122.37942182299997 37.739742509999985, -122.37942180599998 37.73974246400002, -122.38034652099998 37.73871573500003, -122.38043307999999 37.73876573000001, -122.38044047900001 37.73875702700003, -122.38047091 37.738775042999976, -122.38048248100002 37.73876211999999, -122.38274123999997 37.74004335000001, -122.38274126300001 37.740043420999996, -122.38272735599999 37.740058594000004, -122.382815307 37.74010609700002, -122.38281534200001 37.740106208999975, -122.38255868700003 37.740391794999994, -122.38254232799999 37.74038284699998, -122.38253457500002 37.74039098200001, -122.38249726300002 37.74043014, -122.38251599 37.740440433, -122.38251602600002 37.74044054400002, -122.382075531 37.74092975000002, -122.38205777299999 37.74091976599999, -122.38167157499997 37.74134788800001, -122.381388389 37.74118806400003, -122.38133037 37.74119490300001, -122.38128900700002 37.741171742999995, -122.38128896 37.74117160700001, -122.38134406299999 37.74110932299999, -122.379374429 37.739994122999974, -122.37945644299998 37.73990475400001, -122.37949122200001 37.73986609299999, -122.37946334200002 37.73985024699999, -122.37946330400003 37.739850144, -122.37950387699998 37.73979945100002)))\"";
@@ -337,4 +372,13 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_mblr_to_blklot() {
+        assert_eq!(Some("4570025".to_string()), mblr_to_blklot("SF4570025"));
+        assert_eq!(Some("3027A118".to_string()), mblr_to_blklot("SF3027A118"));
+        assert_eq!(Some("4570025".to_string()), mblr_to_blklot("SF4570025ROW1234"));
+        assert_eq!(None, mblr_to_blklot("SFROW1234"));
+        assert_eq!(None, mblr_to_blklot(""));
+    }
 }
@@ -0,0 +1,321 @@
+//! Parser for DBI building permits
+//! https://data.sfgov.org/Housing-and-Buildings/Building-Permits/i86a-77a5
+//! File name: Building_Permits.csv
+//!
+//! PPTS records list their permits in `RELATED_BUILDING_PERMIT`,
+//! so `ProjectPermits` follows a planning project to its permits to see whether it was built.
+//!
+
+use chrono::DateTime;
+use chrono::Utc;
+use crate::schema::RecordSchema;
+use crate::approval_times::days_between;
+use crate::sfplanningacela::Project;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Fields Reference: the Building Permits dataset's column descriptions on data.sfgov.org
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct BuildingPermitRecord {
+    /// e.g. `201505074955`
+    #[serde(rename = "Permit Number")]
+    pub permit_number: String,
+
+    /// 1 to 8, e.g. 1 is new construction and 3 is additions alterations or repairs
+    #[serde(rename = "Permit Type")]
+    pub permit_type: String,
+
+    #[serde(rename = "Permit Type Definition")]
+    pub permit_type_definition: String,
+
+    #[serde(rename = "Block")]
+    pub block: String,
+
+    #[serde(rename = "Lot")]
+    pub lot: String,
+
+    #[serde(rename = "Street Number")]
+    pub street_number: String,
+
+    #[serde(rename = "Street Name")]
+    pub street_name: String,
+
+    #[serde(rename = "Street Suffix")]
+    pub street_suffix: String,
+
+    #[serde(rename = "Description")]
+    pub description: String,
+
+    /// e.g. `filed`, `issued`, `complete`, `expired`, `cancelled`, `withdrawn`
+    #[serde(rename = "Current Status")]
+    pub current_status: String,
+
+    #[serde(rename = "Current Status Date", with = "crate::sfplanningacela::lenient_date")]
+    pub current_status_date: Option<DateTime<Utc>>,
+
+    #[serde(rename = "Filed Date", with = "crate::sfplanningacela::lenient_date")]
+    pub filed_date: Option<DateTime<Utc>>,
+
+    #[serde(rename = "Issued Date", with = "crate::sfplanningacela::lenient_date")]
+    pub issued_date: Option<DateTime<Utc>>,
+
+    #[serde(rename = "Completed Date", with = "crate::sfplanningacela::lenient_date")]
+    pub completed_date: Option<DateTime<Utc>>,
+
+    /// Estimated cost of the work in dollars
+    #[serde(rename = "Estimated Cost", with = "crate::sfplanningacela::lenient_number")]
+    pub estimated_cost: Option<f64>,
+
+    #[serde(rename = "Revised Cost", with = "crate::sfplanningacela::lenient_number")]
+    pub revised_cost: Option<f64>,
+
+    #[serde(rename = "Existing Use")]
+    pub existing_use: String,
+
+    #[serde(rename = "Existing Units", with = "crate::sfplanningacela::lenient_number")]
+    pub existing_units: Option<f64>,
+
+    #[serde(rename = "Proposed Use")]
+    pub proposed_use: String,
+
+    #[serde(rename = "Proposed Units", with = "crate::sfplanningacela::lenient_number")]
+    pub proposed_units: Option<f64>,
+
+    /// `Y` if this is the site permit, which covers the building and is followed by addenda
+    #[serde(rename = "Site Permit", default)]
+    pub site_permit: String,
+}
+
+impl BuildingPermitRecord {
+    /// Whether this permit builds the building: a new construction permit (type 1 or 2) or a site permit
+    pub fn is_construction(&self) -> bool {
+        self.permit_type == "1" || self.permit_type == "2" || self.site_permit == "Y"
+    }
+}
+
+// newer exports use snake_case names (permit_number), which match after normalization
+impl RecordSchema for BuildingPermitRecord {}
+
+/// Building permits by permit number
+pub struct BuildingPermits {
+    permits: HashMap<String, BuildingPermitRecord>,
+}
+
+impl BuildingPermits {
+    pub fn new<I: IntoIterator<Item = BuildingPermitRecord>>(records: I) -> Self {
+        BuildingPermits {
+            permits: records
+                .into_iter()
+                .map(|record| (record.permit_number.clone(), record))
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.permits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.permits.is_empty()
+    }
+
+    pub fn get(&self, permit_number: &str) -> Option<&BuildingPermitRecord> {
+        self.permits.get(permit_number)
+    }
+}
+
+/// How far a project got after it was entitled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// no permit found
+    Entitled,
+    /// a permit was filed but the construction permit has not been issued
+    Filed,
+    /// the construction permit was issued but has not been completed
+    Permitted,
+    /// the construction permit was completed
+    Completed,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 4] = [Stage::Entitled, Stage::Filed, Stage::Permitted, Stage::Completed];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Entitled => "entitled",
+            Stage::Filed => "filed",
+            Stage::Permitted => "permitted",
+            Stage::Completed => "completed",
+        }
+    }
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A project's building permits and how long each step took
+#[derive(Clone, Debug)]
+pub struct ProjectPermits<'a> {
+    pub permits: Vec<&'a BuildingPermitRecord>,
+    /// related permit numbers that are not in the permits file
+    pub missing: Vec<String>,
+    /// stage of the construction permits, so that a completed alteration does not count as built
+    pub stage: Stage,
+    /// first issued date of the construction permits
+    pub first_issued: Option<DateTime<Utc>>,
+    /// last completed date of the construction permits
+    pub last_completed: Option<DateTime<Utc>>,
+    /// days from the approval of the project to the first issued permit
+    pub days_to_issued: Option<i64>,
+    /// days from the first issued permit to the last completed permit
+    pub days_to_completed: Option<i64>,
+    /// largest number of proposed units on any of the permits
+    pub proposed_units: Option<f64>,
+}
+
+impl<'a> ProjectPermits<'a> {
+    pub fn new(project: &Project, permits: &'a BuildingPermits) -> Self {
+        let mut found = vec![];
+        let mut missing = vec![];
+        for number in &project.related_building_permits {
+            match permits.get(number) {
+                Some(permit) => found.push(permit),
+                None => missing.push(number.clone()),
+            }
+        }
+        let construction = construction_permits(&found);
+        let first_issued = construction.iter().filter_map(|permit| permit.issued_date).min();
+        let last_completed = construction.iter().filter_map(|permit| permit.completed_date).max();
+        let stage = if last_completed.is_some() {
+            Stage::Completed
+        } else if first_issued.is_some() {
+            Stage::Permitted
+        } else if !found.is_empty() {
+            Stage::Filed
+        } else {
+            Stage::Entitled
+        };
+        let proposed_units = found
+            .iter()
+            .filter_map(|permit| permit.proposed_units)
+            .fold(None, |max: Option<f64>, units| Some(max.map_or(units, |max| max.max(units))));
+        ProjectPermits {
+            days_to_issued: days_between(project.date_approved, first_issued),
+            days_to_completed: days_between(first_issued, last_completed),
+            permits: found,
+            missing,
+            stage,
+            first_issued,
+            last_completed,
+            proposed_units,
+        }
+    }
+}
+
+/// The permits that build the project: the new construction and site permits,
+/// or else the permit with the most proposed units
+fn construction_permits<'a>(permits: &[&'a BuildingPermitRecord]) -> Vec<&'a BuildingPermitRecord> {
+    let construction: Vec<_> = permits.iter().cloned().filter(|permit| permit.is_construction()).collect();
+    if !construction.is_empty() {
+        return construction;
+    }
+    permits
+        .iter()
+        .cloned()
+        .max_by(|a, b| {
+            let units = |permit: &BuildingPermitRecord| permit.proposed_units.unwrap_or(0.0);
+            units(a).partial_cmp(&units(b)).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .into_iter()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BuildingPermitRecord, BuildingPermits, ProjectPermits, Stage};
+    use crate::sfplanningacela::{permit_numbers, Project, RecordStatus};
+    use csv::Reader;
+    const TEST_LINES: &str = "Permit Number,Permit Type,Permit Type Definition,Permit Creation Date,Block,Lot,Street Number,Street Number Suffix,Street Name,Street Suffix,Unit,Unit Suffix,Description,Current Status,Current Status Date,Filed Date,Issued Date,Completed Date,First Construction Document Date,Structural Notification,Number of Existing Stories,Number of Proposed Stories,Voluntary Soft-Story Retrofit,Fire Only Permit,Permit Expiration Date,Estimated Cost,Revised Cost,Existing Use,Existing Units,Proposed Use,Proposed Units,Plansets,TIDF Compliance,Existing Construction Type,Existing Construction Type Description,Proposed Construction Type,Proposed Construction Type Description,Site Permit,Supervisor District,Neighborhoods - Analysis Boundaries,Zipcode,Location,Record ID
+201505074955,1,new construction,05/07/2015,3731,012,1075,,Folsom,St,,,\"erect 8 stories, 48 dwelling units\",complete,03/01/2019,05/07/2015,07/11/2017,03/01/2019,07/11/2017,,,8,,,,\"1,200,000\",14000000,,,apartments,48,2,,,,1,constr type 1,Y,6,South of Market,94103,\"(37.77 -122.41)\",1381277361298
+201505074956,3,additions alterations or repairs,05/07/2015,3731,012,1075,,Folsom,St,,,revision to 201505074955,issued,07/12/2017,05/07/2015,07/12/2017,,,,,,,,,5000,,,,,,,,,,,,,6,South of Market,94103,,1381277361299
+201801020001,2,new construction wood frame,01/02/2018,3732,001,100,,Harrison,St,,,\"erect 4 stories, 9 dwelling units\",issued,06/01/2018,01/02/2018,06/01/2018,,06/01/2018,,,8,,,,\"1,200,000\",14000000,,,apartments,9,2,,,,1,constr type 1,,6,South of Market,94103,(37.77 -122.41),1381277361300
+201801020002,3,additions alterations or repairs,01/02/2018,3732,001,100,,Harrison,St,,,demolish shed,complete,03/01/2018,01/02/2018,02/01/2018,03/01/2018,,,,,,,,5000,,,,,,,,,,,,,6,South of Market,94103,,1381277361301
+";
+
+    #[test]
+    fn test_parse_record() -> Result<(), csv::Error> {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let records = rdr.deserialize::<BuildingPermitRecord>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!("201505074955", records[0].permit_number);
+        assert_eq!(Some(1200000.0), records[0].estimated_cost);
+        assert_eq!(Some(48.0), records[0].proposed_units);
+        assert!(records[0].completed_date.is_some());
+        assert!(records[1].completed_date.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_permits() -> Result<(), csv::Error> {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let permits = BuildingPermits::new(rdr.deserialize::<BuildingPermitRecord>().collect::<Result<Vec<_>, _>>()?);
+        let approved = "2017-01-11T00:00:00Z".parse().expect("valid date");
+        let project = Project {
+            record_id: "2014.1234PRJ".to_string(),
+            address: "1075 FOLSOM ST".to_string(),
+            description: String::new(),
+            record_status: RecordStatus::Approved,
+            date_opened: approved,
+            date_approved: Some(approved),
+            units_prop: 48.0,
+            net_units: 48.0,
+            affordable_units_prop: 6.0,
+            related_building_permits: permit_numbers("201505074955,201505074956,201599999999"),
+            entitlements: vec![],
+        };
+        let project_permits = ProjectPermits::new(&project, &permits);
+        assert_eq!(2, project_permits.permits.len());
+        assert_eq!(vec!["201599999999".to_string()], project_permits.missing);
+        assert_eq!(Stage::Completed, project_permits.stage);
+        assert_eq!(Some(181), project_permits.days_to_issued);
+        assert_eq!(Some(598), project_permits.days_to_completed);
+        assert_eq!(Some(48.0), project_permits.proposed_units);
+        Ok(())
+    }
+
+    #[test]
+    fn test_completed_alteration() -> Result<(), csv::Error> {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let permits = BuildingPermits::new(rdr.deserialize::<BuildingPermitRecord>().collect::<Result<Vec<_>, _>>()?);
+        let approved = "2017-11-01T00:00:00Z".parse().expect("valid date");
+        let project = Project {
+            record_id: "2017.5678PRJ".to_string(),
+            address: "100 HARRISON ST".to_string(),
+            description: String::new(),
+            record_status: RecordStatus::Approved,
+            date_opened: approved,
+            date_approved: Some(approved),
+            units_prop: 9.0,
+            net_units: 9.0,
+            affordable_units_prop: 0.0,
+            related_building_permits: permit_numbers("201801020001,201801020002"),
+            entitlements: vec![],
+        };
+        let project_permits = ProjectPermits::new(&project, &permits);
+        // the shed demolition was completed but the new building is only issued
+        assert_eq!(Stage::Permitted, project_permits.stage);
+        assert_eq!(None, project_permits.last_completed);
+        assert_eq!(Some(212), project_permits.days_to_issued);
+        assert_eq!(None, project_permits.days_to_completed);
+
+        // a permit issued before the entitlement closed does not give a negative interval
+        let project = Project {
+            date_approved: Some("2018-07-01T00:00:00Z".parse().expect("valid date")),
+            ..project
+        };
+        assert_eq!(None, ProjectPermits::new(&project, &permits).days_to_issued);
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+//! Parser for SF Planning Height and Bulk Districts Map
+//! (“Zoning Map - Height and Bulk Districts” on https://data.sfgov.org)
+//!
+//! Each district is labeled with its height limit in feet and its bulk district letter,
+//! e.g. `40-X` (40 ft., no bulk limit) or `85/130-F` (a podium and a tower height).
+//! Open space (`OS`) districts have no numeric height limit.
+
+use crate::schema::{ColumnAliases, RecordSchema};
+use crate::spatial_layer::{HasGeometry, SpatialLayer};
+use geo::MultiPolygon;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HeightBulkDistrict {
+    pub the_geom: String,
+
+    #[serde(rename = "OBJECTID", alias = "objectid", default)]
+    pub objectid: String,
+
+    /// e.g. `40-X`
+    #[serde(rename = "HEIGHT", alias = "height")]
+    pub height: HeightBulk,
+}
+
+impl HasGeometry for HeightBulkDistrict {
+    fn wkt(&self) -> &str {
+        &self.the_geom
+    }
+}
+
+impl RecordSchema for HeightBulkDistrict {
+    fn column_aliases() -> ColumnAliases {
+        &[("the_geom", &["shape", "geometry"])]
+    }
+}
+
+/// Bulk district (Planning Code Sec. 270), which limits the plan dimensions of the upper stories
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Bulk {
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    R,
+    R2,
+    R3,
+    S,
+    S2,
+    T,
+    V,
+    /// no bulk limit
+    X,
+    Other(String),
+}
+
+impl Bulk {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Bulk::A => "A",
+            Bulk::B => "B",
+            Bulk::C => "C",
+            Bulk::D => "D",
+            Bulk::E => "E",
+            Bulk::F => "F",
+            Bulk::G => "G",
+            Bulk::H => "H",
+            Bulk::I => "I",
+            Bulk::J => "J",
+            Bulk::K => "K",
+            Bulk::L => "L",
+            Bulk::M => "M",
+            Bulk::N => "N",
+            Bulk::R => "R",
+            Bulk::R2 => "R-2",
+            Bulk::R3 => "R-3",
+            Bulk::S => "S",
+            Bulk::S2 => "S-2",
+            Bulk::T => "T",
+            Bulk::V => "V",
+            Bulk::X => "X",
+            Bulk::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for Bulk {
+    fn from(s: &str) -> Self {
+        match s {
+            "A" => Bulk::A,
+            "B" => Bulk::B,
+            "C" => Bulk::C,
+            "D" => Bulk::D,
+            "E" => Bulk::E,
+            "F" => Bulk::F,
+            "G" => Bulk::G,
+            "H" => Bulk::H,
+            "I" => Bulk::I,
+            "J" => Bulk::J,
+            "K" => Bulk::K,
+            "L" => Bulk::L,
+            "M" => Bulk::M,
+            "N" => Bulk::N,
+            "R" => Bulk::R,
+            "R-2" => Bulk::R2,
+            "R-3" => Bulk::R3,
+            "S" => Bulk::S,
+            "S-2" => Bulk::S2,
+            "T" => Bulk::T,
+            "V" => Bulk::V,
+            "X" => Bulk::X,
+            other => Bulk::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Bulk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A height and bulk district label such as `40-X`, parsed
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeightBulk {
+    /// The original label, e.g. `85/130-F`
+    pub label: String,
+    /// The height limit in ft. (the taller one if the label has several); None for open space
+    /// or labels that can't be parsed
+    pub height_limit: Option<f64>,
+    pub bulk: Option<Bulk>,
+}
+
+impl From<&str> for HeightBulk {
+    fn from(label: &str) -> Self {
+        let label = label.trim();
+        let (heights, bulk) = match label.find('-') {
+            Some(i) => (&label[..i], Some(Bulk::from(&label[i + 1..]))),
+            None => (label, None),
+        };
+        let height_limit = heights
+            .split('/')
+            .map(|height| height.trim().parse::<f64>())
+            .collect::<Result<Vec<f64>, _>>()
+            .ok()
+            .and_then(|heights| heights.into_iter().fold(None, |max: Option<f64>, height| {
+                Some(max.map_or(height, |max| max.max(height)))
+            }));
+        HeightBulk {
+            label: label.to_string(),
+            height_limit,
+            bulk,
+        }
+    }
+}
+
+impl fmt::Display for HeightBulk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+impl Serialize for HeightBulk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.label)
+    }
+}
+
+impl<'de> Deserialize<'de> for HeightBulk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(HeightBulk::from(&*s))
+    }
+}
+
+/// How a building's height compares to the height limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HeightStatus {
+    OverHeight,
+    Conforming,
+    /// much shorter than the height limit, so there is room to build up
+    FarUnderHeight,
+}
+
+impl HeightBulk {
+    /// Compare a building height (in ft.) to the height limit.
+    /// The building is over height if it exceeds the limit by more than `tolerance` ft.
+    /// and far under height if it is less than `under_fraction` of the limit.
+    /// None if there is no numeric height limit.
+    pub fn height_status(&self, building_height: f64, tolerance: f64, under_fraction: f64) -> Option<HeightStatus> {
+        self.height_limit.map(|height_limit| {
+            if building_height > height_limit + tolerance {
+                HeightStatus::OverHeight
+            } else if building_height < height_limit * under_fraction {
+                HeightStatus::FarUnderHeight
+            } else {
+                HeightStatus::Conforming
+            }
+        })
+    }
+}
+
+pub fn get_height_limit<'a>(
+    height_bulk_districts: &'a SpatialLayer<HeightBulkDistrict>,
+    shape: &MultiPolygon<f64>,
+) -> Option<&'a HeightBulkDistrict> {
+    height_bulk_districts.at_centroid(shape)
+        .map(|polygon_wrapper| &polygon_wrapper.value)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{get_height_limit, Bulk, HeightBulk, HeightBulkDistrict, HeightStatus};
+    use crate::csv_loader::RowErrorPolicy;
+    use crate::polygon_wrapper::parse_wkt_to_multipolygon;
+    use crate::spatial_layer::SpatialLayer;
+    use csv::Reader;
+
+    const TEST_LINES: &str = "the_geom,OBJECTID,HEIGHT,GEN_HGHT
+\"MULTIPOLYGON (((0 0, 10 0, 10 10, 0 10, 0 0)))\",1,40-X,40
+\"MULTIPOLYGON (((10 0, 20 0, 20 10, 10 10, 10 0)))\",2,85/130-F,130
+\"MULTIPOLYGON (((20 0, 30 0, 30 10, 20 10, 20 0)))\",3,OS,0
+";
+
+    #[test]
+    fn test_parse_label() {
+        let height_bulk = HeightBulk::from("240-S-2");
+        assert_eq!(Some(240.0), height_bulk.height_limit);
+        assert_eq!(Some(Bulk::S2), height_bulk.bulk);
+        assert_eq!("240-S-2", height_bulk.to_string());
+        assert_eq!(Some(130.0), HeightBulk::from("85/130-F").height_limit);
+        let open_space = HeightBulk::from("OS");
+        assert_eq!(None, open_space.height_limit);
+        assert_eq!(None, open_space.bulk);
+    }
+
+    #[test]
+    fn test_height_status() {
+        let height_bulk = HeightBulk::from("40-X");
+        assert_eq!(Some(HeightStatus::OverHeight), height_bulk.height_status(60.0, 16.0, 0.5));
+        assert_eq!(Some(HeightStatus::Conforming), height_bulk.height_status(50.0, 16.0, 0.5));
+        assert_eq!(Some(HeightStatus::FarUnderHeight), height_bulk.height_status(15.0, 16.0, 0.5));
+        assert_eq!(None, HeightBulk::from("OS").height_status(15.0, 16.0, 0.5));
+    }
+
+    #[test]
+    fn test_get_height_limit() {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let layer = SpatialLayer::<HeightBulkDistrict>::load("height_bulk", &mut rdr, &RowErrorPolicy::Fail)
+            .expect("test districts should parse");
+        let building = parse_wkt_to_multipolygon("POLYGON ((12 2, 14 2, 14 4, 12 4, 12 2))")
+            .expect("test building should parse");
+        let district = get_height_limit(&layer, &building).expect("building should be in a district");
+        assert_eq!("85/130-F", district.height.label);
+        assert_eq!(Some(Bulk::F), district.height.bulk);
+    }
+}
@@ -1,3 +1,8 @@
+use crate::schema::{ColumnAliases, RecordSchema};
+use crate::spatial_layer::HasGeometry;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
 /// sf parcels csv file LandUse2016.csv
 /// https://data.sfgov.org/Housing-and-Buildings/Land-Use/us3s-fp9q
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -30,7 +35,7 @@ pub struct LandUseRecord {
     #[serde(rename = "TOTAL_USES")]
     pub total_uses: usize,
     #[serde(rename = "LANDUSE")]
-    pub landuse: String,
+    pub landuse: LandUseCategory,
     #[serde(rename = "CIE")]
     pub cie: usize,
     #[serde(rename = "MED")]
@@ -48,3 +53,190 @@ pub struct LandUseRecord {
     #[serde(rename = "SHAPE_Area")]
     pub shape_area: f64,
 }
+
+impl LandUseRecord {
+    /// Street address range, e.g. `100-102 MAIN ST`
+    pub fn address(&self) -> String {
+        format!(
+            "{}-{} {} {}",
+            self.from_st.unwrap_or_default(),
+            self.to_st.unwrap_or_default(),
+            self.street,
+            self.st_type
+        )
+    }
+}
+
+impl HasGeometry for LandUseRecord {
+    fn wkt(&self) -> &str {
+        &self.the_geom
+    }
+}
+
+impl RecordSchema for LandUseRecord {
+    fn column_aliases() -> ColumnAliases {
+        &[
+            ("the_geom", &["shape", "geometry"]),
+            ("SHAPE_Leng", &["shape_Length", "Shape_Length"]),
+        ]
+    }
+}
+
+/// `LANDUSE` column of LandUse2016.csv
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LandUseCategory {
+    /// RESIDENT: residential only
+    Resident,
+    /// MIXRES: residential mixed with other uses
+    MixRes,
+    /// MIXED: mixed uses without residential
+    Mixed,
+    /// CIE: cultural, institutional, educational
+    Cie,
+    /// MED: medical
+    Med,
+    /// MIPS: office (management, information, professional services)
+    Mips,
+    /// PDR: industrial (production, distribution, repair)
+    Pdr,
+    /// RETAIL/ENT: retail and entertainment
+    RetailEnt,
+    /// VISITOR: hotels
+    Visitor,
+    OpenSpace,
+    Vacant,
+    /// ROW
+    Row,
+    /// Right of Way
+    RightOfWay,
+    /// MISSING DATA
+    MissingData,
+    Other(String),
+}
+
+impl LandUseCategory {
+    pub fn as_str(&self) -> &str {
+        match self {
+            LandUseCategory::Resident => "RESIDENT",
+            LandUseCategory::MixRes => "MIXRES",
+            LandUseCategory::Mixed => "MIXED",
+            LandUseCategory::Cie => "CIE",
+            LandUseCategory::Med => "MED",
+            LandUseCategory::Mips => "MIPS",
+            LandUseCategory::Pdr => "PDR",
+            LandUseCategory::RetailEnt => "RETAIL/ENT",
+            LandUseCategory::Visitor => "VISITOR",
+            LandUseCategory::OpenSpace => "OpenSpace",
+            LandUseCategory::Vacant => "VACANT",
+            LandUseCategory::Row => "ROW",
+            LandUseCategory::RightOfWay => "Right of Way",
+            LandUseCategory::MissingData => "MISSING DATA",
+            LandUseCategory::Other(s) => s,
+        }
+    }
+
+    /// Parcels with housing, including mixed-use buildings with housing
+    pub fn is_residential(&self) -> bool {
+        matches!(self, LandUseCategory::Resident | LandUseCategory::MixRes)
+    }
+
+    /// Streets, which appear as both ROW and Right of Way
+    pub fn is_right_of_way(&self) -> bool {
+        matches!(self, LandUseCategory::Row | LandUseCategory::RightOfWay)
+    }
+}
+
+impl From<&str> for LandUseCategory {
+    fn from(s: &str) -> Self {
+        match s {
+            "RESIDENT" => LandUseCategory::Resident,
+            "MIXRES" => LandUseCategory::MixRes,
+            "MIXED" => LandUseCategory::Mixed,
+            "CIE" => LandUseCategory::Cie,
+            "MED" => LandUseCategory::Med,
+            "MIPS" => LandUseCategory::Mips,
+            "PDR" => LandUseCategory::Pdr,
+            "RETAIL/ENT" => LandUseCategory::RetailEnt,
+            "VISITOR" => LandUseCategory::Visitor,
+            "OpenSpace" => LandUseCategory::OpenSpace,
+            "VACANT" => LandUseCategory::Vacant,
+            "ROW" => LandUseCategory::Row,
+            "Right of Way" => LandUseCategory::RightOfWay,
+            "MISSING DATA" => LandUseCategory::MissingData,
+            other => LandUseCategory::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for LandUseCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for LandUseCategory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for LandUseCategory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(LandUseCategory::from(&*s))
+    }
+}
+
+/// Canonical form of an Assessor block/lot number (e.g. `3027A118`) for joining datasets:
+/// upper case without spaces or separators. None if there is nothing left.
+pub fn normalize_blklot(blklot: &str) -> Option<String> {
+    let normalized: String = blklot
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    if normalized.is_empty() {
+        None
+    } else {
+        Some(normalized)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_blklot, LandUseCategory};
+    use csv::Reader;
+
+    #[test]
+    fn test_normalize_blklot() {
+        assert_eq!(Some("3027A118".to_string()), normalize_blklot("3027a118"));
+        assert_eq!(Some("0549029".to_string()), normalize_blklot(" 0549-029 "));
+        assert_eq!(None, normalize_blklot(""));
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct TestRecord {
+        #[serde(rename = "LANDUSE")]
+        landuse: LandUseCategory,
+    }
+
+    #[test]
+    fn test_land_use_category() -> Result<(), csv::Error> {
+        let lines = "LANDUSE\nMIXRES\nRETAIL/ENT\nRight of Way\nSOMETHING NEW\n";
+        let mut rdr = Reader::from_reader(lines.as_bytes());
+        let categories = rdr.deserialize::<TestRecord>()
+            .map(|result| result.map(|record| record.landuse))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec![
+            LandUseCategory::MixRes,
+            LandUseCategory::RetailEnt,
+            LandUseCategory::RightOfWay,
+            LandUseCategory::Other("SOMETHING NEW".to_string()),
+        ], categories);
+        assert!(categories[0].is_residential());
+        assert!(!categories[1].is_residential());
+        assert!(categories[2].is_right_of_way());
+        assert_eq!("SOMETHING NEW", categories[3].as_str());
+        Ok(())
+    }
+}
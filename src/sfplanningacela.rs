@@ -7,6 +7,11 @@
 
 use chrono::DateTime;
 use chrono::Utc;
+use crate::schema::{ColumnAliases, RecordSchema};
+use crate::spatial_layer::HasGeometry;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
 
 mod my_date_format {
     // see https://serde.rs/custom-date-format.html
@@ -96,14 +101,139 @@ mod optional_date {
         }
     }
 }
+/// Dates of hearings, which may be blank, in the format of `date_opened`, or just a date
+pub(crate) mod lenient_date {
+    use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+    use serde::{self, Deserialize, Serializer, Deserializer};
+    pub fn serialize<S>(
+        date: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        self::super::optional_date::serialize(date, serializer)
+    }
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(None);
+        }
+        use serde::de::IntoDeserializer;
+        let with_time: Result<DateTime<Utc>, serde::de::value::Error> =
+            self::super::my_date_format::deserialize(s.into_deserializer());
+        if let Ok(date) = with_time {
+            return Ok(Some(date));
+        }
+        ["%m/%d/%Y", "%Y-%m-%d"]
+            .iter()
+            .filter_map(|format| NaiveDate::parse_from_str(s, format).ok())
+            .filter_map(|date| date.and_hms_opt(0, 0, 0))
+            .next()
+            .map(|date| Some(Utc.from_utc_datetime(&date)))
+            .ok_or_else(|| serde::de::Error::custom(format!("Failed to parse date: {}", s)))
+    }
+}
+/// Numbers that may be blank or formatted with thousands separators or a dollar sign,
+/// e.g. `1,200` or `$400,000`
+pub(crate) mod lenient_number {
+    use serde::{self, Deserialize, Serializer, Deserializer};
+    pub fn serialize<S>(
+        number: &Option<f64>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        if let &Some(number) = number {
+            serializer.serialize_str(&number.to_string())
+        } else {
+            serializer.serialize_str("")
+        }
+    }
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Option<f64>, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        let digits: String = s.chars().filter(|&c| c != ',' && c != '$' && !c.is_whitespace()).collect();
+        if digits.is_empty() {
+            Ok(None)
+        } else {
+            digits.parse().map(Some).map_err(|e|
+                serde::de::Error::custom(format!("Failed to parse number: {}; string: {}", e, s))
+            )
+        }
+    }
+}
+/// Checkbox columns, which are `CHECKED` when set and blank otherwise
+mod checked_flag {
+    use serde::{self, Deserialize, Serializer, Deserializer};
+    pub fn serialize<S>(
+        flag: &bool,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        serializer.serialize_str(if *flag { "CHECKED" } else { "" })
+    }
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<bool, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        match s.trim().to_ascii_uppercase().as_str() {
+            "CHECKED" | "Y" | "YES" | "TRUE" | "1" => Ok(true),
+            "" | "UNCHECKED" | "N" | "NO" | "FALSE" | "0" => Ok(false),
+            _ => Err(serde::de::Error::custom(format!("Failed to parse flag: {}", s))),
+        }
+    }
+}
+/// Comma-separated record IDs
+mod record_id_list {
+    use serde::{self, Deserialize, Serializer, Deserializer};
+    pub fn serialize<S>(
+        ids: &[String],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+    {
+        serializer.serialize_str(&ids.join(","))
+    }
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<Vec<String>, D::Error>
+        where
+            D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(String::from)
+            .collect())
+    }
+}
 
 /// Fields Reference: http://default.sfplanning.org/GIS/DataSF_PPTS_Fields.xlsx
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PPTSRecord {
     pub the_geom: String,
     /// Estimated construction cost in dollars of the project
-    #[serde(rename = "constructcost")]
-    pub constructcost: String,
+    #[serde(rename = "constructcost", with = "lenient_number")]
+    pub constructcost: Option<f64>,
 
     /// The system ID generated by Esri ArcGIS software. Not an administrative ID.
     #[serde(rename = "OBJECTID")]
@@ -119,7 +249,7 @@ pub struct PPTSRecord {
 
     /// Record status
     #[serde(rename = "record_status")]
-    pub record_status: String,
+    pub record_status: RecordStatus,
 
     /// Date the record was closed
     #[serde(rename = "date_closed", with = "optional_date")]
@@ -135,7 +265,7 @@ pub struct PPTSRecord {
 
     /// Record type category
     #[serde(rename = "record_type_category")]
-    pub record_type_category: String,
+    pub record_type_category: RecordTypeCategory,
 
     /// Record type group
     #[serde(rename = "record_type_group")]
@@ -174,12 +304,12 @@ pub struct PPTSRecord {
     pub templateid: String,
 
     /// Parent record ID if this record is a child of another record
-    #[serde(rename = "parent")]
-    pub parent: String,
+    #[serde(rename = "parent", with = "record_id_list")]
+    pub parent: Vec<String>,
 
     /// The children record IDs if this record is a parent to other records
-    #[serde(rename = "children")]
-    pub children: String,
+    #[serde(rename = "children", with = "record_id_list")]
+    pub children: Vec<String>,
 
     /// Name of the planner assigned to this record
     #[serde(rename = "planner_name")]
@@ -202,92 +332,92 @@ pub struct PPTSRecord {
     pub aalink: String,
 
     /// Project Description - Change of Use
-    #[serde(rename = "CHANGE_OF_USE")]
-    pub change_of_use: String,
+    #[serde(rename = "CHANGE_OF_USE", with = "checked_flag")]
+    pub change_of_use: bool,
 
     /// Project Description - Additions
-    #[serde(rename = "ADDITIONS")]
-    pub additions: String,
+    #[serde(rename = "ADDITIONS", with = "checked_flag")]
+    pub additions: bool,
 
     /// Project Description - New Construction
-    #[serde(rename = "NEW_CONSTRUCTION")]
-    pub new_construction: String,
+    #[serde(rename = "NEW_CONSTRUCTION", with = "checked_flag")]
+    pub new_construction: bool,
 
     /// Project Description - Legislative/Zoning Change
-    #[serde(rename = "LEG_ZONE_CHANGE")]
-    pub leg_zone_change: String,
+    #[serde(rename = "LEG_ZONE_CHANGE", with = "checked_flag")]
+    pub leg_zone_change: bool,
 
     /// Project Description - Demolition
-    #[serde(rename = "DEMOLITION")]
-    pub demolition: String,
+    #[serde(rename = "DEMOLITION", with = "checked_flag")]
+    pub demolition: bool,
 
     /// Project Description - Lot Line Adjustment-Subdivision
-    #[serde(rename = "LOT_LINE_ADJUST")]
-    pub lot_line_adjust: String,
+    #[serde(rename = "LOT_LINE_ADJUST", with = "checked_flag")]
+    pub lot_line_adjust: bool,
 
     /// Project Description - Facade Alterations
-    #[serde(rename = "FACADE_ALT")]
-    pub facade_alt: String,
+    #[serde(rename = "FACADE_ALT", with = "checked_flag")]
+    pub facade_alt: bool,
 
     /// Project Description - ROW Improvements
-    #[serde(rename = "ROW_IMPROVE")]
-    pub row_improve: String,
+    #[serde(rename = "ROW_IMPROVE", with = "checked_flag")]
+    pub row_improve: bool,
 
     /// Project Description - Other
     #[serde(rename = "OTHER_PRJ_DESC")]
     pub other_prj_desc: String,
 
     /// Project Description - Special Needs Housing
-    #[serde(rename = "SPECIAL_NEEDS")]
-    pub special_needs: String,
+    #[serde(rename = "SPECIAL_NEEDS", with = "checked_flag")]
+    pub special_needs: bool,
 
     /// Project Description - Senior Housing
-    #[serde(rename = "SENIOR")]
-    pub senior: String,
+    #[serde(rename = "SENIOR", with = "checked_flag")]
+    pub senior: bool,
 
     /// Project Description - 100% Affordable Housing
-    #[serde(rename = "AFFORDABLE_UNITS")]
-    pub affordable_units: String,
+    #[serde(rename = "AFFORDABLE_UNITS", with = "checked_flag")]
+    pub affordable_units: bool,
 
     /// Project Description - Student Housing
-    #[serde(rename = "STUDENT")]
-    pub student: String,
+    #[serde(rename = "STUDENT", with = "checked_flag")]
+    pub student: bool,
 
     /// Project Description - Inclusionary Housing Required
-    #[serde(rename = "INCLUSIONARY")]
-    pub inclusionary: String,
+    #[serde(rename = "INCLUSIONARY", with = "checked_flag")]
+    pub inclusionary: bool,
 
     /// Project Description - State Density Bonus
-    #[serde(rename = "STATE_DENSITY_BONUS")]
-    pub state_density_bonus: String,
+    #[serde(rename = "STATE_DENSITY_BONUS", with = "checked_flag")]
+    pub state_density_bonus: bool,
 
     /// Project Description - Accessory Dwelling Unit
-    #[serde(rename = "ADU")]
-    pub adu: String,
+    #[serde(rename = "ADU", with = "checked_flag")]
+    pub adu: bool,
 
     /// Project Description - Formula Retail
-    #[serde(rename = "FORMULA_RETAIL")]
-    pub formula_retail: String,
+    #[serde(rename = "FORMULA_RETAIL", with = "checked_flag")]
+    pub formula_retail: bool,
 
     /// Project Description - Medical Cannabis Dispensary
-    #[serde(rename = "MCD")]
-    pub mcd: String,
+    #[serde(rename = "MCD", with = "checked_flag")]
+    pub mcd: bool,
 
     /// Project Description - Tobacco Paraphernalia Est
-    #[serde(rename = "TOBACCO")]
-    pub tobacco: String,
+    #[serde(rename = "TOBACCO", with = "checked_flag")]
+    pub tobacco: bool,
 
     /// Project Description - Financial Services
-    #[serde(rename = "FINANCIAL")]
-    pub financial: String,
+    #[serde(rename = "FINANCIAL", with = "checked_flag")]
+    pub financial: bool,
 
     /// Project Description - Massage Establishment
-    #[serde(rename = "MASSAGE")]
-    pub massage: String,
+    #[serde(rename = "MASSAGE", with = "checked_flag")]
+    pub massage: bool,
 
     /// Project Description - Public Health Review - MCD
-    #[serde(rename = "MCD_REFERRAL")]
-    pub mcd_referral: String,
+    #[serde(rename = "MCD_REFERRAL", with = "checked_flag")]
+    pub mcd_referral: bool,
 
     /// Project Description - Non-Residential Use Type - Other
     #[serde(rename = "OTHER_NON_RES")]
@@ -298,340 +428,340 @@ pub struct PPTSRecord {
     pub environmental_review_type: String,
 
     /// Land Use - Retail/Commercial (sq ft) - Existing
-    #[serde(rename = "LAND_USE_RC_EXIST")]
+    #[serde(rename = "LAND_USE_RC_EXIST", with = "lenient_number")]
     pub land_use_rc_exist: Option<f64>,
 
     /// Land Use - Retail/Commercial (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_RC_PROP")]
+    #[serde(rename = "LAND_USE_RC_PROP", with = "lenient_number")]
     pub land_use_rc_prop: Option<f64>,
 
     /// Land Use - Retail/Commercial (sq ft) - Net
-    #[serde(rename = "LAND_USE_RC_NET")]
+    #[serde(rename = "LAND_USE_RC_NET", with = "lenient_number")]
     pub land_use_rc_net: Option<f64>,
 
     /// Land Use - Residential (sq ft) - Existing
-    #[serde(rename = "LAND_USE_RESIDENTIAL_EXIST")]
+    #[serde(rename = "LAND_USE_RESIDENTIAL_EXIST", with = "lenient_number")]
     pub land_use_residential_exist: Option<f64>,
 
     /// Land Use - Residential (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_RESIDENTIAL_PROP")]
+    #[serde(rename = "LAND_USE_RESIDENTIAL_PROP", with = "lenient_number")]
     pub land_use_residential_prop: Option<f64>,
 
     /// Land Use - Residential (sq ft) - Net
-    #[serde(rename = "LAND_USE_RESIDENTIAL_NET")]
+    #[serde(rename = "LAND_USE_RESIDENTIAL_NET", with = "lenient_number")]
     pub land_use_residential_net: Option<f64>,
 
     /// Land Use - CIE (Cultural, Institutional, Educational) - Existing
-    #[serde(rename = "LAND_USE_CIE_EXIST")]
+    #[serde(rename = "LAND_USE_CIE_EXIST", with = "lenient_number")]
     pub land_use_cie_exist: Option<f64>,
 
     /// Land Use - CIE (Cultural, Institutional, Educational) - Proposed
-    #[serde(rename = "LAND_USE_CIE_PROP")]
+    #[serde(rename = "LAND_USE_CIE_PROP", with = "lenient_number")]
     pub land_use_cie_prop: Option<f64>,
 
     /// Land Use - CIE (Cultural, Institutional, Educational) - Net
-    #[serde(rename = "LAND_USE_CIE_NET")]
+    #[serde(rename = "LAND_USE_CIE_NET", with = "lenient_number")]
     pub land_use_cie_net: Option<f64>,
 
     /// Land Use - Industrial-PDR (sq ft) - Existing
-    #[serde(rename = "LAND_USE_PDR_EXIST")]
+    #[serde(rename = "LAND_USE_PDR_EXIST", with = "lenient_number")]
     pub land_use_pdr_exist: Option<f64>,
 
     /// Land Use - Industrial-PDR (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_PDR_PROP")]
+    #[serde(rename = "LAND_USE_PDR_PROP", with = "lenient_number")]
     pub land_use_pdr_prop: Option<f64>,
 
     /// Land Use - Industrial-PDR (sq ft) - Net
-    #[serde(rename = "LAND_USE_PDR_NET")]
+    #[serde(rename = "LAND_USE_PDR_NET", with = "lenient_number")]
     pub land_use_pdr_net: Option<f64>,
 
     /// Land Use - Office (sq ft) - Existing
-    #[serde(rename = "LAND_USE_OFFICE_EXIST")]
+    #[serde(rename = "LAND_USE_OFFICE_EXIST", with = "lenient_number")]
     pub land_use_office_exist: Option<f64>,
 
     /// Land Use - Office (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_OFFICE_PROP")]
+    #[serde(rename = "LAND_USE_OFFICE_PROP", with = "lenient_number")]
     pub land_use_office_prop: Option<f64>,
 
     /// Land Use - Office (sq ft) - Net
-    #[serde(rename = "LAND_USE_OFFICE_NET")]
+    #[serde(rename = "LAND_USE_OFFICE_NET", with = "lenient_number")]
     pub land_use_office_net: Option<f64>,
 
     /// Land Use - Medical (sq ft) - Existing
-    #[serde(rename = "LAND_USE_MEDICAL_EXIST")]
+    #[serde(rename = "LAND_USE_MEDICAL_EXIST", with = "lenient_number")]
     pub land_use_medical_exist: Option<f64>,
 
     /// Land Use - Medical (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_MEDICAL_PROP")]
+    #[serde(rename = "LAND_USE_MEDICAL_PROP", with = "lenient_number")]
     pub land_use_medical_prop: Option<f64>,
 
     /// Land Use - Medical (sq ft) - Net
-    #[serde(rename = "LAND_USE_MEDICAL_NET")]
+    #[serde(rename = "LAND_USE_MEDICAL_NET", with = "lenient_number")]
     pub land_use_medical_net: Option<f64>,
 
     /// Land Use - Visitor (sq ft) - Existing
-    #[serde(rename = "LAND_USE_VISITOR_EXIST")]
+    #[serde(rename = "LAND_USE_VISITOR_EXIST", with = "lenient_number")]
     pub land_use_visitor_exist: Option<f64>,
 
     /// Land Use - Visitor (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_VISITOR_PROP")]
+    #[serde(rename = "LAND_USE_VISITOR_PROP", with = "lenient_number")]
     pub land_use_visitor_prop: Option<f64>,
 
     /// Land Use - Visitor (sq ft) - Net
-    #[serde(rename = "LAND_USE_VISITOR_NET")]
+    #[serde(rename = "LAND_USE_VISITOR_NET", with = "lenient_number")]
     pub land_use_visitor_net: Option<f64>,
 
     /// Land Use - Parking Spaces (sq ft) - Existing
-    #[serde(rename = "LAND_USE_PARKING_SPACES_EXIST")]
+    #[serde(rename = "LAND_USE_PARKING_SPACES_EXIST", with = "lenient_number")]
     pub land_use_parking_spaces_exist: Option<f64>,
 
     /// Land Use - Parking Spaces (sq ft) - Proposed
-    #[serde(rename = "LAND_USE_PARKING_SPACES_PROP")]
+    #[serde(rename = "LAND_USE_PARKING_SPACES_PROP", with = "lenient_number")]
     pub land_use_parking_spaces_prop: Option<f64>,
 
     /// Land Use - Parking Spaces (sq ft) - Net
-    #[serde(rename = "LAND_USE_PARKING_SPACES_NET")]
+    #[serde(rename = "LAND_USE_PARKING_SPACES_NET", with = "lenient_number")]
     pub land_use_parking_spaces_net: Option<f64>,
 
     /// Project Features - Dwelling Units-Affordable - Existing Unit(s)
-    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_EXIST")]
+    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_EXIST", with = "lenient_number")]
     pub prj_feature_affordable_exist: Option<f64>,
 
     /// Project Features - Dwelling Units-Affordable - Proposed Unit(s)
-    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_PROP")]
+    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_PROP", with = "lenient_number")]
     pub prj_feature_affordable_prop: Option<f64>,
 
     /// Project Features - Dwelling Units-Affordable - Net Unit(s)
-    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_NET")]
+    #[serde(rename = "PRJ_FEATURE_AFFORDABLE_NET", with = "lenient_number")]
     pub prj_feature_affordable_net: Option<f64>,
 
     /// Project Features - Hotel Rooms - Existing
-    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_EXIST")]
-    pub prj_feature_hotel_rooms_exist: String,
+    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_EXIST", with = "lenient_number")]
+    pub prj_feature_hotel_rooms_exist: Option<f64>,
 
     /// Project Features - Hotel Rooms - Proposed
-    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_PROP")]
-    pub prj_feature_hotel_rooms_prop: String,
+    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_PROP", with = "lenient_number")]
+    pub prj_feature_hotel_rooms_prop: Option<f64>,
 
     /// Project Features - Hotel Rooms - Net
-    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_NET")]
-    pub prj_feature_hotel_rooms_net: String,
+    #[serde(rename = "PRJ_FEATURE_HOTEL_ROOMS_NET", with = "lenient_number")]
+    pub prj_feature_hotel_rooms_net: Option<f64>,
 
     /// Project Features - Dwelling Units-Market Rate - Existing Unit(s)
-    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_EXIST")]
+    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_EXIST", with = "lenient_number")]
     pub prj_feature_market_rate_exist: Option<f64>,
 
     /// Project Features - Dwelling Units-Market Rate - Proposed Unit(s)
-    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_PROP")]
+    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_PROP", with = "lenient_number")]
     pub prj_feature_market_rate_prop: Option<f64>,
 
     /// Project Features - Dwelling Units-Market Rate - Net Unit(s)
-    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_NET")]
+    #[serde(rename = "PRJ_FEATURE_MARKET_RATE_NET", with = "lenient_number")]
     pub prj_feature_market_rate_net: Option<f64>,
 
     /// Project Features - Building Number - Existing
-    #[serde(rename = "PRJ_FEATURE_BUILD_EXIST")]
-    pub prj_feature_build_exist: String,
+    #[serde(rename = "PRJ_FEATURE_BUILD_EXIST", with = "lenient_number")]
+    pub prj_feature_build_exist: Option<f64>,
 
     /// Project Features - Building Number - Proposed
-    #[serde(rename = "PRJ_FEATURE_BUILD_PROP")]
-    pub prj_feature_build_prop: String,
+    #[serde(rename = "PRJ_FEATURE_BUILD_PROP", with = "lenient_number")]
+    pub prj_feature_build_prop: Option<f64>,
 
     /// Project Features - Building Number - Net
-    #[serde(rename = "PRJ_FEATURE_BUILD_NET")]
+    #[serde(rename = "PRJ_FEATURE_BUILD_NET", with = "lenient_number")]
     pub prj_feature_build_net: Option<f64>,
 
     /// Project Features - Stories Number - Existing
-    #[serde(rename = "PRJ_FEATURE_STORIES_PROP")]
+    #[serde(rename = "PRJ_FEATURE_STORIES_PROP", with = "lenient_number")]
     pub prj_feature_stories_prop: Option<f64>,
 
     /// Project Features - Stories Number - Proposed
-    #[serde(rename = "PRJ_FEATURE_STORIES_NET")]
+    #[serde(rename = "PRJ_FEATURE_STORIES_NET", with = "lenient_number")]
     pub prj_feature_stories_net: Option<f64>,
 
     /// Project Features - Stories Number - Net
-    #[serde(rename = "PRJ_FEATURE_PARKING_EXIST")]
-    pub prj_feature_parking_exist: String,
+    #[serde(rename = "PRJ_FEATURE_PARKING_EXIST", with = "lenient_number")]
+    pub prj_feature_parking_exist: Option<f64>,
 
     /// Project Features - Parking Spaces - Proposed
-    #[serde(rename = "PRJ_FEATURE_PARKING_PROP")]
-    pub prj_feature_parking_prop: String,
+    #[serde(rename = "PRJ_FEATURE_PARKING_PROP", with = "lenient_number")]
+    pub prj_feature_parking_prop: Option<f64>,
 
     /// Project Features - Parking Spaces - Net
-    #[serde(rename = "PRJ_FEATURE_PARKING_NET")]
-    pub prj_feature_parking_net: String,
+    #[serde(rename = "PRJ_FEATURE_PARKING_NET", with = "lenient_number")]
+    pub prj_feature_parking_net: Option<f64>,
 
     /// Project Features - Loading Spaces - Existing
-    #[serde(rename = "PRJ_FEATURE_LOADING_EXIST")]
-    pub prj_feature_loading_exist: String,
+    #[serde(rename = "PRJ_FEATURE_LOADING_EXIST", with = "lenient_number")]
+    pub prj_feature_loading_exist: Option<f64>,
 
     /// Project Features - Loading Spaces - Proposed
-    #[serde(rename = "PRJ_FEATURE_LOADING_PROP")]
-    pub prj_feature_loading_prop: String,
+    #[serde(rename = "PRJ_FEATURE_LOADING_PROP", with = "lenient_number")]
+    pub prj_feature_loading_prop: Option<f64>,
 
     /// Project Features - Loading Spaces - Net
-    #[serde(rename = "PRJ_FEATURE_LOADING_NET")]
-    pub prj_feature_loading_net: String,
+    #[serde(rename = "PRJ_FEATURE_LOADING_NET", with = "lenient_number")]
+    pub prj_feature_loading_net: Option<f64>,
 
     /// Project Features - Bicycle Spaces - Existing
-    #[serde(rename = "PRJ_FEATURE_BIKE_EXIST")]
-    pub prj_feature_bike_exist: String,
+    #[serde(rename = "PRJ_FEATURE_BIKE_EXIST", with = "lenient_number")]
+    pub prj_feature_bike_exist: Option<f64>,
 
     /// Project Features - Bicycle Spaces - Proposed
-    #[serde(rename = "PRJ_FEATURE_BIKE_PROP")]
-    pub prj_feature_bike_prop: String,
+    #[serde(rename = "PRJ_FEATURE_BIKE_PROP", with = "lenient_number")]
+    pub prj_feature_bike_prop: Option<f64>,
 
     /// Project Features - Bicycle Spaces - Net
-    #[serde(rename = "PRJ_FEATURE_BIKE_NET")]
-    pub prj_feature_bike_net: String,
+    #[serde(rename = "PRJ_FEATURE_BIKE_NET", with = "lenient_number")]
+    pub prj_feature_bike_net: Option<f64>,
 
     /// Project Features - Car Share Spaces - Existing
-    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_EXIST")]
-    pub prj_feature_car_share_exist: String,
+    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_EXIST", with = "lenient_number")]
+    pub prj_feature_car_share_exist: Option<f64>,
 
     /// Project Features - Car Share Spaces - Proposed
-    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_PROP")]
-    pub prj_feature_car_share_prop: String,
+    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_PROP", with = "lenient_number")]
+    pub prj_feature_car_share_prop: Option<f64>,
 
     /// Project Features - Car Share Spaces - Net
-    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_NET")]
-    pub prj_feature_car_share_net: String,
+    #[serde(rename = "PRJ_FEATURE_CAR_SHARE_NET", with = "lenient_number")]
+    pub prj_feature_car_share_net: Option<f64>,
 
     /// Project Features - Usable Open Spaces - Existing
-    #[serde(rename = "PRJ_FEATURE_USABLE_EXIST")]
-    pub prj_feature_usable_exist: String,
+    #[serde(rename = "PRJ_FEATURE_USABLE_EXIST", with = "lenient_number")]
+    pub prj_feature_usable_exist: Option<f64>,
 
     /// Project Features - Usable Open Spaces - Proposed
-    #[serde(rename = "PRJ_FEATURE_USABLE_PROP")]
-    pub prj_feature_usable_prop: String,
+    #[serde(rename = "PRJ_FEATURE_USABLE_PROP", with = "lenient_number")]
+    pub prj_feature_usable_prop: Option<f64>,
 
     /// Project Features - Usable Open Spaces - Existing
-    #[serde(rename = "PRJ_FEATURE_USABLE_NET")]
-    pub prj_feature_usable_net: String,
+    #[serde(rename = "PRJ_FEATURE_USABLE_NET", with = "lenient_number")]
+    pub prj_feature_usable_net: Option<f64>,
 
     /// Project Features - Public Open Space - Existing
-    #[serde(rename = "PRJ_FEATURE_PUBLIC_EXIST")]
-    pub prj_feature_public_exist: String,
+    #[serde(rename = "PRJ_FEATURE_PUBLIC_EXIST", with = "lenient_number")]
+    pub prj_feature_public_exist: Option<f64>,
 
     /// Project Features - Public Open Space - Proposed
-    #[serde(rename = "PRJ_FEATURE_PUBLIC_PROP")]
-    pub prj_feature_public_prop: String,
+    #[serde(rename = "PRJ_FEATURE_PUBLIC_PROP", with = "lenient_number")]
+    pub prj_feature_public_prop: Option<f64>,
 
     /// Project Features - Public Open Space - Net
-    #[serde(rename = "PRJ_FEATURE_PUBLIC_NET")]
-    pub prj_feature_public_net: String,
+    #[serde(rename = "PRJ_FEATURE_PUBLIC_NET", with = "lenient_number")]
+    pub prj_feature_public_net: Option<f64>,
 
     /// Project Features - Public Art - Existing
-    #[serde(rename = "PRJ_FEATURE_ART_EXIST")]
-    pub prj_feature_art_exist: String,
+    #[serde(rename = "PRJ_FEATURE_ART_EXIST", with = "lenient_number")]
+    pub prj_feature_art_exist: Option<f64>,
 
     /// Project Features - Public Art - Proposed
-    #[serde(rename = "PRJ_FEATURE_ART_PROP")]
-    pub prj_feature_art_prop: String,
+    #[serde(rename = "PRJ_FEATURE_ART_PROP", with = "lenient_number")]
+    pub prj_feature_art_prop: Option<f64>,
 
     /// Project Features - Public Art - Net
-    #[serde(rename = "PRJ_FEATURE_ART_NET")]
-    pub prj_feature_art_net: String,
+    #[serde(rename = "PRJ_FEATURE_ART_NET", with = "lenient_number")]
+    pub prj_feature_art_net: Option<f64>,
 
     /// Project Features - Better Roof - Total Roof Area - Existing
-    #[serde(rename = "PRJ_FEATURE_ROOF_EXIST")]
-    pub prj_feature_roof_exist: String,
+    #[serde(rename = "PRJ_FEATURE_ROOF_EXIST", with = "lenient_number")]
+    pub prj_feature_roof_exist: Option<f64>,
 
     /// Project Features - Better Roof - Total Roof Area - Propsoed
-    #[serde(rename = "PRJ_FEATURE_ROOF_PROP")]
-    pub prj_feature_roof_prop: String,
+    #[serde(rename = "PRJ_FEATURE_ROOF_PROP", with = "lenient_number")]
+    pub prj_feature_roof_prop: Option<f64>,
 
     /// Project Features - Better Roof - Total Roof Area - Net
-    #[serde(rename = "PRJ_FEATURE_ROOF_NET")]
-    pub prj_feature_roof_net: String,
+    #[serde(rename = "PRJ_FEATURE_ROOF_NET", with = "lenient_number")]
+    pub prj_feature_roof_net: Option<f64>,
 
     /// Project Features - Better Roof - Solar Area - Existing
-    #[serde(rename = "PRJ_FEATURE_SOLAR_EXIST")]
-    pub prj_feature_solar_exist: String,
+    #[serde(rename = "PRJ_FEATURE_SOLAR_EXIST", with = "lenient_number")]
+    pub prj_feature_solar_exist: Option<f64>,
 
     /// Project Features - Better Roof - Solar Area - Proposed
-    #[serde(rename = "PRJ_FEATURE_SOLAR_PROP")]
-    pub prj_feature_solar_prop: String,
+    #[serde(rename = "PRJ_FEATURE_SOLAR_PROP", with = "lenient_number")]
+    pub prj_feature_solar_prop: Option<f64>,
 
     /// Project Features - Better Roof - Solar Area - Net
-    #[serde(rename = "PRJ_FEATURE_SOLAR_NET")]
-    pub prj_feature_solar_net: String,
+    #[serde(rename = "PRJ_FEATURE_SOLAR_NET", with = "lenient_number")]
+    pub prj_feature_solar_net: Option<f64>,
 
     /// Project Features - Better Roof - Living Roof Area - Existing
-    #[serde(rename = "PRJ_FEATURE_LIVING_EXIST")]
-    pub prj_feature_living_exist: String,
+    #[serde(rename = "PRJ_FEATURE_LIVING_EXIST", with = "lenient_number")]
+    pub prj_feature_living_exist: Option<f64>,
 
     /// Project Features - Better Roof - Living Roof Area - Proposed
-    #[serde(rename = "PRJ_FEATURE_LIVING_PROP")]
-    pub prj_feature_living_prop: String,
+    #[serde(rename = "PRJ_FEATURE_LIVING_PROP", with = "lenient_number")]
+    pub prj_feature_living_prop: Option<f64>,
 
     /// Project Features - Better Roof - Living Roof Area - Net
-    #[serde(rename = "PRJ_FEATURE_LIVING_NET")]
-    pub prj_feature_living_net: String,
+    #[serde(rename = "PRJ_FEATURE_LIVING_NET", with = "lenient_number")]
+    pub prj_feature_living_net: Option<f64>,
 
     /// Project Features - Other Project Feature
     #[serde(rename = "PRJ_FEATURE_OTHER")]
     pub prj_feature_other: String,
 
     /// Project Features - Other Project Feature - Existing Unit(s)
-    #[serde(rename = "PRJ_FEATURE_OTHER_EXIST")]
-    pub prj_feature_other_exist: String,
+    #[serde(rename = "PRJ_FEATURE_OTHER_EXIST", with = "lenient_number")]
+    pub prj_feature_other_exist: Option<f64>,
 
     /// Project Features - Other Project Feature - Proposed Unit(s)
-    #[serde(rename = "PRJ_FEATURE_OTHER_PROP")]
-    pub prj_feature_other_prop: String,
+    #[serde(rename = "PRJ_FEATURE_OTHER_PROP", with = "lenient_number")]
+    pub prj_feature_other_prop: Option<f64>,
 
     /// Project Features - Other Project Feature - Net Unit(s)
-    #[serde(rename = "PRJ_FEATURE_OTHER_NET")]
-    pub prj_feature_other_net: String,
+    #[serde(rename = "PRJ_FEATURE_OTHER_NET", with = "lenient_number")]
+    pub prj_feature_other_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Studios - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_STUDIO_EXIST")]
-    pub residential_studio_exist: String,
+    #[serde(rename = "RESIDENTIAL_STUDIO_EXIST", with = "lenient_number")]
+    pub residential_studio_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Studios - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_STUDIO_PROP")]
-    pub residential_studio_prop: String,
+    #[serde(rename = "RESIDENTIAL_STUDIO_PROP", with = "lenient_number")]
+    pub residential_studio_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Studios - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_STUDIO_NET")]
-    pub residential_studio_net: String,
+    #[serde(rename = "RESIDENTIAL_STUDIO_NET", with = "lenient_number")]
+    pub residential_studio_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 1 Bedroom - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_1BR_EXIST")]
-    pub residential_1br_exist: String,
+    #[serde(rename = "RESIDENTIAL_1BR_EXIST", with = "lenient_number")]
+    pub residential_1br_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 1 Bedroom - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_1BR_PROP")]
-    pub residential_1br_prop: String,
+    #[serde(rename = "RESIDENTIAL_1BR_PROP", with = "lenient_number")]
+    pub residential_1br_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 1 Bedroom - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_1BR_NET")]
-    pub residential_1br_net: String,
+    #[serde(rename = "RESIDENTIAL_1BR_NET", with = "lenient_number")]
+    pub residential_1br_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 2 Bedroom - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_2BR_EXIST")]
-    pub residential_2br_exist: String,
+    #[serde(rename = "RESIDENTIAL_2BR_EXIST", with = "lenient_number")]
+    pub residential_2br_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 2 Bedroom - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_2BR_PROP")]
-    pub residential_2br_prop: String,
+    #[serde(rename = "RESIDENTIAL_2BR_PROP", with = "lenient_number")]
+    pub residential_2br_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 2 Bedroom - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_2BR_NET")]
-    pub residential_2br_net: String,
+    #[serde(rename = "RESIDENTIAL_2BR_NET", with = "lenient_number")]
+    pub residential_2br_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 3+ Bedroom - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_3BR_EXIST")]
-    pub residential_3br_exist: String,
+    #[serde(rename = "RESIDENTIAL_3BR_EXIST", with = "lenient_number")]
+    pub residential_3br_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 3+ Bedroom - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_3BR_PROP")]
-    pub residential_3br_prop: String,
+    #[serde(rename = "RESIDENTIAL_3BR_PROP", with = "lenient_number")]
+    pub residential_3br_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - 3+ Bedroom - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_3BR_NET")]
-    pub residential_3br_net: String,
+    #[serde(rename = "RESIDENTIAL_3BR_NET", with = "lenient_number")]
+    pub residential_3br_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Accessory Dwelling Unit Studio - Existing Unit(s)
     #[serde(rename = "RESIDENTIAL_ADU_STUDIO_EXIST")]
@@ -698,95 +828,709 @@ pub struct PPTSRecord {
     pub residential_adu_3br_area: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Rooms - Existing
-    #[serde(rename = "RESIDENTIAL_GH_ROOMS_EXIST")]
-    pub residential_gh_rooms_exist: String,
+    #[serde(rename = "RESIDENTIAL_GH_ROOMS_EXIST", with = "lenient_number")]
+    pub residential_gh_rooms_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Rooms - Prosed
-    #[serde(rename = "RESIDENTIAL_GH_ROOMS_PROP")]
-    pub residential_gh_rooms_prop: String,
+    #[serde(rename = "RESIDENTIAL_GH_ROOMS_PROP", with = "lenient_number")]
+    pub residential_gh_rooms_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Rooms - Net
-    #[serde(rename = "RESIDENTIAL_GH_ROOMS_NET")]
-    pub residential_gh_rooms_net: String,
+    #[serde(rename = "RESIDENTIAL_GH_ROOMS_NET", with = "lenient_number")]
+    pub residential_gh_rooms_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Beds - Existing
-    #[serde(rename = "RESIDENTIAL_GH_BEDS_EXIST")]
-    pub residential_gh_beds_exist: String,
+    #[serde(rename = "RESIDENTIAL_GH_BEDS_EXIST", with = "lenient_number")]
+    pub residential_gh_beds_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Beds - Prosed
-    #[serde(rename = "RESIDENTIAL_GH_BEDS_PROP")]
-    pub residential_gh_beds_prop: String,
+    #[serde(rename = "RESIDENTIAL_GH_BEDS_PROP", with = "lenient_number")]
+    pub residential_gh_beds_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Group Housing - Beds - Net
-    #[serde(rename = "RESIDENTIAL_GH_BEDS_NET")]
-    pub residential_gh_beds_net: String,
+    #[serde(rename = "RESIDENTIAL_GH_BEDS_NET", with = "lenient_number")]
+    pub residential_gh_beds_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - SRO - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_SRO_EXIST")]
-    pub residential_sro_exist: String,
+    #[serde(rename = "RESIDENTIAL_SRO_EXIST", with = "lenient_number")]
+    pub residential_sro_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - SRO - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_SRO_PROP")]
-    pub residential_sro_prop: String,
+    #[serde(rename = "RESIDENTIAL_SRO_PROP", with = "lenient_number")]
+    pub residential_sro_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - SRO - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_SRO_NET")]
-    pub residential_sro_net: String,
+    #[serde(rename = "RESIDENTIAL_SRO_NET", with = "lenient_number")]
+    pub residential_sro_net: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Micro - Existing Unit(s)
-    #[serde(rename = "RESIDENTIAL_MICRO_EXIST")]
-    pub residential_micro_exist: String,
+    #[serde(rename = "RESIDENTIAL_MICRO_EXIST", with = "lenient_number")]
+    pub residential_micro_exist: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Micro - Proposed Unit(s)
-    #[serde(rename = "RESIDENTIAL_MICRO_PROP")]
-    pub residential_micro_prop: String,
+    #[serde(rename = "RESIDENTIAL_MICRO_PROP", with = "lenient_number")]
+    pub residential_micro_prop: Option<f64>,
 
     /// Land Use - Residential - Dwelling Unit Type - Micro - Net Unit(s)
-    #[serde(rename = "RESIDENTIAL_MICRO_NET")]
-    pub residential_micro_net: String,
+    #[serde(rename = "RESIDENTIAL_MICRO_NET", with = "lenient_number")]
+    pub residential_micro_net: Option<f64>,
 
     /// Related Building Permit Number
     #[serde(rename = "RELATED_BUILDING_PERMIT")]
     pub related_building_permit: String,
 
     /// Full Board Hearing Date 1
-    #[serde(rename = "BOS_1ST_READ")]
-    pub bos_1st_read: String,
+    #[serde(rename = "BOS_1ST_READ", with = "lenient_date")]
+    pub bos_1st_read: Option<DateTime<Utc>>,
 
     /// Full Board Hearing Date 2
-    #[serde(rename = "BOS_2ND_READ")]
-    pub bos_2nd_read: String,
+    #[serde(rename = "BOS_2ND_READ", with = "lenient_date")]
+    pub bos_2nd_read: Option<DateTime<Utc>>,
 
     /// Committee Hearing Date
-    #[serde(rename = "COM_HEARING")]
-    pub com_hearing: String,
+    #[serde(rename = "COM_HEARING", with = "lenient_date")]
+    pub com_hearing: Option<DateTime<Utc>>,
 
     /// Mayoral Action - Ordinance Signed Date
-    #[serde(rename = "MAYORAL_SIGN")]
-    pub mayoral_sign: String,
+    #[serde(rename = "MAYORAL_SIGN", with = "lenient_date")]
+    pub mayoral_sign: Option<DateTime<Utc>>,
 
     /// Materials Hearing to BOS Clerk Date
-    #[serde(rename = "TRANSMIT_DATE_BOS")]
-    pub transmit_date_bos: String,
+    #[serde(rename = "TRANSMIT_DATE_BOS", with = "lenient_date")]
+    pub transmit_date_bos: Option<DateTime<Utc>>,
 
     /// Committee Hearing Date - BOS Review
-    #[serde(rename = "COM_HEARING_DATE_BOS")]
-    pub com_hearing_date_bos: String,
+    #[serde(rename = "COM_HEARING_DATE_BOS", with = "lenient_date")]
+    pub com_hearing_date_bos: Option<DateTime<Utc>>,
+}
+
+impl HasGeometry for PPTSRecord {
+    fn wkt(&self) -> &str {
+        &self.the_geom
+    }
+}
+
+impl RecordSchema for PPTSRecord {
+    fn column_aliases() -> ColumnAliases {
+        &[("the_geom", &["shape", "geometry"])]
+    }
+}
+
+/// The permit numbers in a `RELATED_BUILDING_PERMIT` value, which may list several
+pub fn permit_numbers(related_building_permit: &str) -> Vec<String> {
+    related_building_permit
+        .split(|c: char| c == ',' || c == ';' || c.is_whitespace())
+        .filter(|number| !number.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Where a record is in its life, for deciding e.g. whether a project was approved
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lifecycle {
+    /// Filed and not yet decided
+    Open,
+    /// Approved, entitled, permitted or completed
+    Approved,
+    /// Disapproved or denied
+    Denied,
+    /// Withdrawn or cancelled by the applicant
+    Withdrawn,
+    /// Closed without a decision that we know of
+    Closed,
+}
+
+/// Status of a PPTS record (`record_status` column), e.g. `Under Review` or `Approved`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RecordStatus {
+    Submitted,
+    Accepted,
+    Incomplete,
+    UnderReview,
+    OnHold,
+    AppealFiled,
+    Approved,
+    /// Approved by the Board of Supervisors
+    ApprovalBos,
+    Entitled,
+    Permitted,
+    Issued,
+    Complete,
+    Completed,
+    Disapproved,
+    Denied,
+    Withdrawn,
+    Cancelled,
+    Closed,
+    Expired,
+    Other(String),
+}
+
+impl RecordStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            RecordStatus::Submitted => "Submitted",
+            RecordStatus::Accepted => "Accepted",
+            RecordStatus::Incomplete => "Incomplete",
+            RecordStatus::UnderReview => "Under Review",
+            RecordStatus::OnHold => "On Hold",
+            RecordStatus::AppealFiled => "Appeal Filed",
+            RecordStatus::Approved => "Approved",
+            RecordStatus::ApprovalBos => "Approval BOS",
+            RecordStatus::Entitled => "Entitled",
+            RecordStatus::Permitted => "Permitted",
+            RecordStatus::Issued => "Issued",
+            RecordStatus::Complete => "Complete",
+            RecordStatus::Completed => "Completed",
+            RecordStatus::Disapproved => "Disapproved",
+            RecordStatus::Denied => "Denied",
+            RecordStatus::Withdrawn => "Withdrawn",
+            RecordStatus::Cancelled => "Cancelled",
+            RecordStatus::Closed => "Closed",
+            RecordStatus::Expired => "Expired",
+            RecordStatus::Other(s) => s,
+        }
+    }
+
+    /// Accepted counts as approved because PPTS uses it for accepted
+    /// determinations (e.g. environmental exemptions) as well as applications.
+    /// Other statuses such as `Closed - Approved` are classified by their words.
+    pub fn lifecycle(&self) -> Lifecycle {
+        match self {
+            RecordStatus::Submitted
+            | RecordStatus::Incomplete
+            | RecordStatus::UnderReview
+            | RecordStatus::OnHold
+            | RecordStatus::AppealFiled => Lifecycle::Open,
+            RecordStatus::Accepted
+            | RecordStatus::Approved
+            | RecordStatus::ApprovalBos
+            | RecordStatus::Entitled
+            | RecordStatus::Permitted
+            | RecordStatus::Issued
+            | RecordStatus::Complete
+            | RecordStatus::Completed => Lifecycle::Approved,
+            RecordStatus::Disapproved | RecordStatus::Denied => Lifecycle::Denied,
+            RecordStatus::Withdrawn | RecordStatus::Cancelled => Lifecycle::Withdrawn,
+            RecordStatus::Closed | RecordStatus::Expired => Lifecycle::Closed,
+            RecordStatus::Other(s) => {
+                let s = s.to_ascii_lowercase();
+                // whole words, so that e.g. incomplete is not complete
+                let words: Vec<&str> = s.split(|c: char| !c.is_ascii_alphanumeric()).collect();
+                if s.contains("withdrawn") || s.contains("cancel") {
+                    Lifecycle::Withdrawn
+                } else if s.contains("disapprov") || s.contains("denied") {
+                    Lifecycle::Denied
+                } else if words.iter().any(|word| {
+                    word.starts_with("approv")
+                        || ["permitted", "issued", "complete", "completed", "accepted"].contains(word)
+                }) {
+                    Lifecycle::Approved
+                } else if s.contains("closed") || s.contains("expired") {
+                    Lifecycle::Closed
+                } else {
+                    Lifecycle::Open
+                }
+            }
+        }
+    }
+
+    pub fn is_approved(&self) -> bool {
+        self.lifecycle() == Lifecycle::Approved
+    }
+}
+
+impl From<&str> for RecordStatus {
+    fn from(s: &str) -> Self {
+        match s {
+            "Submitted" => RecordStatus::Submitted,
+            "Accepted" => RecordStatus::Accepted,
+            "Incomplete" => RecordStatus::Incomplete,
+            "Under Review" => RecordStatus::UnderReview,
+            "On Hold" => RecordStatus::OnHold,
+            "Appeal Filed" => RecordStatus::AppealFiled,
+            "Approved" => RecordStatus::Approved,
+            "Approval BOS" => RecordStatus::ApprovalBos,
+            "Entitled" => RecordStatus::Entitled,
+            "Permitted" => RecordStatus::Permitted,
+            "Issued" => RecordStatus::Issued,
+            "Complete" => RecordStatus::Complete,
+            "Completed" => RecordStatus::Completed,
+            "Disapproved" => RecordStatus::Disapproved,
+            "Denied" => RecordStatus::Denied,
+            "Withdrawn" => RecordStatus::Withdrawn,
+            "Cancelled" => RecordStatus::Cancelled,
+            "Closed" => RecordStatus::Closed,
+            "Expired" => RecordStatus::Expired,
+            other => RecordStatus::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for RecordStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for RecordStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(RecordStatus::from(&*s))
+    }
+}
+
+/// Kind of application (`record_type_category` column), e.g. `PRJ` or `ENV`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RecordTypeCategory {
+    /// PRJ: Project Application, the parent of a project's other records
+    Prj,
+    /// ENV: Environmental Review
+    Env,
+    /// CUA: Conditional Use Authorization
+    Cua,
+    /// VAR: Variance
+    Var,
+    /// DRP: Discretionary Review, requested by the public
+    Drp,
+    /// DRM: Discretionary Review, mandatory
+    Drm,
+    /// PPA: Preliminary Project Assessment
+    Ppa,
+    /// SHD: Shadow analysis
+    Shd,
+    /// COA: Certificate of Appropriateness
+    Coa,
+    /// PTA: Permit to Alter
+    Pta,
+    /// ENX: Large Project Authorization in Eastern Neighborhoods
+    Enx,
+    /// DNX: Downtown Project Authorization
+    Dnx,
+    /// OFA: Office Allocation
+    Ofa,
+    /// PCA: Planning Code Amendment
+    Pca,
+    /// MAP: Zoning Map Amendment
+    Map,
+    /// ZAD: Zoning Administrator Determination
+    Zad,
+    Other(String),
+}
+
+impl RecordTypeCategory {
+    pub fn as_str(&self) -> &str {
+        match self {
+            RecordTypeCategory::Prj => "PRJ",
+            RecordTypeCategory::Env => "ENV",
+            RecordTypeCategory::Cua => "CUA",
+            RecordTypeCategory::Var => "VAR",
+            RecordTypeCategory::Drp => "DRP",
+            RecordTypeCategory::Drm => "DRM",
+            RecordTypeCategory::Ppa => "PPA",
+            RecordTypeCategory::Shd => "SHD",
+            RecordTypeCategory::Coa => "COA",
+            RecordTypeCategory::Pta => "PTA",
+            RecordTypeCategory::Enx => "ENX",
+            RecordTypeCategory::Dnx => "DNX",
+            RecordTypeCategory::Ofa => "OFA",
+            RecordTypeCategory::Pca => "PCA",
+            RecordTypeCategory::Map => "MAP",
+            RecordTypeCategory::Zad => "ZAD",
+            RecordTypeCategory::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for RecordTypeCategory {
+    fn from(s: &str) -> Self {
+        match s {
+            "PRJ" => RecordTypeCategory::Prj,
+            "ENV" => RecordTypeCategory::Env,
+            "CUA" => RecordTypeCategory::Cua,
+            "VAR" => RecordTypeCategory::Var,
+            "DRP" => RecordTypeCategory::Drp,
+            "DRM" => RecordTypeCategory::Drm,
+            "PPA" => RecordTypeCategory::Ppa,
+            "SHD" => RecordTypeCategory::Shd,
+            "COA" => RecordTypeCategory::Coa,
+            "PTA" => RecordTypeCategory::Pta,
+            "ENX" => RecordTypeCategory::Enx,
+            "DNX" => RecordTypeCategory::Dnx,
+            "OFA" => RecordTypeCategory::Ofa,
+            "PCA" => RecordTypeCategory::Pca,
+            "MAP" => RecordTypeCategory::Map,
+            "ZAD" => RecordTypeCategory::Zad,
+            other => RecordTypeCategory::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for RecordTypeCategory {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for RecordTypeCategory {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RecordTypeCategory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(RecordTypeCategory::from(&*s))
+    }
+}
+
+/// An entitlement (a record of a project) and its outcome
+#[derive(Clone, Debug)]
+pub struct Entitlement {
+    pub record_id: String,
+    pub record_type_category: RecordTypeCategory,
+    pub record_status: RecordStatus,
+    pub date_opened: DateTime<Utc>,
+    pub date_closed: Option<DateTime<Utc>>,
+}
+
+/// One canonical row for a project tree
+#[derive(Clone, Debug)]
+pub struct Project {
+    /// record ID of the root of the tree, usually the PRJ record
+    pub record_id: String,
+    pub address: String,
+    pub description: String,
+    /// status of the root record
+    pub record_status: RecordStatus,
+    pub date_opened: DateTime<Utc>,
+    /// the latest date that an entitlement of the project was closed as approved,
+    /// or None if the project itself was denied or withdrawn
+    pub date_approved: Option<DateTime<Utc>>,
+    /// proposed market rate and affordable units
+    pub units_prop: f64,
+    /// proposed minus existing units; negative for demolitions and unit mergers
+    pub net_units: f64,
+    pub affordable_units_prop: f64,
+    /// DBI permit numbers of all records of the tree
+    pub related_building_permits: Vec<String>,
+    /// every record of the tree, root first
+    pub entitlements: Vec<Entitlement>,
+}
+
+/// PPTS records linked into project trees by their `parent` and `children` columns,
+/// so that a project with PRJ, ENV, CUA and VAR records is counted once
+pub struct ProjectGraph {
+    records: HashMap<String, PPTSRecord>,
+    /// record IDs of the children of each record, in file order
+    children: HashMap<String, Vec<String>>,
+    parent: HashMap<String, String>,
+    /// record IDs in file order, for deterministic output
+    order: Vec<String>,
+}
+
+impl ProjectGraph {
+    pub fn new<I: IntoIterator<Item = PPTSRecord>>(records: I) -> Self {
+        let mut graph = ProjectGraph {
+            records: HashMap::new(),
+            children: HashMap::new(),
+            parent: HashMap::new(),
+            order: vec![],
+        };
+        for record in records {
+            if graph.records.contains_key(&record.record_id) {
+                warn!("Duplicate PPTS record {}", record.record_id);
+                continue;
+            }
+            graph.order.push(record.record_id.clone());
+            graph.records.insert(record.record_id.clone(), record);
+        }
+        // a link may be listed by the parent, the child, or both
+        let mut links: Vec<(String, String)> = vec![];
+        for record_id in &graph.order {
+            let record = &graph.records[record_id];
+            for parent in &record.parent {
+                links.push((parent.clone(), record_id.clone()));
+            }
+            for child in &record.children {
+                links.push((record_id.clone(), child.clone()));
+            }
+        }
+        for (parent, child) in links {
+            if parent == child
+                || !graph.records.contains_key(&parent)
+                || !graph.records.contains_key(&child)
+                || graph.parent.contains_key(&child)
+                || graph.is_ancestor(&child, &parent)
+            {
+                continue;
+            }
+            graph.children.entry(parent.clone()).or_default().push(child.clone());
+            graph.parent.insert(child, parent);
+        }
+        graph
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, record_id: &str) -> Option<&PPTSRecord> {
+        self.records.get(record_id)
+    }
+
+    /// All records in file order
+    pub fn records(&self) -> impl Iterator<Item = &PPTSRecord> {
+        self.order.iter().map(move |record_id| &self.records[record_id])
+    }
+
+    /// True if `ancestor` is `record_id` or one of its ancestors
+    fn is_ancestor(&self, ancestor: &str, record_id: &str) -> bool {
+        let mut current = record_id;
+        loop {
+            if current == ancestor {
+                return true;
+            }
+            match self.parent.get(current) {
+                Some(parent) => current = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Record ID of the root of the tree that contains `record_id`
+    pub fn root<'a>(&'a self, record_id: &'a str) -> &'a str {
+        let mut current = record_id;
+        while let Some(parent) = self.parent.get(current) {
+            current = parent;
+        }
+        current
+    }
+
+    /// The records of the subtree at `record_id` with their depths, depth-first
+    pub fn tree(&self, record_id: &str) -> Vec<(usize, &PPTSRecord)> {
+        let mut tree = vec![];
+        let mut stack = vec![(0, record_id)];
+        while let Some((depth, record_id)) = stack.pop() {
+            if let Some(record) = self.records.get(record_id) {
+                tree.push((depth, record));
+            }
+            if let Some(children) = self.children.get(record_id) {
+                stack.extend(children.iter().rev().map(|child| (depth + 1, child.as_str())));
+            }
+        }
+        tree
+    }
+
+    /// The project that contains `record_id`
+    pub fn project(&self, record_id: &str) -> Option<Project> {
+        let root = self.records.get(self.root(record_id))?;
+        let records: Vec<&PPTSRecord> = self.tree(&root.record_id).into_iter().map(|(_, record)| record).collect();
+        // sub-records repeat the project's unit counts, so take the largest instead of adding them up
+        let max = |units: &dyn Fn(&PPTSRecord) -> Option<f64>| {
+            records.iter().filter_map(|record| units(record)).fold(0.0, f64::max)
+        };
+        // net counts are negative for demolitions and unit mergers, so take them from the root
+        // (or the first record in the tree that has them)
+        let first = |units: &dyn Fn(&PPTSRecord) -> Option<f64>| {
+            records.iter().find_map(|record| units(record)).unwrap_or(0.0)
+        };
+        let market_rate_prop = max(&|record| record.prj_feature_market_rate_prop);
+        let affordable_units_prop = max(&|record| record.prj_feature_affordable_prop);
+        let net_units = first(&|record| record.prj_feature_market_rate_net) + first(&|record| record.prj_feature_affordable_net);
+        // an accepted ENV does not make a withdrawn or disapproved project approved
+        let date_approved = match root.record_status.lifecycle() {
+            Lifecycle::Denied | Lifecycle::Withdrawn => None,
+            _ => records
+                .iter()
+                .filter(|record| record.record_status.is_approved())
+                .filter_map(|record| record.date_closed)
+                .max(),
+        };
+        let mut related_building_permits: Vec<String> = vec![];
+        for record in &records {
+            for number in permit_numbers(&record.related_building_permit) {
+                if !related_building_permits.contains(&number) {
+                    related_building_permits.push(number);
+                }
+            }
+        }
+        Some(Project {
+            record_id: root.record_id.clone(),
+            address: root.address.clone(),
+            description: root.description.clone(),
+            record_status: root.record_status.clone(),
+            date_opened: records.iter().map(|record| record.date_opened).min().unwrap_or(root.date_opened),
+            date_approved,
+            units_prop: market_rate_prop + affordable_units_prop,
+            net_units,
+            affordable_units_prop,
+            related_building_permits,
+            entitlements: records
+                .iter()
+                .map(|record| Entitlement {
+                    record_id: record.record_id.clone(),
+                    record_type_category: record.record_type_category.clone(),
+                    record_status: record.record_status.clone(),
+                    date_opened: record.date_opened,
+                    date_closed: record.date_closed,
+                })
+                .collect(),
+        })
+    }
+
+    /// One project per tree, in file order of the roots
+    pub fn projects(&self) -> Vec<Project> {
+        self.order
+            .iter()
+            .filter(|record_id| !self.parent.contains_key(*record_id))
+            .filter_map(|record_id| self.project(record_id))
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::PPTSRecord;
+    use super::{
+        checked_flag, lenient_date, lenient_number, permit_numbers, record_id_list, Lifecycle, PPTSRecord, ProjectGraph,
+        RecordStatus, RecordTypeCategory,
+    };
     use csv::Reader;
+    use serde::de::IntoDeserializer;
+    use serde::de::value::{Error, StrDeserializer};
     const TEST_LINES: &str = "the_geom,OBJECTID,record_id,date_opened,record_status,date_closed,address,record_type,record_type_category,record_type_group,record_type_subtype,record_type_type,record_type_4level,record_name,description,planner_id,module,templateid,parent,children,constructcost,planner_name,planner_email,planner_phone,acalink,aalink,CHANGE_OF_USE,ADDITIONS,NEW_CONSTRUCTION,LEG_ZONE_CHANGE,DEMOLITION,LOT_LINE_ADJUST,FACADE_ALT,ROW_IMPROVE,OTHER_PRJ_DESC,SPECIAL_NEEDS,SENIOR,AFFORDABLE_UNITS,STUDENT,INCLUSIONARY,STATE_DENSITY_BONUS,ADU,FORMULA_RETAIL,MCD,TOBACCO,FINANCIAL,MASSAGE,MCD_REFERRAL,OTHER_NON_RES,ENVIRONMENTAL_REVIEW_TYPE,LAND_USE_RC_EXIST,LAND_USE_RC_PROP,LAND_USE_RC_NET,LAND_USE_RESIDENTIAL_EXIST,LAND_USE_RESIDENTIAL_PROP,LAND_USE_RESIDENTIAL_NET,LAND_USE_CIE_EXIST,LAND_USE_CIE_PROP,LAND_USE_CIE_NET,LAND_USE_PDR_EXIST,LAND_USE_PDR_PROP,LAND_USE_PDR_NET,LAND_USE_OFFICE_EXIST,LAND_USE_OFFICE_PROP,LAND_USE_OFFICE_NET,LAND_USE_MEDICAL_EXIST,LAND_USE_MEDICAL_PROP,LAND_USE_MEDICAL_NET,LAND_USE_VISITOR_EXIST,LAND_USE_VISITOR_PROP,LAND_USE_VISITOR_NET,LAND_USE_PARKING_SPACES_EXIST,LAND_USE_PARKING_SPACES_PROP,LAND_USE_PARKING_SPACES_NET,PRJ_FEATURE_AFFORDABLE_EXIST,PRJ_FEATURE_AFFORDABLE_PROP,PRJ_FEATURE_AFFORDABLE_NET,PRJ_FEATURE_HOTEL_ROOMS_EXIST,PRJ_FEATURE_HOTEL_ROOMS_PROP,PRJ_FEATURE_HOTEL_ROOMS_NET,PRJ_FEATURE_MARKET_RATE_EXIST,PRJ_FEATURE_MARKET_RATE_PROP,PRJ_FEATURE_MARKET_RATE_NET,PRJ_FEATURE_BUILD_EXIST,PRJ_FEATURE_BUILD_PROP,PRJ_FEATURE_BUILD_NET,PRJ_FEATURE_STORIES_EXIST,PRJ_FEATURE_STORIES_PROP,PRJ_FEATURE_STORIES_NET,PRJ_FEATURE_PARKING_EXIST,PRJ_FEATURE_PARKING_PROP,PRJ_FEATURE_PARKING_NET,PRJ_FEATURE_LOADING_EXIST,PRJ_FEATURE_LOADING_PROP,PRJ_FEATURE_LOADING_NET,PRJ_FEATURE_BIKE_EXIST,PRJ_FEATURE_BIKE_PROP,PRJ_FEATURE_BIKE_NET,PRJ_FEATURE_CAR_SHARE_EXIST,PRJ_FEATURE_CAR_SHARE_PROP,PRJ_FEATURE_CAR_SHARE_NET,PRJ_FEATURE_USABLE_EXIST,PRJ_FEATURE_USABLE_PROP,PRJ_FEATURE_USABLE_NET,PRJ_FEATURE_PUBLIC_EXIST,PRJ_FEATURE_PUBLIC_PROP,PRJ_FEATURE_PUBLIC_NET,PRJ_FEATURE_ART_EXIST,PRJ_FEATURE_ART_PROP,PRJ_FEATURE_ART_NET,PRJ_FEATURE_ROOF_EXIST,PRJ_FEATURE_ROOF_PROP,PRJ_FEATURE_ROOF_NET,PRJ_FEATURE_SOLAR_EXIST,PRJ_FEATURE_SOLAR_PROP,PRJ_FEATURE_SOLAR_NET,PRJ_FEATURE_LIVING_EXIST,PRJ_FEATURE_LIVING_PROP,PRJ_FEATURE_LIVING_NET,PRJ_FEATURE_OTHER,PRJ_FEATURE_OTHER_EXIST,PRJ_FEATURE_OTHER_PROP,PRJ_FEATURE_OTHER_NET,RESIDENTIAL_STUDIO_EXIST,RESIDENTIAL_STUDIO_PROP,RESIDENTIAL_STUDIO_NET,RESIDENTIAL_1BR_EXIST,RESIDENTIAL_1BR_PROP,RESIDENTIAL_1BR_NET,RESIDENTIAL_2BR_EXIST,RESIDENTIAL_2BR_PROP,RESIDENTIAL_2BR_NET,RESIDENTIAL_3BR_EXIST,RESIDENTIAL_3BR_PROP,RESIDENTIAL_3BR_NET,RESIDENTIAL_ADU_STUDIO_EXIST,RESIDENTIAL_ADU_STUDIO_PROP,RESIDENTIAL_ADU_STUDIO_NET,RESIDENTIAL_ADU_STUDIO_AREA,RESIDENTIAL_ADU_1BR_EXIST,RESIDENTIAL_ADU_1BR_PROP,RESIDENTIAL_ADU_1BR_NET,RESIDENTIAL_ADU_1BR_AREA,RESIDENTIAL_ADU_2BR_EXIST,RESIDENTIAL_ADU_2BR_PROP,RESIDENTIAL_ADU_2BR_NET,RESIDENTIAL_ADU_2BR_AREA,RESIDENTIAL_ADU_3BR_EXIST,RESIDENTIAL_ADU_3BR_PROP,RESIDENTIAL_ADU_3BR_NET,RESIDENTIAL_ADU_3BR_AREA,RESIDENTIAL_GH_ROOMS_EXIST,RESIDENTIAL_GH_ROOMS_PROP,RESIDENTIAL_GH_ROOMS_NET,RESIDENTIAL_GH_BEDS_EXIST,RESIDENTIAL_GH_BEDS_PROP,RESIDENTIAL_GH_BEDS_NET,RESIDENTIAL_SRO_EXIST,RESIDENTIAL_SRO_PROP,RESIDENTIAL_SRO_NET,RESIDENTIAL_MICRO_EXIST,RESIDENTIAL_MICRO_PROP,RESIDENTIAL_MICRO_NET,RELATED_BUILDING_PERMIT,BOS_1ST_READ,BOS_2ND_READ,COM_HEARING,MAYORAL_SIGN,TRANSMIT_DATE_BOS,COM_HEARING_DATE_BOS,Shape_Length,Shape_Area
 \"MULTIPOLYGON (((-122.456226949 37.736700243, -122.456221274 37.736446106, -122.456301489 37.736446023, -122.456389601 37.736445931, -122.456432624 37.736451404, -122.456433604 37.73672754, -122.456398805 37.736721721, -122.456363884 37.736716386, -122.45632885 37.736711535, -122.456293716 37.73670717, -122.456226949 37.736700243)))\",2,2018-015340ENV,11/08/2019 12:00:00 AM +0000,Under Review,,124 ROBINHOOD DR 94127,Environmental (ENV),ENV,Planning,Environmental,Applications,Planning/Applications/Environmental/ENV,124 ROBINHOOD DR,\"Interior remodel including kitchen and baths, small addition at rear under (E) roof and rebuild (E) deck, new bay and roof at master bedroom. Replacement of all doors & windows.\",LLYNCH,Planning,19CAP-00000-0016E,2018-015340PRJ,,400000,Laura Lynch,laura.lynch@sfgov.org,415-575-9045,https://aca.accela.com/ccsf/Cap/CapDetail.aspx?Module=Planning&TabName=Planning&capID1=19CAP&capID2=00000&capID3=0016E&agencyCode=CCSF,https://av.accela.com/portlets/cap/capsummary/CapTabSummary.do?mode=tabSummary&serviceProviderCode=CCSF&ID1=19CAP&ID2=00000&ID3=0016E&requireNotice=YES&clearForm=clearForm&module=Planning&isGeneralCAP=N,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,0.00095054097651,0.00000005554121
 \"MULTIPOLYGON (((-122.43713213 37.745567486, -122.437162248 37.745879656, -122.43711879 37.745882227, -122.437076022 37.745884757, -122.437045904 37.745572586, -122.43713213 37.745567486)))\",1,2018-015993ENV,11/27/2019 12:00:00 AM +0000,Under Review,,762 DUNCAN ST 94131,Environmental (ENV),ENV,Planning,Environmental,Applications,Planning/Applications/Environmental/ENV,762 DUNCAN ST,\"The purpose of the project is to add additional living space, bedrooms and bathrooms to a small 2 bedroom, 1 bath residence of 1093 s.f. to accommodate  an elderly parent winning to move in with property owners.\",JCLEEMAN,Planning,19CAP-00000-000YG,2018-015993PRJ,,725000,Jorgen Cleemann,Jorgen.Cleemann@sfgov.org,415-575-8763,https://aca.accela.com/ccsf/Cap/CapDetail.aspx?Module=Planning&TabName=Planning&capID1=19CAP&capID2=00000&capID3=000YG&agencyCode=CCSF,https://av.accela.com/portlets/cap/capsummary/CapTabSummary.do?mode=tabSummary&serviceProviderCode=CCSF&ID1=19CAP&ID2=00000&ID3=000YG&requireNotice=YES&clearForm=clearForm&module=Planning&isGeneralCAP=N,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,,0.000799993478698,0.000000027070834";
     #[test]
     fn test_parse_record() -> Result<(), csv::Error> {
         let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
-        for line in rdr.deserialize::<PPTSRecord>() {
-            line?;
+        let records = rdr.deserialize::<PPTSRecord>().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(vec!["2018-015340PRJ".to_string()], records[0].parent);
+        assert!(records[0].children.is_empty());
+        assert_eq!(Some(400000.0), records[0].constructcost);
+        assert_eq!(None, records[0].residential_studio_prop);
+        assert!(!records[0].demolition);
+        assert_eq!(RecordStatus::UnderReview, records[0].record_status);
+        assert_eq!(RecordTypeCategory::Env, records[0].record_type_category);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lenient_fields() -> Result<(), Error> {
+        assert_eq!(Some(1200.0), lenient_number::deserialize("1,200".into_deserializer())?);
+        assert_eq!(Some(400000.0), lenient_number::deserialize("$400,000.00".into_deserializer())?);
+        assert_eq!(None, lenient_number::deserialize(" ".into_deserializer())?);
+        assert_eq!(None, lenient_date::deserialize("".into_deserializer())?);
+        assert_eq!(
+            lenient_date::deserialize("03/05/2019 12:00:00 AM +0000".into_deserializer())?,
+            lenient_date::deserialize("03/05/2019".into_deserializer())?
+        );
+        assert!(lenient_date::deserialize::<StrDeserializer<Error>>("soon".into_deserializer()).is_err());
+        assert!(lenient_number::deserialize::<StrDeserializer<Error>>("n/a".into_deserializer()).is_err());
+        assert!(checked_flag::deserialize("CHECKED".into_deserializer())?);
+        assert!(!checked_flag::deserialize("".into_deserializer())?);
+        assert_eq!(
+            vec!["2019-001PRJ".to_string(), "2019-001ENV".to_string()],
+            record_id_list::deserialize::<StrDeserializer<Error>>("2019-001PRJ, 2019-001ENV".into_deserializer())?
+        );
+        assert_eq!(
+            vec!["201505074955".to_string(), "201505074956".to_string()],
+            permit_numbers("201505074955, 201505074956")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_status() {
+        for status in &["Under Review", "Approval BOS", "Closed - CEQA", "Withdrawn"] {
+            assert_eq!(*status, RecordStatus::from(*status).as_str());
         }
+        assert_eq!(Lifecycle::Open, RecordStatus::from("Under Review").lifecycle());
+        assert!(RecordStatus::from("Approval BOS").is_approved());
+        assert!(RecordStatus::from("Closed - Approved").is_approved());
+        assert_eq!(Lifecycle::Denied, RecordStatus::from("Disapproved").lifecycle());
+        assert_eq!(Lifecycle::Withdrawn, RecordStatus::from("Closed - Withdrawn").lifecycle());
+        assert_eq!(Lifecycle::Closed, RecordStatus::from("Closed - CEQA").lifecycle());
+        assert_eq!(Lifecycle::Closed, RecordStatus::from("Closed - Incomplete").lifecycle());
+        assert!(RecordStatus::from("Closed - Completed").is_approved());
+        assert_eq!(RecordTypeCategory::Prj, RecordTypeCategory::from("PRJ"));
+        assert_eq!("XYZ", RecordTypeCategory::from("XYZ").to_string());
+    }
+
+    #[test]
+    fn test_project_graph() -> Result<(), csv::Error> {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let mut records = rdr.deserialize::<PPTSRecord>().collect::<Result<Vec<_>, _>>()?;
+        let mut prj = records[0].clone();
+        prj.record_id = "2018-015340PRJ".to_string();
+        prj.record_type_category = RecordTypeCategory::Prj;
+        prj.parent = vec![];
+        prj.children = vec!["2018-015340ENV".to_string(), "2018-015340VAR".to_string()];
+        prj.prj_feature_market_rate_prop = Some(3.0);
+        // 5 units merged into 3
+        prj.prj_feature_market_rate_net = Some(-2.0);
+        prj.prj_feature_affordable_net = Some(0.0);
+        let mut var = records[0].clone();
+        var.record_id = "2018-015340VAR".to_string();
+        var.record_type_category = RecordTypeCategory::Var;
+        var.record_status = RecordStatus::Approved;
+        var.date_closed = Some(var.date_opened);
+        var.prj_feature_market_rate_prop = Some(3.0);
+        var.prj_feature_market_rate_net = Some(3.0);
+        records.push(var);
+        records.insert(0, prj);
+
+        let graph = ProjectGraph::new(records);
+        assert_eq!(4, graph.len());
+        assert_eq!("2018-015340PRJ", graph.root("2018-015340VAR"));
+        let tree: Vec<(usize, &str)> = graph
+            .tree("2018-015340PRJ")
+            .into_iter()
+            .map(|(depth, record)| (depth, record.record_id.as_str()))
+            .collect();
+        assert_eq!(vec![(0, "2018-015340PRJ"), (1, "2018-015340ENV"), (1, "2018-015340VAR")], tree);
+
+        let projects = graph.projects();
+        assert_eq!(2, projects.len());
+        let project = &projects[0];
+        assert_eq!("2018-015340PRJ", project.record_id);
+        assert_eq!(3, project.entitlements.len());
+        assert_eq!(3.0, project.units_prop);
+        assert_eq!(-2.0, project.net_units);
+        assert!(project.date_approved.is_some());
+        assert_eq!("2018-015993ENV", projects[1].record_id);
+        Ok(())
+    }
+
+    #[test]
+    fn test_withdrawn_project() -> Result<(), csv::Error> {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let records = rdr.deserialize::<PPTSRecord>().collect::<Result<Vec<_>, _>>()?;
+        let mut prj = records[0].clone();
+        prj.record_id = "2018-015340PRJ".to_string();
+        prj.record_type_category = RecordTypeCategory::Prj;
+        prj.record_status = RecordStatus::Withdrawn;
+        prj.parent = vec![];
+        prj.children = vec!["2018-015340ENV".to_string()];
+        let mut env = records[0].clone();
+        env.record_status = RecordStatus::Accepted;
+        env.date_closed = Some(env.date_opened);
+
+        let graph = ProjectGraph::new(vec![prj, env]);
+        let project = graph.project("2018-015340ENV").expect("project");
+        assert_eq!("2018-015340PRJ", project.record_id);
+        assert_eq!(None, project.date_approved);
         Ok(())
     }
 }
@@ -3,13 +3,14 @@
 //! https://data.sfgov.org/Geographic-Locations-and-Boundaries/Zoning-Map-Zoning-Districts/xvjh-uu28
 //! File name: Zoning_Map_-_Zoning_Districts_data.csv
 //!
-//! See also Zoning Height Map
+//! See also the Height and Bulk Districts Map in `sfheightbulk`
 //!
 
-use rstar::RTree;
-use crate::polygon_wrapper::PolygonWrapper;
-use geo::{MultiPolygon, Point};
-use geo::algorithm::centroid::Centroid;
+use crate::schema::{ColumnAliases, RecordSchema};
+use crate::spatial_layer::{HasGeometry, SpatialLayer};
+use geo::MultiPolygon;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct ZoningDistrict {
@@ -18,7 +19,7 @@ pub struct ZoningDistrict {
     #[serde(rename = "OBJECTID")]
     pub objectid: String,
 
-    pub zoning_sim: String,
+    pub zoning_sim: ZoningCode,
 
     pub districtname: String,
 
@@ -37,19 +38,324 @@ pub struct ZoningDistrict {
     pub shape_area: f64,
 }
 
+impl HasGeometry for ZoningDistrict {
+    fn wkt(&self) -> &str {
+        &self.the_geom
+    }
+}
+
+impl RecordSchema for ZoningDistrict {
+    fn column_aliases() -> ColumnAliases {
+        &[
+            ("the_geom", &["shape", "geometry"]),
+            ("shape_Length", &["SHAPE_Leng", "Shape_Leng"]),
+        ]
+    }
+}
+
+/// Simplified zoning district (`zoning_sim` column), e.g. `RH-2` or `NCT-3`.
+/// Named Neighborhood Commercial and NCT districts keep their names.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ZoningCode {
+    /// RH-1(D): Residential, House, One Family, Detached
+    Rh1D,
+    /// RH-1: Residential, House, One Family
+    Rh1,
+    /// RH-1(S): Residential, House, One Family with Minor Second Unit
+    Rh1S,
+    /// RH-2: Residential, House, Two Family
+    Rh2,
+    /// RH-3: Residential, House, Three Family
+    Rh3,
+    /// RM-1: Residential, Mixed, Low Density
+    Rm1,
+    /// RM-2: Residential, Mixed, Moderate Density
+    Rm2,
+    /// RM-3: Residential, Mixed, Medium Density
+    Rm3,
+    /// RM-4: Residential, Mixed, High Density
+    Rm4,
+    /// RC-1: Residential-Commercial, Low Density
+    Rc1,
+    /// RC-2: Residential-Commercial, Moderate Density
+    Rc2,
+    /// RC-3: Residential-Commercial, Medium Density
+    Rc3,
+    /// RC-4: Residential-Commercial, High Density
+    Rc4,
+    /// RTO: Residential Transit Oriented
+    Rto,
+    /// RTO-M: Residential Transit Oriented, Mission
+    RtoM,
+    /// NC-1: Neighborhood Commercial Cluster
+    Nc1,
+    /// NC-2: Small-Scale Neighborhood Commercial
+    Nc2,
+    /// NC-3: Moderate-Scale Neighborhood Commercial
+    Nc3,
+    /// NC-S: Neighborhood Commercial Shopping Center
+    NcS,
+    /// Named Neighborhood Commercial District, e.g. `NCD`
+    Ncd(String),
+    /// NCT-1: Neighborhood Commercial Transit Cluster
+    Nct1,
+    /// NCT-2: Small-Scale Neighborhood Commercial Transit
+    Nct2,
+    /// NCT-3: Moderate-Scale Neighborhood Commercial Transit
+    Nct3,
+    /// Named Neighborhood Commercial Transit District, e.g. `NCT`
+    Nct(String),
+    /// C-2: Community Business
+    C2,
+    /// C-3-O: Downtown Office
+    C3O,
+    /// C-3-O(SD): Downtown Office (Special Development)
+    C3OSd,
+    /// C-3-R: Downtown Retail
+    C3R,
+    /// C-3-G: Downtown General Commercial
+    C3G,
+    /// C-3-S: Downtown Support
+    C3S,
+    /// MUR: Mixed Use-Residential
+    Mur,
+    /// MUG: Mixed Use-General
+    Mug,
+    /// MUO: Mixed Use-Office
+    Muo,
+    /// UMU: Urban Mixed Use
+    Umu,
+    /// CMUO: Central SoMa Mixed Use-Office
+    Cmuo,
+    /// PDR-1-B, PDR-1-D, PDR-1-G, PDR-2: Production, Distribution and Repair
+    Pdr(String),
+    /// P: Public
+    Public,
+    Other(String),
+}
+
+/// How many dwelling units the base zoning allows on a lot
+/// (Planning Code Sec. 207 and the zoning control tables), without Conditional Use,
+/// density bonuses or ADUs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Density {
+    /// A fixed number of units per lot regardless of its size
+    UnitsPerLot(u32),
+    /// One unit per this many sq. ft. of lot area
+    LotAreaPerUnit(f64),
+    /// No density limit; the number of units is limited only by height, bulk, and setbacks
+    NoLimit,
+    /// Dwelling units are not permitted
+    NotPermitted,
+}
+
+impl Density {
+    /// Maximum number of units on a lot of `lot_area` sq. ft., or None if there is no limit
+    pub fn max_units(&self, lot_area: f64) -> Option<u32> {
+        match *self {
+            Density::UnitsPerLot(units) => Some(units),
+            Density::LotAreaPerUnit(area_per_unit) => Some((lot_area / area_per_unit).floor() as u32),
+            Density::NoLimit => None,
+            Density::NotPermitted => Some(0),
+        }
+    }
+}
+
+/// Required rear yard (Planning Code Sec. 134). In commercial and mixed-use districts
+/// it applies to the lowest story containing a dwelling unit and above.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RearYard {
+    /// Fraction of the lot depth
+    pub depth_fraction: f64,
+    /// Minimum depth in ft.
+    pub min_depth: f64,
+}
+
+impl RearYard {
+    /// Required depth in ft. of the rear yard of a lot that is `lot_depth` ft. deep
+    pub fn depth(&self, lot_depth: f64) -> f64 {
+        f64::max(self.depth_fraction * lot_depth, self.min_depth).min(lot_depth)
+    }
+}
+
+/// Base Planning Code controls of a zoning district
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ZoningRules {
+    pub density: Density,
+    pub rear_yard: Option<RearYard>,
+}
+
+impl ZoningRules {
+    /// Maximum fraction of a rectangular lot that can be built, ignoring front setbacks
+    /// and the rear yard minimum depth
+    pub fn max_lot_coverage(&self) -> f64 {
+        self.rear_yard.map(|rear_yard| 1.0 - rear_yard.depth_fraction).unwrap_or(1.0)
+    }
+}
+
+const REAR_YARD_25: RearYard = RearYard { depth_fraction: 0.25, min_depth: 15.0 };
+const REAR_YARD_30: RearYard = RearYard { depth_fraction: 0.30, min_depth: 15.0 };
+
+impl ZoningCode {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ZoningCode::Rh1D => "RH-1(D)",
+            ZoningCode::Rh1 => "RH-1",
+            ZoningCode::Rh1S => "RH-1(S)",
+            ZoningCode::Rh2 => "RH-2",
+            ZoningCode::Rh3 => "RH-3",
+            ZoningCode::Rm1 => "RM-1",
+            ZoningCode::Rm2 => "RM-2",
+            ZoningCode::Rm3 => "RM-3",
+            ZoningCode::Rm4 => "RM-4",
+            ZoningCode::Rc1 => "RC-1",
+            ZoningCode::Rc2 => "RC-2",
+            ZoningCode::Rc3 => "RC-3",
+            ZoningCode::Rc4 => "RC-4",
+            ZoningCode::Rto => "RTO",
+            ZoningCode::RtoM => "RTO-M",
+            ZoningCode::Nc1 => "NC-1",
+            ZoningCode::Nc2 => "NC-2",
+            ZoningCode::Nc3 => "NC-3",
+            ZoningCode::NcS => "NC-S",
+            ZoningCode::Ncd(s) => s,
+            ZoningCode::Nct1 => "NCT-1",
+            ZoningCode::Nct2 => "NCT-2",
+            ZoningCode::Nct3 => "NCT-3",
+            ZoningCode::Nct(s) => s,
+            ZoningCode::C2 => "C-2",
+            ZoningCode::C3O => "C-3-O",
+            ZoningCode::C3OSd => "C-3-O(SD)",
+            ZoningCode::C3R => "C-3-R",
+            ZoningCode::C3G => "C-3-G",
+            ZoningCode::C3S => "C-3-S",
+            ZoningCode::Mur => "MUR",
+            ZoningCode::Mug => "MUG",
+            ZoningCode::Muo => "MUO",
+            ZoningCode::Umu => "UMU",
+            ZoningCode::Cmuo => "CMUO",
+            ZoningCode::Pdr(s) => s,
+            ZoningCode::Public => "P",
+            ZoningCode::Other(s) => s,
+        }
+    }
+
+    /// RH-* districts
+    pub fn is_rh(&self) -> bool {
+        matches!(self, ZoningCode::Rh1D | ZoningCode::Rh1 | ZoningCode::Rh1S | ZoningCode::Rh2 | ZoningCode::Rh3)
+    }
+
+    /// Base density and rear yard, or None if this table doesn't know the district
+    /// (e.g. named NCDs, whose controls vary)
+    pub fn rules(&self) -> Option<ZoningRules> {
+        let (density, rear_yard) = match self {
+            ZoningCode::Rh1D | ZoningCode::Rh1 => (Density::UnitsPerLot(1), Some(REAR_YARD_25)),
+            // the second unit may be at most 600 sq. ft.
+            ZoningCode::Rh1S => (Density::UnitsPerLot(2), Some(REAR_YARD_25)),
+            ZoningCode::Rh2 => (Density::UnitsPerLot(2), Some(REAR_YARD_30)),
+            ZoningCode::Rh3 => (Density::UnitsPerLot(3), Some(REAR_YARD_30)),
+            ZoningCode::Rm1 | ZoningCode::Rc1 => (Density::LotAreaPerUnit(800.0), Some(REAR_YARD_30)),
+            ZoningCode::Rm2 | ZoningCode::Rc2 => (Density::LotAreaPerUnit(600.0), Some(REAR_YARD_30)),
+            ZoningCode::Rm3 | ZoningCode::Rc3 => (Density::LotAreaPerUnit(400.0), Some(REAR_YARD_25)),
+            ZoningCode::Rm4 | ZoningCode::Rc4 => (Density::LotAreaPerUnit(200.0), Some(REAR_YARD_25)),
+            ZoningCode::Rto | ZoningCode::RtoM => (Density::NoLimit, Some(REAR_YARD_30)),
+            ZoningCode::Nc1 | ZoningCode::Nc2 | ZoningCode::NcS | ZoningCode::C2 =>
+                (Density::LotAreaPerUnit(800.0), Some(REAR_YARD_25)),
+            ZoningCode::Nc3 => (Density::LotAreaPerUnit(600.0), Some(REAR_YARD_25)),
+            ZoningCode::Nct1 | ZoningCode::Nct2 | ZoningCode::Nct3 | ZoningCode::Nct(_) =>
+                (Density::NoLimit, Some(REAR_YARD_25)),
+            ZoningCode::C3O | ZoningCode::C3OSd | ZoningCode::C3R | ZoningCode::C3G | ZoningCode::C3S =>
+                (Density::NoLimit, Some(REAR_YARD_25)),
+            ZoningCode::Mur | ZoningCode::Mug | ZoningCode::Muo | ZoningCode::Umu | ZoningCode::Cmuo =>
+                (Density::NoLimit, Some(REAR_YARD_25)),
+            ZoningCode::Pdr(_) | ZoningCode::Public => (Density::NotPermitted, None),
+            ZoningCode::Ncd(_) | ZoningCode::Other(_) => return None,
+        };
+        Some(ZoningRules { density, rear_yard })
+    }
+
+    /// Maximum number of units that the base zoning allows on a lot of `lot_area` sq. ft.
+    /// None if there is no density limit or the district is unknown.
+    pub fn max_units(&self, lot_area: f64) -> Option<u32> {
+        self.rules().and_then(|rules| rules.density.max_units(lot_area))
+    }
+}
+
+impl From<&str> for ZoningCode {
+    fn from(s: &str) -> Self {
+        match s {
+            "RH-1(D)" => ZoningCode::Rh1D,
+            "RH-1" => ZoningCode::Rh1,
+            "RH-1(S)" => ZoningCode::Rh1S,
+            "RH-2" => ZoningCode::Rh2,
+            "RH-3" => ZoningCode::Rh3,
+            "RM-1" => ZoningCode::Rm1,
+            "RM-2" => ZoningCode::Rm2,
+            "RM-3" => ZoningCode::Rm3,
+            "RM-4" => ZoningCode::Rm4,
+            "RC-1" => ZoningCode::Rc1,
+            "RC-2" => ZoningCode::Rc2,
+            "RC-3" => ZoningCode::Rc3,
+            "RC-4" => ZoningCode::Rc4,
+            "RTO" => ZoningCode::Rto,
+            "RTO-M" => ZoningCode::RtoM,
+            "NC-1" => ZoningCode::Nc1,
+            "NC-2" => ZoningCode::Nc2,
+            "NC-3" => ZoningCode::Nc3,
+            "NC-S" => ZoningCode::NcS,
+            "NCT-1" => ZoningCode::Nct1,
+            "NCT-2" => ZoningCode::Nct2,
+            "NCT-3" => ZoningCode::Nct3,
+            "C-2" => ZoningCode::C2,
+            "C-3-O" => ZoningCode::C3O,
+            "C-3-O(SD)" => ZoningCode::C3OSd,
+            "C-3-R" => ZoningCode::C3R,
+            "C-3-G" => ZoningCode::C3G,
+            "C-3-S" => ZoningCode::C3S,
+            "MUR" => ZoningCode::Mur,
+            "MUG" => ZoningCode::Mug,
+            "MUO" => ZoningCode::Muo,
+            "UMU" => ZoningCode::Umu,
+            "CMUO" => ZoningCode::Cmuo,
+            "P" => ZoningCode::Public,
+            pdr if pdr.starts_with("PDR-") => ZoningCode::Pdr(pdr.to_string()),
+            nct if nct == "NCT" || nct.ends_with(" NCT") => ZoningCode::Nct(nct.to_string()),
+            ncd if ncd == "NCD" || ncd.ends_with(" NCD") => ZoningCode::Ncd(ncd.to_string()),
+            other => ZoningCode::Other(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ZoningCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ZoningCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ZoningCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(ZoningCode::from(&*s))
+    }
+}
+
 pub fn get_zoning<'a>(
-    rtree: &'a RTree<PolygonWrapper<ZoningDistrict>>,
+    zoning_districts: &'a SpatialLayer<ZoningDistrict>,
     shape: &MultiPolygon<f64>,
 ) -> Option<&'a ZoningDistrict> {
-    let centroid: Point<f64> = shape.centroid()
-        .expect("multipolygon should have at least one point");
-    rtree.locate_at_point(&[centroid.0.x, centroid.0.y])
+    zoning_districts.at_centroid(shape)
         .map(|polygon_wrapper| &polygon_wrapper.value)
 }
 
 #[cfg(test)]
 mod test {
-    use super::ZoningDistrict;
+    use super::{Density, ZoningCode, ZoningDistrict};
     use csv::Reader;
     const TEST_LINES: &str = "the_geom,OBJECTID,zoning_sim,districtname,url,gen,zoning,codesection,shape_Length,shape_Area
 \"MULTIPOLYGON (((-122.395895701 37.784896928, -122.395160622 37.784311012, -122.394956564 37.784147886, -122.394951788 37.784144067, -122.394925604 37.784123136, -122.394854047 37.784065932, -122.394820682 37.784039259, -122.394814269 37.784034133, -122.394757559 37.783988798, -122.394718913 37.783957903, -122.394625576 37.783883287, -122.394726078 37.783804206, -122.395119253 37.783494832, -122.395217547 37.783417488, -122.395400943 37.78327318, -122.395384745 37.78326028, -122.395397059 37.783250634, -122.396841427 37.782119121, -122.399079634 37.780362887, -122.399616232 37.780791482, -122.400149637 37.78121752, -122.400613071 37.781587665, -122.400668574 37.781631995, -122.40031732 37.781908576, -122.400268489 37.781869654, -122.399802203 37.781497998, -122.398879207 37.782224234, -122.398951115 37.782281091, -122.39888968 37.782329429, -122.398650276 37.782517795, -122.399072481 37.782855996, -122.398518244 37.783293881, -122.398415805 37.783374814, -122.397942938 37.782997897, -122.397385539 37.782553592, -122.396754161 37.783052317, -122.396489274 37.78326155, -122.396455659 37.783288101, -122.396262401 37.783440752, -122.396678728 37.783770182, -122.397035043 37.784052123, -122.396569303 37.784419099, -122.396536412 37.78439277, -122.396532045 37.784396206, -122.396464808 37.784449114, -122.396031078 37.784790405, -122.396028035 37.784792799, -122.395895701 37.784896928)))\",18890,CMUO,CENTRAL SOMA-MIXED USE OFFICE,http://library.amlegal.com/nxt/gateway.dll/California/planning/article8mixedusedistricts?f=templates$fn=default.htm$3.0$vid=amlegal:sanfrancisco_ca$anc=JD_848,Mixed Use,CMUO,TBD,0.018678318753143,0.000007881758246
@@ -62,4 +368,25 @@ mod test {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_zoning_code() {
+        for code in &["RH-1(D)", "RH-2", "NCT-3", "C-3-O(SD)", "PDR-1-G", "HAYES-GOUGH NCT", "NCD", "SPD"] {
+            assert_eq!(*code, ZoningCode::from(*code).as_str());
+        }
+        assert_eq!(ZoningCode::Nct("HAYES-GOUGH NCT".to_string()), ZoningCode::from("HAYES-GOUGH NCT"));
+        assert!(ZoningCode::from("SPD").rules().is_none());
+
+        assert_eq!(Some(2), ZoningCode::Rh2.max_units(7500.0));
+        assert_eq!(Some(3), ZoningCode::Rm1.max_units(2500.0));
+        assert_eq!(Some(12), ZoningCode::Rm4.max_units(2500.0));
+        assert_eq!(None, ZoningCode::Nct3.max_units(2500.0));
+        assert_eq!(Some(Density::NotPermitted), ZoningCode::from("PDR-2").rules().map(|r| r.density));
+
+        let rh2 = ZoningCode::Rh2.rules().expect("RH-2 rules");
+        assert!((rh2.max_lot_coverage() - 0.7).abs() < 1e-9);
+        let rear_yard = rh2.rear_yard.expect("RH-2 rear yard");
+        assert!((rear_yard.depth(100.0) - 30.0).abs() < 1e-9);
+        assert!((rear_yard.depth(40.0) - 15.0).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,230 @@
+//! Spatial index over the rows of any dataset with a WKT geometry column,
+//! so that analyses can join datasets by location.
+
+use crate::csv_loader::{RowError, RowErrorHandler, RowErrorPolicy};
+use crate::dataset_cache::DatasetCache;
+use crate::polygon_wrapper::{parse_wkt_to_multipolygon, PolygonWrapper};
+use crate::schema::RecordSchema;
+use geo::algorithm::centroid::Centroid;
+use geo::{MultiPolygon, Point};
+use rayon::prelude::*;
+use rstar::{RTree, AABB};
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+/// A record that has a WKT polygon or multipolygon column
+pub trait HasGeometry {
+    /// The WKT of this record's shape, e.g. the `the_geom` column
+    fn wkt(&self) -> &str;
+}
+
+/// All the rows of one dataset, indexed by their shapes
+#[derive(Serialize, Deserialize)]
+pub struct SpatialLayer<T> {
+    pub rtree: RTree<PolygonWrapper<T>>,
+}
+
+impl<T: HasGeometry + RecordSchema + Send + Sync> SpatialLayer<T> {
+    /// Read all records of `rdr` and index them by shape. Geometry is parsed in parallel;
+    /// rows that fail to deserialize or that have bad geometry are handled by `policy`.
+    pub fn load<R: Read + Send>(
+        dataset: &str,
+        rdr: &mut csv::Reader<R>,
+        policy: &RowErrorPolicy,
+    ) -> Result<Self, RowError> {
+        let errors = RowErrorHandler::new(dataset, policy);
+        let polygons = errors
+            .records::<_, T>(rdr)
+            .par_bridge()
+            .map(|result| -> Result<Option<PolygonWrapper<T>>, RowError> {
+                let row = result?;
+                match parse_wkt_to_multipolygon(row.record.wkt()) {
+                    Ok(multi_polygon) => Ok(Some(PolygonWrapper::new(multi_polygon, row.record))),
+                    Err(err) => {
+                        errors.reject_row(&row, "geometry", &err.to_string())?;
+                        Ok(None)
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>, RowError>>()?;
+        errors.log_summary();
+        info!("Generating {} rtree", dataset);
+        Ok(SpatialLayer::from_polygons(
+            polygons.into_iter().flatten().collect(),
+        ))
+    }
+}
+
+impl<T: HasGeometry + RecordSchema + Serialize + Send + Sync> SpatialLayer<T> {
+    /// Like `load`, but read the parsed layer (including the rtree layout) from `cache`
+    /// if the csv file at `path` hasn't changed since it was cached.
+    /// The cache is only read with `RowErrorPolicy::Skip`, since a cached layer
+    /// has already dropped its bad rows.
+    pub fn load_cached(
+        dataset: &str,
+        path: &Path,
+        policy: &RowErrorPolicy,
+        cache: &DatasetCache,
+    ) -> Result<Self, RowError> {
+        let rebuilding;
+        let cache = match policy {
+            RowErrorPolicy::Skip => cache,
+            _ => {
+                rebuilding = cache.rebuilding();
+                &rebuilding
+            }
+        };
+        cache.load_or_build(dataset, path, || {
+            let mut rdr = csv::Reader::from_path(path)?;
+            SpatialLayer::load(dataset, &mut rdr, policy)
+        })
+    }
+}
+
+impl<T> SpatialLayer<T> {
+    pub fn from_polygons(polygons: Vec<PolygonWrapper<T>>) -> Self {
+        SpatialLayer {
+            rtree: RTree::bulk_load(polygons),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rtree.size()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rtree.size() == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PolygonWrapper<T>> {
+        self.rtree.iter()
+    }
+
+    /// One shape that contains the point, if any
+    pub fn at_point(&self, point: &Point<f64>) -> Option<&PolygonWrapper<T>> {
+        self.rtree.locate_at_point(&[point.x(), point.y()])
+    }
+
+    /// All shapes that contain the point (shapes in some datasets overlap)
+    pub fn all_at_point<'a>(
+        &'a self,
+        point: &Point<f64>,
+    ) -> impl Iterator<Item = &'a PolygonWrapper<T>> + 'a {
+        self.rtree.locate_all_at_point(&[point.x(), point.y()])
+    }
+
+    /// The shape that contains the centroid of `shape`
+    pub fn at_centroid(&self, shape: &MultiPolygon<f64>) -> Option<&PolygonWrapper<T>> {
+        let centroid: Point<f64> = shape
+            .centroid()
+            .expect("multipolygon should have at least one point");
+        self.at_point(&centroid)
+    }
+
+    /// All shapes whose bounding boxes intersect the envelope
+    pub fn in_envelope<'a>(
+        &'a self,
+        envelope: &AABB<[f64; 2]>,
+    ) -> impl Iterator<Item = &'a PolygonWrapper<T>> + 'a {
+        self.rtree.locate_in_envelope_intersecting(envelope)
+    }
+
+    /// The `k` shapes closest to the point, nearest first. Shapes containing the point come first.
+    pub fn nearest(&self, point: &Point<f64>, k: usize) -> Vec<&PolygonWrapper<T>> {
+        self.rtree
+            .nearest_neighbor_iter(&[point.x(), point.y()])
+            .take(k)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HasGeometry, SpatialLayer};
+    use crate::csv_loader::RowErrorPolicy;
+    use crate::dataset_cache::{CacheMode, DatasetCache};
+    use crate::schema::RecordSchema;
+    use csv::Reader;
+    use geo::Point;
+    use rstar::AABB;
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct TestRecord {
+        name: String,
+        the_geom: String,
+    }
+    impl HasGeometry for TestRecord {
+        fn wkt(&self) -> &str {
+            &self.the_geom
+        }
+    }
+    impl RecordSchema for TestRecord {}
+    const TEST_LINES: &str = "name,the_geom
+a,\"POLYGON ((0 0, 1 0, 1 1, 0 1, 0 0))\"
+b,\"POLYGON ((2 0, 3 0, 3 1, 2 1, 2 0))\"
+bad,\"POINT (0 0)\"
+c,\"MULTIPOLYGON (((10 0, 11 0, 11 1, 10 1, 10 0)))\"
+";
+
+    fn names<'a>(
+        polygons: impl IntoIterator<Item = &'a crate::polygon_wrapper::PolygonWrapper<TestRecord>>,
+    ) -> Vec<&'a str> {
+        polygons.into_iter().map(|p| &*p.value.name).collect()
+    }
+
+    #[test]
+    fn test_queries() {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let layer = SpatialLayer::<TestRecord>::load("test", &mut rdr, &RowErrorPolicy::Skip)
+            .expect("skip policy should not fail");
+        assert_eq!(3, layer.len());
+        assert_eq!(
+            Some("b"),
+            layer.at_point(&Point::new(2.5, 0.5)).map(|p| &*p.value.name)
+        );
+        assert!(layer.at_point(&Point::new(1.5, 0.5)).is_none());
+        let mut in_envelope = names(layer.in_envelope(&AABB::from_corners([0.5, 0.5], [2.5, 0.5])));
+        in_envelope.sort();
+        assert_eq!(vec!["a", "b"], in_envelope);
+        assert_eq!(vec!["b", "a"], names(layer.nearest(&Point::new(1.8, 0.5), 2)));
+        assert_eq!(vec!["c", "b"], names(layer.nearest(&Point::new(9.0, 0.5), 2)));
+    }
+
+    #[test]
+    fn test_fail_on_bad_geometry() {
+        let mut rdr = Reader::from_reader(TEST_LINES.as_bytes());
+        let result = SpatialLayer::<TestRecord>::load("test", &mut rdr, &RowErrorPolicy::Fail);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_load_cached() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("parcelscan-layer-{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let source = dir.join("test.csv");
+        std::fs::write(&source, TEST_LINES)?;
+        let cache = DatasetCache::new(dir.join("cache"), CacheMode::Use);
+        let built = SpatialLayer::<TestRecord>::load_cached("test", &source, &RowErrorPolicy::Skip, &cache)?;
+        let cached = SpatialLayer::<TestRecord>::load_cached("test", &source, &RowErrorPolicy::Skip, &cache)?;
+        assert_eq!(built.len(), cached.len());
+        assert_eq!(
+            names(built.nearest(&Point::new(1.8, 0.5), 3)),
+            names(cached.nearest(&Point::new(1.8, 0.5), 3))
+        );
+        // the cached layer has already dropped the bad row, so the other policies rebuild it
+        let failed = SpatialLayer::<TestRecord>::load_cached("test", &source, &RowErrorPolicy::Fail, &cache);
+        assert!(failed.is_err());
+        let quarantine_dir = dir.join("quarantine");
+        let quarantined = SpatialLayer::<TestRecord>::load_cached(
+            "test",
+            &source,
+            &RowErrorPolicy::Quarantine(quarantine_dir.clone()),
+            &cache,
+        )?;
+        assert_eq!(built.len(), quarantined.len());
+        assert!(quarantine_dir.join("test_rejects.csv").is_file());
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
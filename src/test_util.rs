@@ -0,0 +1,79 @@
+//! Records for the tests of more than one module
+
+use crate::polygon_wrapper::{parse_wkt_to_multipolygon, PolygonWrapper};
+use crate::schema::RecordSchema;
+use crate::sfassessormap::TaxProperty;
+use crate::sflanduse::{LandUseCategory, LandUseRecord};
+use crate::xlsxdeserialize::SheetRecords;
+use calamine::{DataType, Range};
+
+/// A residential parcel with blklot e.g. `3731012` on the map lot `mapblklot`
+pub fn land_use(blklot: &str, mapblklot: &str, the_geom: &str) -> PolygonWrapper<LandUseRecord> {
+    let (block_num, lot_num) = blklot.split_at(blklot.len().min(4));
+    let record = LandUseRecord {
+        objectid: 0,
+        blklot: blklot.to_string(),
+        the_geom: the_geom.to_string(),
+        mapblklot: mapblklot.to_string(),
+        block_num: block_num.to_string(),
+        lot_num: lot_num.to_string(),
+        from_st: None,
+        to_st: None,
+        street: String::new(),
+        st_type: String::new(),
+        resunits: 0,
+        bldgsqft: 0,
+        yrbuilt: 0,
+        total_uses: 0,
+        landuse: LandUseCategory::Resident,
+        cie: 0,
+        med: 0,
+        mips: 0,
+        retail: 0,
+        pdr: 0,
+        visitor: 0,
+        shape_leng: 0.0,
+        shape_area: 0.0,
+    };
+    PolygonWrapper::new(parse_wkt_to_multipolygon(the_geom).unwrap(), record)
+}
+
+/// A roll sheet with the given (column, value) cells in each row, and an extra column that the record does not read
+pub fn roll_sheet(rows: &[&[(&str, DataType)]]) -> Range<DataType> {
+    roll_sheet_without(&[], rows)
+}
+
+/// A roll sheet like `roll_sheet` without the `missing` columns
+pub fn roll_sheet_without(missing: &[&str], rows: &[&[(&str, DataType)]]) -> Range<DataType> {
+    let mut header: Vec<&str> = TaxProperty::columns()
+        .iter()
+        .cloned()
+        .filter(|column| !missing.contains(column))
+        .collect();
+    header.push("EXTRA");
+    let mut range = Range::new((0, 0), (rows.len() as u32, header.len() as u32 - 1));
+    for (col, column) in header.iter().enumerate() {
+        range.set_value((0, col as u32), DataType::String(column.to_string()));
+    }
+    for (row, cells) in rows.iter().enumerate() {
+        for (col, column) in header.iter().enumerate() {
+            let value = match cells.iter().find(|(name, _)| name == column) {
+                Some((_, value)) => value.clone(),
+                None if *column == "LAREA" => DataType::Float(2500.0),
+                None => DataType::Empty,
+            };
+            range.set_value((row as u32 + 1, col as u32), value);
+        }
+    }
+    range
+}
+
+/// A property with the given (column, value) cells; other columns are blank
+pub fn tax_property(cells: &[(&str, DataType)]) -> TaxProperty {
+    let sheets = vec![("test".to_string(), roll_sheet(&[cells]))];
+    SheetRecords::<TaxProperty>::from_ranges(sheets)
+        .next()
+        .expect("one row")
+        .expect("valid row")
+        .record
+}
@@ -1,68 +1,486 @@
+//! Deserialize the rows of a spreadsheet (calamine `Range`) into records, like `csv::Reader::deserialize`.
+//!
+//! Each row is a map from the header names to its cells, so the columns can be in any order,
+//! columns with a blank header are skipped, and columns that the record does not read are ignored.
+//! `deserialize_records` also renames columns as in `schema::HeaderDiff`.
+//! Errors give the sheet, row (numbered from 1 like in Excel) and column name.
+//! `SheetRecords` reads the records of every sheet of a workbook (or the sheets whose names
+//! match a pattern), e.g. a roll that is split into a sheet per year.
+
+use calamine::open_workbook_auto;
 use calamine::DataType;
+use calamine::Range;
+use calamine::Reader;
 use calamine::Rows;
 use conv::errors::UnwrapOrInf;
 use conv::ApproxInto;
 use conv::GeneralError;
 use conv::ValueInto;
-use serde::de::value::BorrowedStrDeserializer;
-use serde::de::DeserializeSeed;
-use serde::de::MapAccess;
-use serde::de::Visitor;
+use crate::schema::{HeaderDiff, RecordSchema};
+use serde::de::value::{SeqDeserializer, StrDeserializer};
+use serde::de::{DeserializeSeed, IntoDeserializer, MapAccess, Visitor};
 use serde::Deserialize;
 use serde::Deserializer;
 use std;
-use std::iter::Peekable;
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
+use std::path::Path;
+use thiserror::Error;
 
 // See csv deserializer for how to implement a Deserializer
 // https://github.com/BurntSushi/rust-csv/blob/master/src/deserializer.rs
 
-pub fn deserialize<'de, T: Deserialize<'de>>(rows: Rows<'de, DataType>) -> RowsDeserializeIter<T> {
+type Error = serde::de::value::Error;
+
+/// A cell that could not be deserialized
+#[derive(Error, Debug)]
+#[error(
+    "sheet {sheet} row {row}{}: {source}",
+    .column.as_ref().map(|column| format!(" column {}", column)).unwrap_or_default()
+)]
+pub struct XlsxDeError {
+    pub sheet: String,
+    /// Excel row number, starting from 1
+    pub row: usize,
+    /// None if the error is not about one cell, e.g. a missing column
+    pub column: Option<String>,
+    pub source: Error,
+}
+
+/// Rows of a range whose first row is the header
+pub fn deserialize<'de, T: Deserialize<'de>>(rows: Rows<'de, DataType>) -> RowsDeserializeIter<'de, T> {
     RowsDeserializeIter::new(rows)
 }
+
+/// Rows of a sheet whose header is row `header_row` of the range (0 for the first row),
+/// with the columns renamed to the names that `T` reads
+pub fn deserialize_records<'de, T: RecordSchema>(
+    range: &'de Range<DataType>,
+    sheet_name: &str,
+    header_row: usize,
+) -> RowsDeserializeIter<'de, T> {
+    let rows = RowsDeserializeIter::from_range(range, header_row).sheet_name(sheet_name);
+    let diff = HeaderDiff::for_record::<T>(&rows.header());
+    diff.log(sheet_name);
+    rows.rename_header(&diff)
+}
+
 pub struct RowsDeserializeIter<'de, T> {
-    header: Vec<Option<&'de str>>,
+    sheet_name: String,
+    header: Vec<Option<String>>,
     rows: Rows<'de, DataType>,
-    _phantom: PhantomData<&'de ()>,
-    _phantom_t: PhantomData<&'de T>,
+    /// Excel row number of the next row
+    row_number: usize,
+    _phantom_t: PhantomData<T>,
 }
+
+fn header_name(cell: &DataType) -> Option<String> {
+    let name = match cell {
+        DataType::String(s) => s.trim().to_string(),
+        DataType::Int(i) => i.to_string(),
+        DataType::Float(f) => f.to_string(),
+        _ => return None,
+    };
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
 impl<'de, T: Deserialize<'de>> RowsDeserializeIter<'de, T> {
-    fn new(mut rows: Rows<'de, DataType>) -> Self {
-        let header_row: &[DataType] = rows.next().unwrap_or(&[]);
-        let header = header_row
-            .iter()
-            .map(|data_type: &DataType| data_type.get_string())
-            .collect::<Vec<Option<&str>>>();
+    fn new(rows: Rows<'de, DataType>) -> Self {
+        RowsDeserializeIter::with_header_row(rows, 0, 1)
+    }
+
+    /// Rows of `range` below the header row `header_row`; rows above it (e.g. titles) are skipped
+    pub fn from_range(range: &'de Range<DataType>, header_row: usize) -> Self {
+        let first_row = range.start().map_or(0, |(row, _)| row as usize);
+        RowsDeserializeIter::with_header_row(range.rows(), header_row, first_row + 1)
+    }
 
+    fn with_header_row(mut rows: Rows<'de, DataType>, header_row: usize, first_row_number: usize) -> Self {
+        let header_cells: &[DataType] = rows.nth(header_row).unwrap_or(&[]);
         RowsDeserializeIter {
-            header,
+            sheet_name: String::new(),
+            header: header_cells.iter().map(header_name).collect(),
             rows,
-            _phantom: PhantomData,
+            row_number: first_row_number + header_row + 1,
             _phantom_t: PhantomData,
         }
     }
+
+    /// Name of the sheet, for errors
+    pub fn sheet_name(mut self, sheet_name: &str) -> Self {
+        self.sheet_name = sheet_name.to_string();
+        self
+    }
+
+    /// The header, with blank names for blank header cells
+    pub fn header(&self) -> Vec<String> {
+        self.header.iter().map(|name| name.clone().unwrap_or_default()).collect()
+    }
+
+    /// Give the renamed columns of `diff` the names that the record reads
+    pub fn rename_header(mut self, diff: &HeaderDiff) -> Self {
+        self.header = diff
+            .rename_header(&self.header())
+            .into_iter()
+            .map(|name| if name.is_empty() { None } else { Some(name) })
+            .collect();
+        self
+    }
 }
+
 impl<'de, T: Deserialize<'de>> Iterator for RowsDeserializeIter<'de, T> {
-    type Item = Result<T, serde::de::value::Error>;
+    type Item = Result<T, XlsxDeError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let row = self.rows.next()?;
+            let row_number = self.row_number;
+            self.row_number += 1;
+            if let Some(result) = deserialize_row(&self.header, row, &self.sheet_name, row_number) {
+                return Some(result);
+            }
+        }
+    }
+}
+
+/// Deserialize one row with the names in `header`, or None if the row is blank
+fn deserialize_row<'de, T: Deserialize<'de>>(
+    header: &[Option<String>],
+    row: &'de [DataType],
+    sheet_name: &str,
+    row_number: usize,
+) -> Option<Result<T, XlsxDeError>> {
+    if row.iter().all(|cell| cell.is_empty()) {
+        return None;
+    }
+    let mut deserializer = RowDeserializer {
+        header,
+        row,
+        col: 0,
+        value: None,
+        column: None,
+    };
+    let result = T::deserialize(&mut deserializer);
+    Some(result.map_err(|source| XlsxDeError {
+        sheet: sheet_name.to_string(),
+        row: row_number,
+        column: deserializer.column.map(String::from),
+        source,
+    }))
+}
+
+/// A record and the name of the sheet that it was read from
+#[derive(Clone, Debug, PartialEq)]
+pub struct SheetRecord<T> {
+    pub sheet: String,
+    pub record: T,
+}
+
+#[derive(Error, Debug)]
+pub enum WorkbookError {
+    #[error("xlsx error: {0}")]
+    Xlsx(String),
+    #[error("sheet {sheet}: {diff}")]
+    IncompatibleHeader { sheet: String, diff: HeaderDiff },
+    #[error(transparent)]
+    Row(#[from] XlsxDeError),
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any characters and `?` matches one character
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // matches[j]: whether the pattern so far matches the first j characters of the name
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for p in pattern {
+        let mut next = vec![false; name.len() + 1];
+        for j in 0..=name.len() {
+            next[j] = match p {
+                '*' => matches[j] || (j > 0 && next[j - 1]),
+                '?' => j > 0 && matches[j - 1],
+                _ => j > 0 && matches[j - 1] && name[j - 1] == p,
+            };
+        }
+        matches = next;
+    }
+    matches[name.len()]
+}
+
+type LoadSheet = Box<dyn FnMut(&str) -> Result<Range<DataType>, WorkbookError>>;
+
+/// The records of the sheets of a workbook, in sheet order, tagged with their sheet names.
+/// Every sheet is checked to have the columns that `T` reads (possibly renamed) before it is read.
+/// Sheets are loaded one at a time, and their rows are deserialized as they are read.
+pub struct SheetRecords<T> {
+    sheet_names: Vec<String>,
+    load_sheet: LoadSheet,
+    pattern: Option<String>,
+    header_row: usize,
+    /// sheets that have none of these columns are skipped
+    key_columns: Vec<String>,
+    /// index of the next sheet
+    next_sheet: usize,
+    /// the sheet that is being read
+    sheet: Option<CurrentSheet>,
+    _phantom_t: PhantomData<T>,
+}
+
+/// A loaded sheet and how far it has been read
+struct CurrentSheet {
+    name: String,
+    range: Range<DataType>,
+    /// header renamed to the names that the record reads
+    header: Vec<Option<String>>,
+    /// index in `range` of the next row
+    next_row: usize,
+    /// Excel row number of the first row of `range`
+    first_row_number: usize,
+    records: usize,
+}
+
+impl<T: RecordSchema> SheetRecords<T> {
+    /// The sheets of an xlsx, xls or ods file
+    pub fn open(path: &Path) -> Result<Self, WorkbookError> {
+        let mut workbook = open_workbook_auto(path).map_err(|err| WorkbookError::Xlsx(format!("{:?}", err)))?;
+        let sheet_names = workbook.sheet_names().to_vec();
+        let load_sheet = move |sheet_name: &str| {
+            workbook
+                .worksheet_range(sheet_name)
+                .ok_or_else(|| WorkbookError::Xlsx(format!("missing worksheet {}", sheet_name)))?
+                .map_err(|err| WorkbookError::Xlsx(format!("{:?}", err)))
+        };
+        Ok(SheetRecords::new(sheet_names, Box::new(load_sheet)))
+    }
+
+    /// Sheets that are already loaded, e.g. from another reader
+    pub fn from_ranges(ranges: Vec<(String, Range<DataType>)>) -> Self {
+        let sheet_names = ranges.iter().map(|(sheet_name, _)| sheet_name.clone()).collect();
+        let mut ranges: BTreeMap<String, Range<DataType>> = ranges.into_iter().collect();
+        let load_sheet = move |sheet_name: &str| {
+            ranges
+                .remove(sheet_name)
+                .ok_or_else(|| WorkbookError::Xlsx(format!("missing worksheet {}", sheet_name)))
+        };
+        SheetRecords::new(sheet_names, Box::new(load_sheet))
+    }
+
+    fn new(sheet_names: Vec<String>, load_sheet: LoadSheet) -> Self {
+        SheetRecords {
+            sheet_names,
+            load_sheet,
+            pattern: None,
+            header_row: 0,
+            key_columns: vec![],
+            next_sheet: 0,
+            sheet: None,
+            _phantom_t: PhantomData,
+        }
+    }
+
+    /// Only read the sheets whose names match `pattern`, e.g. `20*`.
+    /// `*` matches any characters and `?` matches one character.
+    pub fn sheets_matching(mut self, pattern: &str) -> Self {
+        self.pattern = Some(pattern.to_string());
+        self
+    }
+
+    /// Row of the header in every sheet (0 for the first row)
+    pub fn header_row(mut self, header_row: usize) -> Self {
+        self.header_row = header_row;
+        self
+    }
+
+    /// Skip sheets that have none of `key_columns` (e.g. a sheet of notes)
+    /// instead of failing with `WorkbookError::IncompatibleHeader`.
+    /// A sheet with a key column that is missing other columns of `T` still fails.
+    pub fn skip_sheets_without(mut self, key_columns: &[&str]) -> Self {
+        self.key_columns = key_columns.iter().map(|column| column.to_string()).collect();
+        self
+    }
+
+    /// The names of the sheets that will be read, before their headers are checked
+    pub fn sheet_names(&self) -> Vec<&str> {
+        self.sheet_names
+            .iter()
+            .filter(|sheet_name| self.reads_sheet(sheet_name))
+            .map(String::as_str)
+            .collect()
+    }
+
+    fn reads_sheet(&self, sheet_name: &str) -> bool {
+        match &self.pattern {
+            Some(pattern) => matches_pattern(pattern, sheet_name),
+            None => true,
+        }
+    }
+
+    /// Load the next sheet whose header is compatible into `self.sheet`.
+    /// Returns false if there are no more sheets.
+    fn read_next_sheet(&mut self) -> Result<bool, WorkbookError> {
+        while self.next_sheet < self.sheet_names.len() {
+            let sheet_name = self.sheet_names[self.next_sheet].clone();
+            self.next_sheet += 1;
+            if !self.reads_sheet(&sheet_name) {
+                continue;
+            }
+            let range = (self.load_sheet)(&sheet_name)?;
+            let rows = RowsDeserializeIter::<T>::from_range(&range, self.header_row);
+            let diff = HeaderDiff::for_record::<T>(&rows.header());
+            if !diff.is_compatible() {
+                if !self.key_columns.is_empty() && !self.key_columns.iter().any(|column| diff.has_column(column)) {
+                    info!("Skipping sheet {}: {}", sheet_name, diff);
+                    continue;
+                }
+                return Err(WorkbookError::IncompatibleHeader { sheet: sheet_name, diff });
+            }
+            diff.log(&sheet_name);
+            let header = rows.rename_header(&diff).header;
+            let first_row_number = range.start().map_or(0, |(row, _)| row as usize) + 1;
+            self.sheet = Some(CurrentSheet {
+                name: sheet_name,
+                range,
+                header,
+                next_row: self.header_row + 1,
+                first_row_number,
+                records: 0,
+            });
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+impl<T: RecordSchema> Iterator for SheetRecords<T> {
+    type Item = Result<SheetRecord<T>, WorkbookError>;
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(data_types) = self.rows.next() {
-            let row_iter = data_types.iter().peekable();
-            let header_iter = self.header.iter();
-            Some(T::deserialize(&mut RowDeserializer {
-                row_iter,
-                header_iter,
-            }))
-        } else {
-            None
+        loop {
+            if let Some(sheet) = self.sheet.as_mut() {
+                while sheet.next_row < sheet.range.height() {
+                    let row = &sheet.range[sheet.next_row];
+                    let row_number = sheet.first_row_number + sheet.next_row;
+                    sheet.next_row += 1;
+                    if let Some(result) = deserialize_row::<T>(&sheet.header, row, &sheet.name, row_number) {
+                        sheet.records += 1;
+                        return Some(
+                            result
+                                .map(|record| SheetRecord { sheet: sheet.name.clone(), record })
+                                .map_err(WorkbookError::from),
+                        );
+                    }
+                }
+                info!("Sheet {}: {} records", sheet.name, sheet.records);
+                self.sheet = None;
+            }
+            match self.read_next_sheet() {
+                Ok(true) => {}
+                Ok(false) => return None,
+                Err(err) => return Some(Err(err)),
+            }
         }
     }
 }
 
+static EMPTY: DataType = DataType::Empty;
+
+/// A row, deserialized as a map from header names to cells (or as a sequence of cells)
 struct RowDeserializer<'a, 'de> {
-    header_iter: std::slice::Iter<'a, Option<&'de str>>,
-    row_iter: Peekable<std::slice::Iter<'de, DataType>>,
+    header: &'a [Option<String>],
+    row: &'de [DataType],
+    /// index of the next column
+    col: usize,
+    /// cell of the key that was just read
+    value: Option<&'de DataType>,
+    /// name of the column that was read last, for errors
+    column: Option<&'a str>,
+}
+
+impl<'a, 'de> Deserializer<'de> for &mut RowDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    /// The cells of the row in order, ignoring the header
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(SeqDeserializer::new(self.row.iter().map(|cell| CellDeserializer { cell })))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct map struct enum identifier
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for RowDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        let header: &'a [Option<String>] = self.header;
+        while self.col < header.len() {
+            let col = self.col;
+            self.col += 1;
+            if let Some(name) = &header[col] {
+                self.value = Some(self.row.get(col).unwrap_or(&EMPTY));
+                self.column = Some(name);
+                let key: StrDeserializer<Error> = name.as_str().into_deserializer();
+                return seed.deserialize(key).map(Some);
+            }
+        }
+        // errors after the last column (e.g. a missing field) are not about the last column
+        self.column = None;
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let cell = self.value.take().expect("next_value_seed should be called after next_key_seed");
+        seed.deserialize(CellDeserializer { cell })
+    }
+}
+
+/// One cell. Numbers, booleans and strings are converted to each other where that is lossless,
+/// e.g. the string `12` is an int and the float 12.0 is the string `12`.
+struct CellDeserializer<'de> {
+    cell: &'de DataType,
+}
+
+impl<'de> IntoDeserializer<'de, Error> for CellDeserializer<'de> {
+    type Deserializer = Self;
+    fn into_deserializer(self) -> Self {
+        self
+    }
 }
-fn conv_error_to_serde_error<T, E: Into<GeneralError<T>>>(error: E) -> serde::de::value::Error {
+
+fn conv_error_to_serde_error<T, E: Into<GeneralError<T>>>(error: E) -> Error {
     let error: GeneralError<T> = error.into();
     match error {
         GeneralError::NegOverflow(_n) => serde::de::Error::custom("NegOverflow"),
@@ -70,55 +488,81 @@ fn conv_error_to_serde_error<T, E: Into<GeneralError<T>>>(error: E) -> serde::de
         GeneralError::Unrepresentable(_n) => serde::de::Error::custom("Unrepresentable"),
     }
 }
+
+fn unexpected(expected: &str, cell: &DataType) -> Error {
+    serde::de::Error::custom(format!("expected {} cell, got {:?}", expected, cell))
+}
+
+impl<'de> CellDeserializer<'de> {
+    fn int(&self) -> Result<i64, Error> {
+        match self.cell {
+            DataType::Bool(value) => Ok(if *value { 1 } else { 0 }),
+            DataType::Int(value) => Ok(*value),
+            // Excel stores all numbers as floats
+            DataType::Float(value) if value.fract() == 0f64 => Ok(*value as i64),
+            DataType::String(value) => value.trim().parse().map_err(|_| unexpected("int", self.cell)),
+            _ => Err(unexpected("int", self.cell)),
+        }
+    }
+
+    fn float(&self) -> Result<f64, Error> {
+        match self.cell {
+            DataType::Int(value) => Ok(ValueInto::value_into(*value).unwrap_or_inf()),
+            DataType::Float(value) => Ok(*value),
+            DataType::String(value) => value.trim().parse().map_err(|_| unexpected("float", self.cell)),
+            _ => Err(unexpected("float", self.cell)),
+        }
+    }
+
+    /// Text of a cell that is not a string
+    fn to_text(&self) -> Result<String, Error> {
+        match self.cell {
+            DataType::String(value) => Ok(value.clone()),
+            DataType::Int(value) => Ok(value.to_string()),
+            DataType::Float(value) => Ok(value.to_string()),
+            DataType::Bool(value) => Ok(value.to_string()),
+            DataType::Empty => Ok(String::new()),
+            DataType::Error(_) => Err(unexpected("string", self.cell)),
+        }
+    }
+}
+
 macro_rules! deserialize_int {
     ($method:ident, $visit:ident, $ty:ty) => {
-        fn $method<V: Visitor<'de>>(
-            self,
-            visitor: V,
-        ) -> Result<V::Value, Self::Error> {
-            let n = match self.row_iter.next() {
-                Some(&DataType::Bool(value)) => if value {1} else {0},
-                Some(&DataType::Int(value)) => ValueInto::<$ty>::value_into(value)
-                    .map_err(conv_error_to_serde_error::<i64, _>)?,
-                Some(&DataType::Float(_)) => return Err(serde::de::Error::custom("Expected int, got float")),
-                Some(&DataType::String(_)) => return Err(serde::de::Error::custom("Expected int, got string")),
-                Some(&DataType::Error(_)) => return Err(serde::de::Error::custom("Expected int, got Error")),
-                Some(&DataType::Empty) => return Err(serde::de::Error::custom("Expected int, got Empty")),
-                None => return Err(serde::de::Error::custom("Empty cell instead of number")),
-            };
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+            let n: $ty = ValueInto::<$ty>::value_into(self.int()?).map_err(conv_error_to_serde_error::<i64, _>)?;
             visitor.$visit(n)
         }
-    }
+    };
 }
 
-impl<'a, 'de> Deserializer<'de> for &mut RowDeserializer<'a, 'de> {
-    type Error = serde::de::value::Error;
-
-    fn deserialize_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
-    }
-
-    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        match self.row_iter.next() {
-            Some(&DataType::Bool(value)) => visitor.visit_bool(value),
-            Some(&DataType::Int(value)) => visitor.visit_bool(value != 0),
-            Some(&DataType::Float(value)) => visitor.visit_bool(value != 0f64),
-            //            Some(&DataType::Empty) | None => visitor.visit_bool(false),
-            Some(_) => {
-                let error: serde::de::value::Error = serde::de::Error::custom("expected bool cell");
-                Err(error)
-            }
-            None => {
-                let error: serde::de::value::Error =
-                    serde::de::Error::custom("expected bool cell; got None");
-                Err(error)
-            }
+impl<'de> Deserializer<'de> for CellDeserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::Int(value) => visitor.visit_i64(*value),
+            // whole numbers as ints, so that they can be buffered (e.g. for `#[serde(flatten)]`) into int fields
+            DataType::Float(value) if value.fract() == 0f64 && value.abs() < 1e15 => visitor.visit_i64(*value as i64),
+            DataType::Float(value) => visitor.visit_f64(*value),
+            DataType::String(value) => visitor.visit_borrowed_str(value),
+            DataType::Bool(value) => visitor.visit_bool(*value),
+            DataType::Empty => visitor.visit_none(),
+            DataType::Error(_) => Err(unexpected("non-error", self.cell)),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::Bool(value) => visitor.visit_bool(*value),
+            DataType::Int(value) => visitor.visit_bool(*value != 0),
+            DataType::Float(value) => visitor.visit_bool(*value != 0f64),
+            DataType::String(value) => match value.trim().to_ascii_lowercase().as_str() {
+                "true" => visitor.visit_bool(true),
+                "false" => visitor.visit_bool(false),
+                _ => Err(unexpected("bool", self.cell)),
+            },
+            _ => Err(unexpected("bool", self.cell)),
         }
     }
 
@@ -131,218 +575,366 @@ impl<'a, 'de> Deserializer<'de> for &mut RowDeserializer<'a, 'de> {
     deserialize_int!(deserialize_u32, visit_u32, u32);
     deserialize_int!(deserialize_u64, visit_u64, u64);
 
-    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.row_iter.next() {
-            Some(&DataType::Bool(_value)) => {
-                Err(serde::de::Error::custom("expected float cell, got bool"))
-            }
-            Some(&DataType::Int(value)) => {
-                visitor.visit_f32(ValueInto::value_into(value).unwrap_or_inf())
-            }
-            Some(&DataType::Float(value)) => {
-                visitor.visit_f32(ApproxInto::approx_into(value).unwrap_or_inf())
-            }
-            //            Some(&DataType::Empty) | None => visitor.visit_bool(false),
-            Some(_) => {
-                let error: serde::de::value::Error =
-                    serde::de::Error::custom("expected float cell");
-                Err(error)
-            }
-            None => {
-                let error: serde::de::value::Error =
-                    serde::de::Error::custom("expected bool cell; got None");
-                Err(error)
-            }
-        }
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f32(ApproxInto::approx_into(self.float()?).unwrap_or_inf())
     }
-    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.row_iter.next() {
-            Some(&DataType::Bool(_value)) => {
-                Err(serde::de::Error::custom("expected float cell, got bool"))
-            }
-            Some(&DataType::Int(value)) => {
-                visitor.visit_f64(ValueInto::value_into(value).unwrap_or_inf())
-            }
-            Some(&DataType::Float(value)) => visitor.visit_f64(value),
-            //            Some(&DataType::Empty) | None => visitor.visit_bool(false),
-            Some(_) => {
-                let error: serde::de::value::Error =
-                    serde::de::Error::custom("expected float cell");
-                Err(error)
-            }
-            None => {
-                let error: serde::de::value::Error =
-                    serde::de::Error::custom("expected bool cell; got None");
-                Err(error)
-            }
-        }
-    }
-    fn deserialize_char<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
-        unimplemented!()
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_f64(self.float()?)
     }
 
-    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.row_iter.next() {
-            Some(&DataType::String(ref value)) => visitor.visit_str(&value),
-            _ => return Err(serde::de::Error::custom("expected string cell")),
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let text = self.to_text()?;
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(unexpected("single character", self.cell)),
         }
     }
 
-    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.row_iter.next() {
-            Some(&DataType::String(ref value)) => visitor.visit_string(value.clone()),
-            Some(&DataType::Error(_)) => {
-                return Err(serde::de::Error::custom("expected string cell; got error"))
-            }
-            Some(&DataType::Bool(_)) => {
-                return Err(serde::de::Error::custom("expected string cell; got bool"))
-            }
-            Some(&DataType::Int(value)) => visitor.visit_string(format!("{}", value)),
-            Some(&DataType::Float(value)) => visitor.visit_string(format!("{}", value)),
-            Some(&DataType::Empty) => {
-                return Err(serde::de::Error::custom("expected string cell; got empty"))
-            }
-            None => return Err(serde::de::Error::custom("expected string cell; got None")),
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::String(value) => visitor.visit_borrowed_str(value),
+            _ => visitor.visit_string(self.to_text()?),
         }
     }
 
-    fn deserialize_bytes<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_byte_buf<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::String(value) => visitor.visit_borrowed_bytes(value.as_bytes()),
+            _ => visitor.visit_byte_buf(self.to_text()?.into_bytes()),
+        }
     }
 
-    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
-        match self.row_iter.peek() {
-            Some(DataType::Empty) | None => visitor.visit_none(),
-            Some(_) => visitor.visit_some(self),
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    /// Empty and blank cells are None
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::Empty => visitor.visit_none(),
+            DataType::String(value) if value.trim().is_empty() => visitor.visit_none(),
+            _ => visitor.visit_some(self),
         }
     }
 
-    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::Empty => visitor.visit_unit(),
+            _ => Err(unexpected("empty", self.cell)),
+        }
     }
 
-    fn deserialize_unit_struct<V>(
-        self,
-        _name: &'static str,
-        _visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_unit_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
     }
 
-    fn deserialize_newtype_struct<V>(
-        self,
-        _name: &'static str,
-        _visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
     }
 
-    fn deserialize_seq<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    /// A comma separated list, e.g. `a, b`; a number is a list of one
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.cell {
+            DataType::String(value) => visitor.visit_seq(SeqDeserializer::new(
+                value.split(',').map(str::trim).filter(|item| !item.is_empty()),
+            )),
+            DataType::Empty => visitor.visit_seq(SeqDeserializer::new(std::iter::empty::<CellDeserializer>())),
+            _ => visitor.visit_seq(SeqDeserializer::new(std::iter::once(self))),
+        }
     }
 
-    fn deserialize_tuple<V>(self, _len: usize, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
     }
 
-    fn deserialize_tuple_struct<V>(
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
         self,
         _name: &'static str,
         _len: usize,
-        _visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
     }
 
-    fn deserialize_map<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_map<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(unexpected("map (a cell can't be a map)", self.cell))
     }
 
     fn deserialize_struct<V: Visitor<'de>>(
         self,
         _name: &'static str,
         _fields: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value, Self::Error> {
-        visitor.visit_map(self)
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        Err(unexpected("struct (a cell can't be a struct)", self.cell))
     }
 
-    fn deserialize_enum<V>(
+    /// Unit variants, by name
+    fn deserialize_enum<V: Visitor<'de>>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
-        _visitor: V,
-    ) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let text = self.to_text()?;
+        let variant: StrDeserializer<Error> = text.trim().into_deserializer();
+        variant.deserialize_enum(name, variants, visitor)
     }
 
-    fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
     }
 
-    fn deserialize_ignored_any<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
-    where
-        V: Visitor<'de>,
-    {
-        unimplemented!()
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
     }
 }
-impl<'a, 'de> MapAccess<'de> for RowDeserializer<'a, 'de> {
-    type Error = serde::de::value::Error;
-
-    fn next_key_seed<K: DeserializeSeed<'de>>(
-        &mut self,
-        seed: K,
-    ) -> Result<Option<K::Value>, Self::Error> {
-        let key = match self.header_iter.next() {
-            None | Some(None) => return Ok(None),
-            Some(&Some(ref key)) => key,
+
+/// A date cell (a number of days since 1900, which calamine converts), or a date string as in
+/// `sfplanningacela::lenient_date`. Empty cells are None.
+pub mod date_cell {
+    use calamine::DataType;
+    use chrono::{DateTime, TimeZone, Utc};
+    use serde::de::{self, IntoDeserializer, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+
+    pub fn serialize<S: Serializer>(date: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::sfplanningacela::lenient_date::serialize(date, serializer)
+    }
+
+    struct DateCellVisitor;
+
+    impl<'de> Visitor<'de> for DateCellVisitor {
+        type Value = Option<DateTime<Utc>>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a date cell or date string")
+        }
+
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+            DataType::Float(value)
+                .as_datetime()
+                .map(|date| Some(Utc.from_utc_datetime(&date)))
+                .ok_or_else(|| E::custom(format!("not a date: {}", value)))
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+            self.visit_f64(value as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+            self.visit_f64(value as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+            let deserializer: de::value::StrDeserializer<E> = value.into_deserializer();
+            crate::sfplanningacela::lenient_date::deserialize(deserializer)
+        }
+
+        fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        deserializer.deserialize_any(DateCellVisitor)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deserialize, deserialize_records, matches_pattern, RowsDeserializeIter, SheetRecords, WorkbookError};
+    use crate::schema::RecordSchema;
+    use calamine::{DataType, Range};
+    use chrono::{DateTime, Utc};
+    use std::collections::BTreeMap;
+
+    fn range(rows: &[&[DataType]]) -> Range<DataType> {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(1);
+        let mut range = Range::new((0, 0), (rows.len() as u32 - 1, width as u32 - 1));
+        for (r, row) in rows.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                range.set_value((r as u32, c as u32), cell.clone());
+            }
+        }
+        range
+    }
+
+    fn s(s: &str) -> DataType {
+        DataType::String(s.to_string())
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Kind {
+        Flat,
+        House,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Apn(String);
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Extra {
+        #[serde(rename = "Units")]
+        units: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Property {
+        #[serde(rename = "APN")]
+        apn: Apn,
+        #[serde(rename = "Kind")]
+        kind: Kind,
+        #[serde(rename = "Class")]
+        class: char,
+        #[serde(rename = "Value")]
+        value: Option<f64>,
+        #[serde(rename = "Owners")]
+        owners: Vec<String>,
+        #[serde(rename = "Sold", with = "super::date_cell")]
+        sold: Option<DateTime<Utc>>,
+        #[serde(flatten)]
+        extra: Extra,
+    }
+
+    impl RecordSchema for Property {
+        fn columns() -> &'static [&'static str] {
+            &["APN", "Kind", "Class", "Value", "Owners", "Sold", "Units"]
+        }
+    }
+
+    #[test]
+    fn test_deserialize_records() {
+        let sheet = range(&[
+            &[s("Secured roll 2018")],
+            &[s("apn"), s("Units"), DataType::Empty, s("Kind"), s("Class"), s("Value"), s("Owners"), s("Sold"), s("Notes")],
+            &[s("3731012"), DataType::Float(2.0), s("x"), s("Flat"), s("F"), DataType::Int(5), s("A, B"), DataType::Float(43466.0), s("n")],
+            &[DataType::Empty],
+            &[DataType::Int(3731013), DataType::Int(1), DataType::Empty, s("House"), s("D"), s(" "), DataType::Empty, s("2019-01-02")],
+        ]);
+        let rows = deserialize_records::<Property>(&sheet, "2018", 1)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("rows should parse");
+        assert_eq!(2, rows.len());
+        assert_eq!(Apn("3731012".to_string()), rows[0].apn);
+        assert_eq!(Kind::Flat, rows[0].kind);
+        assert_eq!('F', rows[0].class);
+        assert_eq!(Some(5.0), rows[0].value);
+        assert_eq!(vec!["A".to_string(), "B".to_string()], rows[0].owners);
+        assert_eq!("2019-01-01T00:00:00Z".parse::<DateTime<Utc>>().ok(), rows[0].sold);
+        assert_eq!(Extra { units: 2 }, rows[0].extra);
+        assert_eq!(Apn("3731013".to_string()), rows[1].apn);
+        assert_eq!(None, rows[1].value);
+        assert!(rows[1].owners.is_empty());
+        assert_eq!("2019-01-02T00:00:00Z".parse::<DateTime<Utc>>().ok(), rows[1].sold);
+        assert_eq!(1, rows[1].extra.units);
+    }
+
+    #[test]
+    fn test_errors() {
+        let sheet = range(&[
+            &[s("APN"), s("Units"), s("Kind"), s("Class"), s("Value"), s("Owners"), s("Sold")],
+            &[s("1"), DataType::Float(1.5), s("Flat"), s("F"), DataType::Empty, DataType::Empty, DataType::Empty],
+            &[s("1"), DataType::Int(1), s("Castle"), s("F"), DataType::Empty, DataType::Empty, DataType::Empty],
+            &[s("1"), DataType::Int(1), s("Flat"), s("FF"), DataType::Empty, DataType::Empty, DataType::Empty],
+        ]);
+        let errors: Vec<String> = RowsDeserializeIter::<Property>::from_range(&sheet, 0)
+            .sheet_name("roll")
+            .map(|result| result.expect_err("row should not parse").to_string())
+            .collect();
+        // flattened fields are read after the whole row, so their errors have no column
+        assert!(errors[0].starts_with("sheet roll row 2: "), "{}", errors[0]);
+        assert!(errors[1].starts_with("sheet roll row 3 column Kind: unknown variant `Castle`"), "{}", errors[1]);
+        assert!(errors[2].starts_with("sheet roll row 4 column Class: "), "{}", errors[2]);
+
+        let sheet = range(&[
+            &[s("APN"), s("Class"), s("Value"), s("Owners"), s("Sold"), s("Units")],
+            &[s("1"), s("F"), DataType::Empty, DataType::Empty, DataType::Empty, DataType::Int(1)],
+        ]);
+        let error = RowsDeserializeIter::<Property>::from_range(&sheet, 0)
+            .sheet_name("roll")
+            .next()
+            .expect("one row")
+            .expect_err("Kind is missing")
+            .to_string();
+        assert_eq!("sheet roll row 2: missing field `Kind`", error);
+    }
+
+    #[test]
+    fn test_rows_as_maps_and_tuples() {
+        let sheet = range(&[&[s("a"), s("b")], &[DataType::Int(1), s("x")]]);
+        let maps = deserialize::<BTreeMap<String, String>>(sheet.rows())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("maps");
+        assert_eq!(Some(&"1".to_string()), maps[0].get("a"));
+        let tuples = deserialize::<(u8, String)>(sheet.rows())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("tuples");
+        assert_eq!(vec![(1, "x".to_string())], tuples);
+    }
+
+    #[test]
+    fn test_sheet_records() {
+        let header = || vec![s("APN"), s("Kind"), s("Class"), s("Value"), s("Owners"), s("Sold"), s("Units")];
+        let row = |apn: &str| vec![s(apn), s("House"), s("D"), DataType::Empty, DataType::Empty, DataType::Empty, DataType::Int(1)];
+        let sheets = || {
+            vec![
+                ("2017".to_string(), range(&[&header(), &row("1")])),
+                ("Notes".to_string(), range(&[&[s("See the data dictionary")]])),
+                ("2018".to_string(), range(&[&header(), &row("2"), &row("3")])),
+            ]
         };
-        seed.deserialize(BorrowedStrDeserializer::new(key))
-            .map(Some)
+        let records = SheetRecords::<Property>::from_ranges(sheets())
+            .sheets_matching("20??")
+            .collect::<Result<Vec<_>, _>>()
+            .expect("year sheets should parse");
+        let sheet_apns: Vec<(&str, &str)> = records
+            .iter()
+            .map(|sheet_record| (sheet_record.sheet.as_str(), (sheet_record.record.apn.0.as_str())))
+            .collect();
+        assert_eq!(vec![("2017", "1"), ("2018", "2"), ("2018", "3")], sheet_apns);
+
+        let records = SheetRecords::<Property>::from_ranges(sheets()).skip_sheets_without(&["APN"]).collect::<Result<Vec<_>, _>>();
+        assert_eq!(3, records.expect("notes should be skipped").len());
+
+        // a sheet with the key column but without another column is not skipped
+        let drifted = vec![("2019".to_string(), range(&[&[s("APN"), s("Kind")], &[s("4"), s("House")]]))];
+        let mut records = SheetRecords::<Property>::from_ranges(drifted).skip_sheets_without(&["APN"]);
+        match records.next() {
+            Some(Err(WorkbookError::IncompatibleHeader { sheet, .. })) => assert_eq!("2019", sheet),
+            other => panic!("expected an incompatible header, got {:?}", other.map(|result| result.map(|_| ()))),
+        }
+
+        let mut records = SheetRecords::<Property>::from_ranges(sheets());
+        assert_eq!(vec!["2017", "Notes", "2018"], records.sheet_names());
+        assert!(records.next().expect("first record").is_ok());
+        match records.next() {
+            Some(Err(WorkbookError::IncompatibleHeader { sheet, diff })) => {
+                assert_eq!("Notes", sheet);
+                assert_eq!(7, diff.missing.len());
+            }
+            other => panic!("expected an incompatible header, got {:?}", other.map(|result| result.map(|_| ()))),
+        }
     }
 
-    fn next_value_seed<V: DeserializeSeed<'de>>(
-        &mut self,
-        seed: V,
-    ) -> Result<V::Value, Self::Error> {
-        seed.deserialize(self)
+    #[test]
+    fn test_matches_pattern() {
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("20*", "2018"));
+        assert!(matches_pattern("Roll ????", "Roll 2018"));
+        assert!(matches_pattern("*class*", "By class code"));
+        assert!(!matches_pattern("20*", "Notes"));
+        assert!(!matches_pattern("20?", "2018"));
     }
 }
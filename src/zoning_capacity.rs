@@ -0,0 +1,116 @@
+//! Theoretical maximum development of a parcel under its base zoning (“housing capacity”).
+//!
+//! Units come from the district's density limit. Floor area is approximated as the buildable
+//! part of the lot (behind the rear yard) times the number of stories that fit under the height
+//! limit. This ignores Conditional Use, density bonuses, ADUs, bulk limits, front setbacks
+//! and open space requirements, so it is an estimate of the zoning envelope, not of what would
+//! be approved.
+
+use crate::sfheightbulk::HeightBulk;
+use crate::sflanduse::LandUseRecord;
+use crate::sfzoningdistricts::{Density, ZoningCode};
+
+/// Assumed height of each story in ft. when converting a height limit into stories
+pub const FLOOR_TO_FLOOR_HEIGHT: f64 = 10.0;
+
+/// One parcel's existing development and what its zoning allows
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ParcelCapacity {
+    pub blklot: String,
+    pub address: String,
+    /// sq. ft.
+    pub lot_area: f64,
+    pub zoning: Option<String>,
+    /// height and bulk district label, e.g. 40-X
+    pub height_district: Option<String>,
+    pub height_limit: Option<f64>,
+    /// true if the district has no density limit, so max_units is empty
+    pub no_density_limit: bool,
+    /// units allowed by density; empty if there is no limit or the zoning is unknown
+    pub max_units: Option<u32>,
+    pub max_stories: Option<u32>,
+    /// approximate gross floor area in sq. ft. that fits in the height limit and rear yard
+    pub max_floor_area: Option<f64>,
+    pub existing_units: usize,
+    pub existing_floor_area: usize,
+    /// max_units minus existing_units (negative if the parcel has more units than allowed)
+    pub additional_units: Option<i64>,
+    /// max_floor_area minus existing_floor_area
+    pub additional_floor_area: Option<f64>,
+}
+
+/// Compute the zoning capacity of `parcel`, given the zoning and the height limit at its location
+pub fn parcel_capacity(
+    parcel: &LandUseRecord,
+    zoning: Option<&ZoningCode>,
+    height_bulk: Option<&HeightBulk>,
+) -> ParcelCapacity {
+    let lot_area = parcel.shape_area;
+    let rules = zoning.and_then(|zoning| zoning.rules());
+    let no_density_limit = rules
+        .map(|rules| rules.density == Density::NoLimit)
+        .unwrap_or(false);
+    let max_units = rules.and_then(|rules| rules.density.max_units(lot_area));
+    let height_limit = height_bulk.and_then(|height_bulk| height_bulk.height_limit);
+    let max_stories = height_limit.map(|height_limit| (height_limit / FLOOR_TO_FLOOR_HEIGHT).floor() as u32);
+    let max_floor_area = match (rules, max_stories) {
+        (Some(rules), Some(max_stories)) if rules.density != Density::NotPermitted =>
+            Some(lot_area * rules.max_lot_coverage() * f64::from(max_stories)),
+        (Some(_), Some(_)) => Some(0.0),
+        _ => None,
+    };
+    ParcelCapacity {
+        blklot: parcel.blklot.clone(),
+        address: parcel.address(),
+        lot_area,
+        zoning: zoning.map(ZoningCode::to_string),
+        height_district: height_bulk.map(|height_bulk| height_bulk.label.clone()),
+        height_limit,
+        no_density_limit,
+        max_units,
+        max_stories,
+        max_floor_area,
+        existing_units: parcel.resunits,
+        existing_floor_area: parcel.bldgsqft,
+        additional_units: max_units.map(|max_units| i64::from(max_units) - parcel.resunits as i64),
+        additional_floor_area: max_floor_area.map(|max_floor_area| max_floor_area - parcel.bldgsqft as f64),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parcel_capacity;
+    use crate::sfheightbulk::HeightBulk;
+    use crate::sflanduse::LandUseRecord;
+    use crate::sfzoningdistricts::ZoningCode;
+
+    const TEST_LINES: &str = "OBJECTID,BLKLOT,the_geom,MAPBLKLOT,BLOCK_NUM,LOT_NUM,FROM_ST,TO_ST,STREET,ST_TYPE,RESUNITS,BLDGSQFT,YRBUILT,TOTAL_USES,LANDUSE,CIE,MED,MIPS,RETAIL,PDR,VISITOR,SHAPE_Leng,SHAPE_Area
+1,1234001,\"POLYGON ((0 0, 1 0, 1 1, 0 1, 0 0))\",1234001,1234,001,100,100,MAIN,ST,1,1500,1925,1500,RESIDENT,0,0,0,0,0,0,250,2500
+";
+
+    fn parcel() -> LandUseRecord {
+        csv::Reader::from_reader(TEST_LINES.as_bytes())
+            .deserialize()
+            .next()
+            .expect("test line")
+            .expect("test line should parse")
+    }
+
+    #[test]
+    fn test_parcel_capacity() {
+        let parcel = parcel();
+        let capacity = parcel_capacity(&parcel, Some(&ZoningCode::Rm2), Some(&HeightBulk::from("40-X")));
+        assert_eq!(Some(4), capacity.max_units);
+        assert_eq!(Some(3), capacity.additional_units);
+        assert_eq!(Some(4), capacity.max_stories);
+        // 70% of the lot behind a 30% rear yard, 4 stories
+        assert_eq!(Some(7000.0), capacity.max_floor_area.map(f64::round));
+        assert_eq!(Some(5500.0), capacity.additional_floor_area.map(f64::round));
+        assert_eq!("100-100 MAIN ST", capacity.address);
+
+        let capacity = parcel_capacity(&parcel, Some(&ZoningCode::Nct3), None);
+        assert!(capacity.no_density_limit);
+        assert_eq!(None, capacity.max_units);
+        assert_eq!(None, capacity.max_floor_area);
+    }
+}